@@ -1,6 +1,7 @@
 use std::fmt::{Display, Formatter};
 
 use chrono::{DateTime, Utc};
+use serde::Deserializer;
 use serde_derive::Deserialize;
 use serde_derive::Serialize;
 
@@ -17,9 +18,14 @@ pub struct Task {
     pub id: i32,
     pub title: String,
     pub description: Option<String>,
-    pub user_id: i32,
+    pub reporter_id: i32,
     pub project_id: i32,
     pub created_at: DateTime<Utc>,
+    pub status: Option<String>,
+    pub start_at: Option<DateTime<Utc>>,
+    pub due_at: Option<DateTime<Utc>>,
+    pub assignees: Vec<i32>,
+    pub parent_id: Option<i32>,
 }
 
 #[derive(Deserialize, Serialize, Clone)]
@@ -27,6 +33,98 @@ pub struct TaskRequest {
     pub title: String,
     pub description: Option<String>,
     pub project_id: i32,
+    pub start_at: Option<DateTime<Utc>>,
+    pub due_at: Option<DateTime<Utc>>,
+    /// Creates a subtask of a task of the same project.
+    pub parent_id: Option<i32>,
+}
+
+/// Moves the task with its subtasks below another task, or to the top level without a parent.
+#[derive(Deserialize, Serialize)]
+pub struct TaskParentRequest {
+    pub parent_id: Option<i32>,
+}
+
+/// A task with its subtasks, the counts roll up the whole subtree below the task.
+#[derive(Deserialize, Serialize)]
+pub struct TaskTree {
+    #[serde(flatten)]
+    pub task: Task,
+    pub done: bool,
+    pub subtask_count: i64,
+    pub done_subtask_count: i64,
+    pub subtasks: Vec<TaskTree>,
+}
+
+impl TaskTree {
+    fn fmt_indented(&self, f: &mut Formatter<'_>, depth: usize) -> std::fmt::Result {
+        writeln!(f, "{}[task: id = {},title = {},status = {},done = {},subtasks done = {}/{}];",
+                 "  ".repeat(depth),
+                 self.task.id,
+                 self.task.title,
+                 self.task.status.as_deref().unwrap_or_default(),
+                 self.done,
+                 self.done_subtask_count,
+                 self.subtask_count)?;
+        for subtask in self.subtasks.iter() {
+            subtask.fmt_indented(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for TaskTree {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+/// Changes of a task, fields that are left out stay as they are and `null` clears the optional
+/// ones. A new `project_id` moves the task to another project of the same organization.
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct TaskUpdateRequest {
+    pub title: Option<String>,
+    #[serde(default, deserialize_with = "nullable", skip_serializing_if = "Option::is_none")]
+    pub description: Option<Option<String>>,
+    pub project_id: Option<i32>,
+    #[serde(default, deserialize_with = "nullable", skip_serializing_if = "Option::is_none")]
+    pub start_at: Option<Option<DateTime<Utc>>>,
+    #[serde(default, deserialize_with = "nullable", skip_serializing_if = "Option::is_none")]
+    pub due_at: Option<Option<DateTime<Utc>>>,
+}
+
+/// Reads a present field as `Some`, even when it is `null`, so that it can be told apart from
+/// a missing one.
+fn nullable<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+    where T: serde::Deserialize<'de>,
+          D: Deserializer<'de> {
+    <Option<T> as serde::Deserialize>::deserialize(deserializer).map(Some)
+}
+
+/// Due date buckets, "today" and "this week" are computed in the time zone of the user.
+#[derive(Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum DueFilter {
+    Overdue,
+    Today,
+    Week,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct TaskFilter {
+    pub due: Option<DueFilter>,
+    pub assigned_to_me: Option<bool>,
+}
+
+/// Archived projects are only listed when asked for, and then only they are.
+#[derive(Deserialize, Serialize, Default)]
+pub struct ProjectFilter {
+    pub archived: Option<bool>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct AssigneeRequest {
+    pub user_id: i32,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -37,7 +135,11 @@ pub struct User {
     pub email: String,
     pub pwd: String,
     pub enabled: bool,
+    pub time_zone: String,
+    pub role: String,
     pub created_at: DateTime<Utc>,
+    /// Raised to end all sessions of the user, JWTs issued for an older version are refused.
+    pub token_version: i32,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -46,6 +148,9 @@ pub struct UserDto {
     pub first_name: Option<String>,
     pub last_name: Option<String>,
     pub email: String,
+    pub enabled: bool,
+    pub time_zone: String,
+    pub role: String,
     pub created_at: DateTime<Utc>,
 }
 
@@ -57,17 +162,83 @@ impl Into<UserDto> for User {
             first_name: self.first_name,
             last_name: self.last_name,
             email: self.email,
+            enabled: self.enabled,
+            time_zone: self.time_zone,
+            role: self.role,
             created_at: self.created_at,
         }
     }
 }
 
+#[derive(Deserialize, Serialize)]
+pub struct RoleRequest {
+    pub role: String,
+}
+
+/// Changes an administrator makes to another account, fields that are left out stay as they are.
+#[derive(Deserialize, Serialize, Default)]
+pub struct UserUpdateRequest {
+    pub enabled: Option<bool>,
+    pub role: Option<String>,
+}
+
+/// What happens to the tasks and projects of a deleted user.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum UserDeleteStrategy {
+    /// Tasks and projects nobody else is a member of move to `reassign_to`.
+    Reassign,
+    /// Tasks reported by the user and projects nobody else is a member of are deleted.
+    Cascade,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct UserDeleteQuery {
+    pub strategy: UserDeleteStrategy,
+    pub reassign_to: Option<i32>,
+}
+
 #[derive(Deserialize)]
 pub struct UserRequest {
     pub first_name: Option<String>,
     pub last_name: Option<String>,
     pub email: String,
     pub pwd: String,
+    pub time_zone: Option<String>,
+}
+
+impl Display for UserDto {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[user: id = {},first_name = {},last_name = {},email = {},enabled = {},time_zone = {},role = {},created_at = {}];",
+               self.id,
+               self.first_name.as_deref().unwrap_or(""),
+               self.last_name.as_deref().unwrap_or(""),
+               self.email,
+               self.enabled,
+               self.time_zone,
+               self.role,
+               self.created_at)
+    }
+}
+
+/// Changes of the own profile, fields that are left out stay as they are.
+#[derive(Deserialize, Serialize)]
+pub struct ProfileUpdateRequest {
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub time_zone: Option<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct PasswordChangeRequest {
+    pub current_pwd: String,
+    pub new_pwd: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct EmailChangeRequest {
+    pub email: String,
+    pub pwd: String,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -76,24 +247,56 @@ pub struct Project {
     pub title: String,
     pub description: Option<String>,
     pub created_at: DateTime<Utc>,
+    pub archived_at: Option<DateTime<Utc>>,
 }
 
 impl Display for Project {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "[project: id = {},title = {}, description = {} ,created_at = {}];",
+        write!(f, "[project: id = {},title = {}, description = {} ,created_at = {},archived_at = {}];",
                self.id,
                self.title,
                match self.description {
                    Some(_) => self.description.as_ref().unwrap(),
                    None => ""
                },
-               self.created_at)
+               self.created_at,
+               self.archived_at.map(|archived_at| archived_at.to_string()).unwrap_or_default())
     }
 }
 
+/// A project together with how many of its tasks are in each status of its workflow.
+#[derive(Serialize, Deserialize)]
+pub struct ProjectDetails {
+    #[serde(flatten)]
+    pub project: Project,
+    pub task_count: i64,
+    pub status_counts: Vec<StatusCount>,
+}
+
+impl Display for ProjectDetails {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.project)?;
+        write!(f, "\n[tasks: count = {}];", self.task_count)?;
+        for status_count in self.status_counts.iter() {
+            write!(f, "\n[status: name = {},done = {},count = {}];",
+                   status_count.status,
+                   status_count.done,
+                   status_count.count)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StatusCount {
+    pub status: String,
+    pub done: bool,
+    pub count: i64,
+}
+
 impl Display for Task {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "[task: id = {},title = {}, description = {} ,project_id = {},created_at = {}];",
+        write!(f, "[task: id = {},title = {}, description = {} ,project_id = {},parent_id = {},status = {},reporter_id = {},assignees = {},start_at = {},due_at = {},created_at = {}];",
                self.id,
                self.title,
                match self.description {
@@ -101,6 +304,24 @@ impl Display for Task {
                    None => ""
                },
                self.project_id,
+               self.parent_id.map(|parent_id| parent_id.to_string()).unwrap_or_default(),
+               match self.status {
+                   Some(_) => self.status.as_ref().unwrap(),
+                   None => ""
+               },
+               self.reporter_id,
+               self.assignees.iter()
+                   .map(|assignee| assignee.to_string())
+                   .collect::<Vec<String>>()
+                   .join(","),
+               match self.start_at {
+                   Some(start_at) => start_at.to_string(),
+                   None => String::new()
+               },
+               match self.due_at {
+                   Some(due_at) => due_at.to_string(),
+                   None => String::new()
+               },
                self.created_at)
     }
 }
@@ -111,6 +332,238 @@ pub struct ProjectRequest {
     pub description: Option<String>,
 }
 
+/// What happens to the tasks of a deleted project.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ProjectDeleteStrategy {
+    /// A project that still has tasks is not deleted.
+    Refuse,
+    /// The tasks are deleted with the project.
+    Cascade,
+    /// The tasks move to the project `move_to`.
+    Move,
+}
+
+#[derive(Deserialize, Serialize, Default)]
+pub struct ProjectDeleteQuery {
+    pub strategy: Option<ProjectDeleteStrategy>,
+    pub move_to: Option<i32>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum TrashKind {
+    Task,
+    Project,
+}
+
+impl Display for TrashKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrashKind::Task => write!(f, "task"),
+            TrashKind::Project => write!(f, "project"),
+        }
+    }
+}
+
+/// A deleted task or project that can still be restored.
+#[derive(Deserialize, Serialize)]
+pub struct TrashItem {
+    pub kind: TrashKind,
+    pub id: i32,
+    pub title: String,
+    pub deleted_at: DateTime<Utc>,
+}
+
+impl Display for TrashItem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}: id = {},title = {},deleted_at = {}];",
+               self.kind,
+               self.id,
+               self.title,
+               self.deleted_at)
+    }
+}
+
+/// Changes of a project, fields that are left out stay as they are.
+#[derive(Deserialize, Serialize, Default)]
+pub struct ProjectUpdateRequest {
+    pub title: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ProjectMember {
+    pub user_id: i32,
+    pub email: String,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    /// `owner`, `maintainer`, `member` or `viewer`.
+    pub role: String,
+}
+
+impl Display for ProjectMember {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[member: user_id = {},email = {},first_name = {},last_name = {},role = {}];",
+               self.user_id,
+               self.email,
+               self.first_name.as_deref().unwrap_or(""),
+               self.last_name.as_deref().unwrap_or(""),
+               self.role)
+    }
+}
+
+/// An organization the user belongs to, with the role they have in it.
+#[derive(Serialize, Deserialize)]
+pub struct Organization {
+    pub id: i32,
+    pub name: String,
+    /// `admin` or `member`.
+    pub role: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Display for Organization {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[organization: id = {},name = {},role = {},created_at = {}];",
+               self.id,
+               self.name,
+               self.role,
+               self.created_at)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct OrganizationRequest {
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct OrganizationMember {
+    pub user_id: i32,
+    pub email: String,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    /// `admin` or `member`.
+    pub role: String,
+}
+
+impl Display for OrganizationMember {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[member: user_id = {},email = {},first_name = {},last_name = {},role = {}];",
+               self.user_id,
+               self.email,
+               self.first_name.as_deref().unwrap_or(""),
+               self.last_name.as_deref().unwrap_or(""),
+               self.role)
+    }
+}
+
+/// Adds a registered user to an organization, as a `member` unless another role is given.
+#[derive(Serialize, Deserialize)]
+pub struct OrganizationMemberRequest {
+    pub email: String,
+    pub role: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct InvitationRequest {
+    pub email: String,
+    /// Role the invited user joins with, `member` when left out.
+    pub role: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct MemberRoleRequest {
+    pub role: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ProjectTransferRequest {
+    pub user_id: i32,
+}
+
+/// A pending invitation, the token only goes out by email.
+#[derive(Serialize, Deserialize)]
+pub struct ProjectInvitation {
+    pub id: i32,
+    pub project_id: i32,
+    pub project_title: String,
+    pub email: String,
+    pub role: String,
+    pub expiry_date: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Display for ProjectInvitation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[invitation: id = {},project_id = {},project_title = {},email = {},role = {},expiry_date = {},created_at = {}];",
+               self.id,
+               self.project_id,
+               self.project_title,
+               self.email,
+               self.role,
+               self.expiry_date,
+               self.created_at)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct InvitationTokenRequest {
+    pub token: String,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct TaskStatus {
+    pub id: i32,
+    pub project_id: i32,
+    pub name: String,
+    pub position: i32,
+    pub done: bool,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct StatusTransition {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct Workflow {
+    pub statuses: Vec<TaskStatus>,
+    pub transitions: Vec<StatusTransition>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct TaskStatusRequest {
+    pub name: String,
+    pub done: bool,
+}
+
+/// Replaces the workflow of a project. The first status is the one new tasks start in.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct WorkflowRequest {
+    pub statuses: Vec<TaskStatusRequest>,
+    pub transitions: Vec<StatusTransition>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct TaskStatusChangeRequest {
+    pub status: String,
+}
+
+impl Display for Workflow {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for status in &self.statuses {
+            writeln!(f, "[status: name = {}, done = {}];", status.name, status.done)?;
+        }
+        for transition in &self.transitions {
+            writeln!(f, "[transition: {} -> {}];", transition.from, transition.to)?;
+        }
+        Ok(())
+    }
+}
+
 
 #[derive(Deserialize, Serialize)]
 pub struct LoginRequest {
@@ -121,5 +574,147 @@ pub struct LoginRequest {
 #[derive(Serialize, Deserialize)]
 pub struct LoginResponse {
     pub token: String,
+    pub refresh_token: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+/// Handed out instead of tokens when the password was right but a second factor is required.
+#[derive(Serialize, Deserialize)]
+pub struct TwoFactorChallenge {
+    pub challenge: String,
+    pub expiry_date: DateTime<Utc>,
+}
+
+/// What a login answers with, depending on whether the user enabled two-factor authentication.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum LoginResult {
+    Tokens(LoginResponse),
+    Challenge(TwoFactorChallenge),
+}
+
+/// Completes a login with either a code from the authenticator app or an unused recovery code.
+#[derive(Serialize, Deserialize)]
+pub struct TwoFactorLoginRequest {
+    pub challenge: String,
+    pub code: Option<String>,
+    pub recovery_code: Option<String>,
+}
+
+/// Sent back by the OpenID Connect provider after the user logged in there.
+#[derive(Serialize, Deserialize)]
+pub struct OidcCallbackQuery {
+    pub state: String,
+    pub code: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Lets a client without a browser log in through the provider: the user opens
+/// `verification_uri_complete` anywhere while the client polls with `device_code`.
+#[derive(Serialize, Deserialize)]
+pub struct DeviceAuthorization {
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    pub verification_uri_complete: String,
+    pub expires_in: i64,
+    /// Seconds to wait between polls.
+    pub interval: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DeviceVerificationQuery {
+    pub user_code: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DeviceTokenRequest {
+    pub device_code: String,
+}
+
+/// The secret and recovery codes are only shown once, when two-factor authentication is set up.
+#[derive(Serialize, Deserialize)]
+pub struct TwoFactorEnrollment {
+    pub secret: String,
+    pub otpauth_uri: String,
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TwoFactorCodeRequest {
+    pub code: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TwoFactorDisableRequest {
+    pub pwd: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AccessTokenRequest {
+    pub name: String,
+    pub scopes: Vec<String>,
+    /// Days until the token stops working, it never expires when missing.
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AccessToken {
+    pub id: i32,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub expiry_date: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Display for AccessToken {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[token: id = {},name = {},scopes = {},expiry_date = {},last_used_at = {},created_at = {}];",
+               self.id,
+               self.name,
+               self.scopes.join(","),
+               match self.expiry_date {
+                   Some(expiry_date) => expiry_date.to_string(),
+                   None => String::new()
+               },
+               match self.last_used_at {
+                   Some(last_used_at) => last_used_at.to_string(),
+                   None => String::new()
+               },
+               self.created_at)
+    }
+}
+
+/// The secret is only part of this response, the server keeps nothing but its hash.
+#[derive(Serialize, Deserialize)]
+pub struct AccessTokenResponse {
+    pub token: String,
+    pub access_token: AccessToken,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ConfirmationQuery {
+    pub token: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ResendVerificationRequest {
+    pub email: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ForgotPasswordRequest {
+    pub email: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ResetPasswordRequest {
+    pub token: String,
+    pub pwd: String,
 }
 
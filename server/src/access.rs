@@ -0,0 +1,276 @@
+use std::fmt;
+use std::fmt::Formatter;
+
+use crate::{db, DBPool, error};
+use crate::data::{ProjectAccess, TaskAccess};
+use crate::error::Error::{NoPermissionError, NotFoundError};
+
+type Result<T> = std::result::Result<T, error::Error>;
+
+/// Role of a member within a project, every role may do what the ones before it may.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum ProjectRole {
+    Viewer,
+    Member,
+    Maintainer,
+    Owner,
+}
+
+impl ProjectRole {
+    pub fn parse(str: &str) -> Option<ProjectRole> {
+        match str {
+            "viewer" => Some(ProjectRole::Viewer),
+            "member" => Some(ProjectRole::Member),
+            "maintainer" => Some(ProjectRole::Maintainer),
+            "owner" => Some(ProjectRole::Owner),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for ProjectRole {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ProjectRole::Viewer => write!(f, "viewer"),
+            ProjectRole::Member => write!(f, "member"),
+            ProjectRole::Maintainer => write!(f, "maintainer"),
+            ProjectRole::Owner => write!(f, "owner")
+        }
+    }
+}
+
+/// Role of a member within an organization, independent of the global `Role::Admin`.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum OrganizationRole {
+    Member,
+    Admin,
+}
+
+impl OrganizationRole {
+    pub fn parse(str: &str) -> Option<OrganizationRole> {
+        match str {
+            "member" => Some(OrganizationRole::Member),
+            "admin" => Some(OrganizationRole::Admin),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for OrganizationRole {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            OrganizationRole::Member => write!(f, "member"),
+            OrganizationRole::Admin => write!(f, "admin")
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OrganizationAction {
+    Read,
+    ManageMembers,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ProjectAction {
+    Read,
+    CreateTask,
+    ManageWorkflow,
+    ManageMembers,
+    Update,
+    Delete,
+    Transfer,
+}
+
+impl ProjectAction {
+    /// The permission matrix: viewers read, members work on tasks, maintainers run the
+    /// project and owners decide about its existence.
+    fn required_role(self) -> ProjectRole {
+        match self {
+            ProjectAction::Read => ProjectRole::Viewer,
+            ProjectAction::CreateTask => ProjectRole::Member,
+            ProjectAction::ManageWorkflow | ProjectAction::ManageMembers | ProjectAction::Update => ProjectRole::Maintainer,
+            ProjectAction::Delete | ProjectAction::Transfer => ProjectRole::Owner,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TaskAction {
+    Read,
+    Update,
+    Delete,
+}
+
+/// Checks that the user may perform the action on the organization.
+///
+/// Organizations the user does not belong to are reported as `NotFoundError`, like projects.
+pub async fn authorize_organization(db_pool: &DBPool,
+                                    user_id: i32,
+                                    organization_id: i32,
+                                    action: OrganizationAction) -> Result<OrganizationRole> {
+    let role = db::find_organization_role(db_pool, organization_id, user_id).await?;
+    check_organization(role, action)
+}
+
+/// Checks that the user may perform the action on the project.
+///
+/// Only projects of the organization the request works in are found, see
+/// [`db::find_project_access`]. Resources the user cannot see are reported as `NotFoundError`,
+/// so ids belonging to other users cannot be probed; `NoPermissionError` is only returned for
+/// resources the user can see.
+pub async fn authorize_project(db_pool: &DBPool,
+                               user_id: i32,
+                               organization: Option<i32>,
+                               project_id: i32,
+                               action: ProjectAction) -> Result<ProjectAccess> {
+    let access = db::find_project_access(db_pool, project_id, user_id, organization, false).await?;
+    check_project(access, action)
+}
+
+/// Checks that the user may perform the action on the task, see [`authorize_project`].
+pub async fn authorize_task(db_pool: &DBPool,
+                            user_id: i32,
+                            organization: Option<i32>,
+                            task_id: i32,
+                            action: TaskAction) -> Result<TaskAccess> {
+    let access = db::find_task_access(db_pool, task_id, user_id, organization, false).await?;
+    check_task(access, user_id, action)
+}
+
+/// Like [`authorize_project`], but only finds the project while it is in the trash.
+pub async fn authorize_trashed_project(db_pool: &DBPool,
+                                       user_id: i32,
+                                       organization: Option<i32>,
+                                       project_id: i32,
+                                       action: ProjectAction) -> Result<ProjectAccess> {
+    let access = db::find_project_access(db_pool, project_id, user_id, organization, true).await?;
+    check_project(access, action)
+}
+
+/// Like [`authorize_task`], but only finds the task while it is in the trash on its own.
+pub async fn authorize_trashed_task(db_pool: &DBPool,
+                                    user_id: i32,
+                                    organization: Option<i32>,
+                                    task_id: i32,
+                                    action: TaskAction) -> Result<TaskAccess> {
+    let access = db::find_task_access(db_pool, task_id, user_id, organization, true).await?;
+    check_task(access, user_id, action)
+}
+
+/// Members see the organization and its members, admins manage the members.
+fn check_organization(role: Option<OrganizationRole>, action: OrganizationAction) -> Result<OrganizationRole> {
+    match (role, action) {
+        (None, _) => Err(NotFoundError),
+        (Some(role), OrganizationAction::Read) => Ok(role),
+        (Some(OrganizationRole::Admin), OrganizationAction::ManageMembers) => Ok(OrganizationRole::Admin),
+        (Some(_), _) => Err(NoPermissionError),
+    }
+}
+
+fn check_project(access: Option<ProjectAccess>, action: ProjectAction) -> Result<ProjectAccess> {
+    match access {
+        Some(access) => match access.role {
+            Some(role) if role >= action.required_role() => Ok(access),
+            Some(_) => Err(NoPermissionError),
+            None => Err(NotFoundError),
+        },
+        None => Err(NotFoundError),
+    }
+}
+
+/// Members update any task of the project, reporters delete their own and maintainers any.
+fn check_task(access: Option<TaskAccess>, user_id: i32, action: TaskAction) -> Result<TaskAccess> {
+    match access {
+        Some(access) => match (access.role, action) {
+            (None, _) => Err(NotFoundError),
+            (Some(_), TaskAction::Read) => Ok(access),
+            (Some(role), TaskAction::Update) if role >= ProjectRole::Member => Ok(access),
+            (Some(role), TaskAction::Delete) if role >= ProjectRole::Maintainer => Ok(access),
+            (Some(role), TaskAction::Delete)
+            if role >= ProjectRole::Member && access.reporter_id == user_id => Ok(access),
+            (Some(_), _) => Err(NoPermissionError),
+        },
+        None => Err(NotFoundError),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OWNER: i32 = 1;
+    const TEAMMATE: i32 = 2;
+
+    fn project(role: Option<ProjectRole>) -> Option<ProjectAccess> {
+        Some(ProjectAccess { organization_id: 1, role })
+    }
+
+    fn task(role: Option<ProjectRole>) -> Option<TaskAccess> {
+        Some(TaskAccess { project_id: 1, reporter_id: OWNER, role })
+    }
+
+    #[test]
+    fn missing_and_foreign_projects_look_the_same() {
+        let missing = check_project(None, ProjectAction::Read);
+        let foreign = check_project(project(None), ProjectAction::Read);
+        assert!(matches!(missing, Err(NotFoundError)));
+        assert!(matches!(foreign, Err(NotFoundError)));
+    }
+
+    #[test]
+    fn roles_follow_the_permission_matrix() {
+        let actions = [ProjectAction::Read, ProjectAction::CreateTask, ProjectAction::ManageWorkflow,
+            ProjectAction::ManageMembers, ProjectAction::Update, ProjectAction::Delete, ProjectAction::Transfer];
+        let allowed = [
+            (ProjectRole::Viewer, 1),
+            (ProjectRole::Member, 2),
+            (ProjectRole::Maintainer, 5),
+            (ProjectRole::Owner, 7),
+        ];
+        for (role, count) in allowed {
+            for (index, action) in actions.iter().enumerate() {
+                let result = check_project(project(Some(role)), *action);
+                if index < count {
+                    assert!(result.is_ok(), "{:?} {:?}", role, action);
+                } else {
+                    assert!(matches!(result, Err(NoPermissionError)), "{:?} {:?}", role, action);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn only_organization_admins_manage_members() {
+        assert!(matches!(check_organization(None, OrganizationAction::Read), Err(NotFoundError)));
+        assert!(check_organization(Some(OrganizationRole::Member), OrganizationAction::Read).is_ok());
+        assert!(matches!(check_organization(Some(OrganizationRole::Member), OrganizationAction::ManageMembers),
+                         Err(NoPermissionError)));
+        assert!(check_organization(Some(OrganizationRole::Admin), OrganizationAction::ManageMembers).is_ok());
+    }
+
+    #[test]
+    fn outsiders_cannot_see_tasks() {
+        for action in [TaskAction::Read, TaskAction::Update, TaskAction::Delete] {
+            assert!(matches!(check_task(task(None), TEAMMATE, action), Err(NotFoundError)));
+            assert!(matches!(check_task(None, TEAMMATE, action), Err(NotFoundError)));
+        }
+    }
+
+    #[test]
+    fn only_the_reporter_deletes_a_task() {
+        let member = task(Some(ProjectRole::Member));
+        assert!(check_task(task(Some(ProjectRole::Member)), OWNER, TaskAction::Delete).is_ok());
+        assert!(matches!(check_task(task(Some(ProjectRole::Member)), TEAMMATE, TaskAction::Delete), Err(NoPermissionError)));
+        assert!(check_task(member, TEAMMATE, TaskAction::Update).is_ok());
+        assert!(check_task(task(Some(ProjectRole::Maintainer)), TEAMMATE, TaskAction::Delete).is_ok());
+    }
+
+    #[test]
+    fn viewers_cannot_change_tasks() {
+        for action in [TaskAction::Update, TaskAction::Delete] {
+            assert!(matches!(check_task(task(Some(ProjectRole::Viewer)), OWNER, action), Err(NoPermissionError)));
+        }
+        assert!(check_task(task(Some(ProjectRole::Viewer)), TEAMMATE, TaskAction::Read).is_ok());
+    }
+}
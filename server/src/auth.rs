@@ -1,21 +1,29 @@
 use std::fmt;
 use std::fmt::Formatter;
+use std::fs;
+use std::str::FromStr;
+use std::sync::Arc;
 
 use chrono::Utc;
-use jsonwebtoken::{Algorithm, decode, DecodingKey, EncodingKey, Header, Validation};
+use jsonwebtoken::{Algorithm, decode, decode_header, DecodingKey, EncodingKey, Header, Validation};
 use serde_derive::Deserialize;
 use serde_derive::Serialize;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
 use warp::{Filter, header, reject, Rejection};
 use warp::http::{HeaderMap, HeaderValue};
 use warp::http::header::AUTHORIZATION;
 
 use common::data::User;
 
+use crate::{db, DBPool};
+use crate::config::Config;
 use crate::error::Error;
-use crate::error::Error::JWTTokenCreationError;
+use crate::error::Error::{AccessTokenError, JWTKeyError, JWTTokenCreationError, NoPermissionError};
 
 const BEARER: &str = "Bearer ";
-const JWT_SECRET: &[u8] = b"secret";
+/// Marks personal access tokens, anything else in the auth header is taken for a JWT.
+pub const ACCESS_TOKEN_PREFIX: &str = "tm_pat_";
 
 #[derive(Clone, PartialEq)]
 pub enum Role {
@@ -25,9 +33,14 @@ pub enum Role {
 
 impl Role {
     pub fn from_str(str: &str) -> Role {
+        Role::parse(str).unwrap_or(Role::User)
+    }
+
+    pub fn parse(str: &str) -> Option<Role> {
         match str {
-            "Admin" => Role::Admin,
-            _ => Role::User
+            "Admin" => Some(Role::Admin),
+            "User" => Some(Role::User),
+            _ => None
         }
     }
 }
@@ -41,57 +54,236 @@ impl fmt::Display for Role {
     }
 }
 
+/// What a personal access token may be used for. Logged in users are not limited by scopes.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Scope {
+    TasksRead,
+    TasksWrite,
+    ProjectsRead,
+    ProjectsAdmin,
+}
+
+impl Scope {
+    pub fn parse(str: &str) -> Option<Scope> {
+        match str {
+            "tasks:read" => Some(Scope::TasksRead),
+            "tasks:write" => Some(Scope::TasksWrite),
+            "projects:read" => Some(Scope::ProjectsRead),
+            "projects:admin" => Some(Scope::ProjectsAdmin),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Scope::TasksRead => write!(f, "tasks:read"),
+            Scope::TasksWrite => write!(f, "tasks:write"),
+            Scope::ProjectsRead => write!(f, "projects:read"),
+            Scope::ProjectsAdmin => write!(f, "projects:admin")
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 struct Claims {
     sub: i32,
     role: String,
     exp: usize,
+    /// The `token_version` of the user when the token was issued.
+    #[serde(default)]
+    ver: i32,
+}
+
+/// Signing key and every key a token may still be verified with.
+pub struct JwtKeys {
+    algorithm: Algorithm,
+    key_id: Option<String>,
+    encoding_key: EncodingKey,
+    decoding_keys: Vec<(Option<String>, DecodingKey<'static>)>,
+    lifetime: chrono::Duration,
+}
+
+impl JwtKeys {
+    pub fn from_config(config: &Config) -> Result<JwtKeys, Error> {
+        let algorithm = Algorithm::from_str(&config.jwt_algorithm)
+            .map_err(|_| JWTKeyError)?;
+        let (encoding_key, decoding_key) = match algorithm {
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+                let secret = config.jwt_secret.as_ref().ok_or(JWTKeyError)?;
+                (EncodingKey::from_secret(secret.as_bytes()),
+                 DecodingKey::from_secret(secret.as_bytes()).into_static())
+            }
+            Algorithm::RS256 | Algorithm::ES256 => {
+                let private_key = fs::read(config.jwt_private_key.as_ref().ok_or(JWTKeyError)?)?;
+                let encoding_key = if algorithm == Algorithm::RS256 {
+                    EncodingKey::from_rsa_pem(&private_key)
+                } else {
+                    EncodingKey::from_ec_pem(&private_key)
+                }
+                    .map_err(|_| JWTKeyError)?;
+                let public_key = config.jwt_public_key.as_ref().ok_or(JWTKeyError)?;
+                (encoding_key, decoding_key_from_file(algorithm, public_key)?)
+            }
+            _ => return Err(JWTKeyError),
+        };
+        let mut decoding_keys = vec![(config.jwt_key_id.clone(), decoding_key)];
+        for (kid, key) in &config.jwt_previous_keys {
+            let decoding_key = match algorithm {
+                Algorithm::RS256 | Algorithm::ES256 => decoding_key_from_file(algorithm, key)?,
+                _ => DecodingKey::from_secret(key.as_bytes()).into_static(),
+            };
+            decoding_keys.push((Some(kid.clone()), decoding_key));
+        }
+        Ok(JwtKeys {
+            algorithm,
+            key_id: config.jwt_key_id.clone(),
+            encoding_key,
+            decoding_keys,
+            lifetime: chrono::Duration::seconds(config.jwt_lifetime_seconds),
+        })
+    }
+
+    /// Picks the verification key by the `kid` header, tokens without one are checked
+    /// against the keys configured without an id.
+    fn decoding_key(&self, kid: &Option<String>) -> Option<&DecodingKey<'static>> {
+        self.decoding_keys.iter()
+            .find(|(key_id, _)| key_id == kid)
+            .map(|(_, key)| key)
+    }
+}
+
+fn decoding_key_from_file(algorithm: Algorithm, path: &str) -> Result<DecodingKey<'static>, Error> {
+    let pem = fs::read(path)?;
+    let key = if algorithm == Algorithm::RS256 {
+        DecodingKey::from_rsa_pem(&pem)
+    } else {
+        DecodingKey::from_ec_pem(&pem)
+    };
+    key.map(|key| key.into_static())
+        .map_err(|_| JWTKeyError)
 }
 
-pub fn create_token(user: &User) -> Result<String, Error> {
+pub fn create_token(user: &User, keys: &JwtKeys) -> Result<String, Error> {
     let expiration = Utc::now()
-        .checked_add_signed(chrono::Duration::seconds(600))
+        .checked_add_signed(keys.lifetime)
         .expect("valid timestamp")
         .timestamp();
     let claims = Claims {
         sub: user.id,
-        role: Role::Admin.to_string(),
+        role: Role::from_str(&user.role).to_string(),
         exp: expiration as usize,
+        ver: user.token_version,
     };
-    let header = Header::new(Algorithm::HS512);
-    jsonwebtoken::encode(&header, &claims, &EncodingKey::from_secret(JWT_SECRET))
+    let mut header = Header::new(keys.algorithm);
+    header.kid = keys.key_id.clone();
+    jsonwebtoken::encode(&header, &claims, &keys.encoding_key)
         .map_err(|_| JWTTokenCreationError)
 }
 
-pub fn with_auth(roles: Vec<Role>) -> impl Filter<Extract=(i32, ), Error=Rejection> + Clone {
+/// Checks the signature and expiry of a JWT with the key its `kid` names.
+fn decode_token(jwt: &str, keys: &JwtKeys) -> Result<Claims, Error> {
+    let header = decode_header(jwt)
+        .map_err(|_| Error::JWTTokenError)?;
+    let decoding_key = keys.decoding_key(&header.kid)
+        .ok_or(Error::JWTTokenError)?;
+    decode::<Claims>(jwt, decoding_key, &Validation::new(keys.algorithm))
+        .map(|data| data.claims)
+        .map_err(|_| Error::JWTTokenError)
+}
+
+pub fn generate_access_token() -> String {
+    format!("{}{}", ACCESS_TOKEN_PREFIX, generate_token())
+}
+
+/// Random opaque token handed out to clients, only its [`hash_token`] is stored.
+pub fn generate_token() -> String {
+    format!("{}{}", Uuid::new_v4().to_simple(), Uuid::new_v4().to_simple())
+}
+
+/// One-time code to log in without the authenticator app, formatted as `xxxx-xxxx-xxxx`.
+pub fn generate_recovery_code() -> String {
+    let random = Uuid::new_v4().to_simple().to_string();
+    format!("{}-{}-{}", &random[..4], &random[4..8], &random[8..12])
+}
+
+/// Recovery codes are hashed like tokens, but typed in by hand so case and spaces are ignored.
+pub fn hash_recovery_code(code: &str) -> String {
+    hash_token(&code.trim().to_lowercase())
+}
+
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Accepts JWTs of the given roles and personal access tokens of users with those roles.
+///
+/// Access tokens also need the `scope`, routes without one are only open to logged in users.
+pub fn with_auth(keys: Arc<JwtKeys>, db_pool: DBPool, roles: Vec<Role>,
+                 scope: Option<Scope>) -> impl Filter<Extract=(i32, ), Error=Rejection> + Clone {
     header::headers_cloned()
-        .map(move |headers: HeaderMap<HeaderValue>| (roles.clone(), headers))
+        .map(move |headers: HeaderMap<HeaderValue>| (keys.clone(), db_pool.clone(), roles.clone(), scope, headers))
         .and_then(authorize)
 }
 
 
-async fn authorize((roles, headers): (Vec<Role>, HeaderMap<HeaderValue>)) -> crate::Result<i32> {
+async fn authorize((keys, db_pool, roles, scope, headers): (Arc<JwtKeys>, DBPool, Vec<Role>, Option<Scope>, HeaderMap<HeaderValue>)) -> crate::Result<i32> {
     match jwt_from_header(&headers) {
+        Ok(token) if token.starts_with(ACCESS_TOKEN_PREFIX) => {
+            authorize_access_token(&db_pool, &token, &roles, scope)
+                .await
+                .map_err(reject::custom)
+        }
         Ok(jwt) => {
-            let decoded = decode::<Claims>(
-                &jwt,
-                &DecodingKey::from_secret(JWT_SECRET),
-                &Validation::new(Algorithm::HS512),
-            )
-                .map_err(|_| reject::custom(Error::JWTTokenError))?;
-
-            let assigned_role = Role::from_str(&decoded.claims.role);
-
-            if !roles.contains(&assigned_role) {
-                return Err(reject::custom(Error::NoPermissionError));
-            }
-
-            Ok(decoded.claims.sub)
+            let claims = decode_token(&jwt, &keys)
+                .map_err(reject::custom)?;
+            authorize_session(&db_pool, &claims, &roles)
+                .await
+                .map_err(reject::custom)
         }
         Err(e) => return Err(reject::custom(e)),
     }
 }
 
+async fn authorize_access_token(db_pool: &DBPool, token: &str, roles: &[Role],
+                                scope: Option<Scope>) -> Result<i32, Error> {
+    let access_token = db::find_personal_access_token(db_pool, token)
+        .await?
+        .ok_or(AccessTokenError)?;
+    let expired = access_token.expiry_date
+        .map(|expiry_date| expiry_date < Utc::now())
+        .unwrap_or(false);
+    if expired || !access_token.enabled {
+        return Err(AccessTokenError);
+    }
+    let scope = scope.ok_or(NoPermissionError)?;
+    if !roles.contains(&Role::from_str(&access_token.role))
+        || !access_token.scopes.contains(&scope.to_string()) {
+        return Err(NoPermissionError);
+    }
+    db::touch_personal_access_token(db_pool, access_token.id).await?;
+    Ok(access_token.user_id)
+}
+
+/// Checks the user behind a JWT as it is now, so disabling or demoting someone, or ending their
+/// sessions, takes effect before their token expires.
+async fn authorize_session(db_pool: &DBPool, claims: &Claims, roles: &[Role]) -> Result<i32, Error> {
+    let user = db::find_user_by_id(db_pool, claims.sub)
+        .await
+        .map_err(|e| match e {
+            Error::NotFoundError => Error::JWTTokenError,
+            e => e,
+        })?;
+    if !user.enabled || user.token_version != claims.ver {
+        return Err(Error::JWTTokenError);
+    }
+    if !roles.contains(&Role::from_str(&user.role)) {
+        return Err(NoPermissionError);
+    }
+    Ok(user.id)
+}
+
 fn jwt_from_header(headers: &HeaderMap<HeaderValue>) -> Result<String, Error> {
     let header = match headers.get(AUTHORIZATION) {
         Some(v) => v,
@@ -105,4 +297,79 @@ fn jwt_from_header(headers: &HeaderMap<HeaderValue>) -> Result<String, Error> {
         return Err(Error::InvalidAuthHeaderError);
     }
     Ok(auth_header.trim_start_matches(BEARER).to_owned())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config;
+
+    use super::*;
+
+    fn fixture(name: &str) -> Option<String> {
+        Some(format!("{}/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name))
+    }
+
+    fn hmac_keys(kid: &str, secret: &str, previous_keys: Vec<(String, String)>) -> JwtKeys {
+        let mut config = config::from_env();
+        config.jwt_algorithm = "HS512".to_string();
+        config.jwt_secret = Some(secret.to_string());
+        config.jwt_key_id = Some(kid.to_string());
+        config.jwt_previous_keys = previous_keys;
+        JwtKeys::from_config(&config).unwrap()
+    }
+
+    fn pem_keys(algorithm: &str, name: &str) -> JwtKeys {
+        let mut config = config::from_env();
+        config.jwt_algorithm = algorithm.to_string();
+        config.jwt_private_key = fixture(&format!("{}.pem", name));
+        config.jwt_public_key = fixture(&format!("{}.pub.pem", name));
+        config.jwt_key_id = None;
+        config.jwt_previous_keys = Vec::new();
+        JwtKeys::from_config(&config).unwrap()
+    }
+
+    fn user() -> User {
+        User {
+            id: 7,
+            first_name: None,
+            last_name: None,
+            email: "user@example.com".to_string(),
+            pwd: String::new(),
+            enabled: true,
+            time_zone: "UTC".to_string(),
+            role: "Admin".to_string(),
+            created_at: Utc::now(),
+            token_version: 0,
+        }
+    }
+
+    #[test]
+    fn tokens_are_verified_with_the_key_their_kid_names() {
+        let keys = hmac_keys("2", "current", vec![("1".to_string(), "previous".to_string())]);
+        let claims = decode_token(&create_token(&user(), &keys).unwrap(), &keys).unwrap();
+        assert_eq!(claims.sub, 7);
+        assert_eq!(claims.role, "Admin");
+
+        let previous = hmac_keys("1", "previous", Vec::new());
+        assert!(decode_token(&create_token(&user(), &previous).unwrap(), &keys).is_ok());
+        let forged = hmac_keys("1", "current", Vec::new());
+        assert!(decode_token(&create_token(&user(), &forged).unwrap(), &keys).is_err());
+        let unknown = hmac_keys("3", "previous", Vec::new());
+        assert!(decode_token(&create_token(&user(), &unknown).unwrap(), &keys).is_err());
+        assert!(decode_token(&create_token(&user(), &keys).unwrap(), &previous).is_err());
+    }
+
+    #[test]
+    fn tokens_are_signed_with_rsa_and_ec_keys() {
+        let rsa = pem_keys("RS256", "jwt_rsa_key");
+        let ec = pem_keys("ES256", "jwt_ec_key");
+        for keys in [&rsa, &ec] {
+            let token = create_token(&user(), keys).unwrap();
+            assert_eq!(decode_header(&token).unwrap().alg, keys.algorithm);
+            assert_eq!(decode_token(&token, keys).unwrap().sub, 7);
+        }
+        assert!(decode_token(&create_token(&user(), &rsa).unwrap(), &ec).is_err());
+        let hmac = hmac_keys("1", "secret", Vec::new());
+        assert!(decode_token(&create_token(&user(), &hmac).unwrap(), &rsa).is_err());
+    }
+}
@@ -8,6 +8,63 @@ pub struct Config {
     pub postgres_password: String,
     pub postgres_host: String,
     pub dbname: String,
+    pub jwt_algorithm: String,
+    pub jwt_secret: Option<String>,
+    pub jwt_private_key: Option<String>,
+    pub jwt_public_key: Option<String>,
+    pub jwt_key_id: Option<String>,
+    /// Keys that are no longer used for signing but still accepted, as `kid=secret` for HMAC
+    /// algorithms or `kid=path/to/public.pem` for RS256/ES256.
+    ///
+    /// `JWT_PREVIOUS_KEYS` separates the entries with `,` and splits each at its first `=`, so key
+    /// ids can't contain `=` and keys can't contain `,`.
+    pub jwt_previous_keys: Vec<(String, String)>,
+    pub jwt_lifetime_seconds: i64,
+    pub refresh_token_lifetime_days: i64,
+    /// Failed logins after which an account is locked for `login_lockout_minutes`.
+    pub login_max_failures: i64,
+    /// Failed logins from one client address, for any account, after which it is locked.
+    pub login_ip_max_failures: i64,
+    pub login_lockout_minutes: i64,
+    /// Take the client address from `X-Forwarded-For`, only safe behind a reverse proxy.
+    pub trust_forwarded_for: bool,
+    /// Name authenticator apps show next to the account.
+    pub totp_issuer: String,
+    /// How long the second login step may take once the password was accepted.
+    pub two_factor_challenge_minutes: i64,
+    /// Lifetime of the links that verify an email address, at registration or when it changes.
+    pub verification_token_lifetime_minutes: i64,
+    pub password_reset_token_lifetime_minutes: i64,
+    /// How long an invitation to a project can be accepted, it may have to wait for a registration.
+    pub project_invitation_lifetime_days: i64,
+    /// How often expired verification, password reset and email change tokens and invitations are removed.
+    pub token_cleanup_interval_seconds: u64,
+    /// How long deleted tasks and projects stay in the trash before they are purged for good.
+    pub trash_retention_days: i64,
+    pub trash_purge_interval_seconds: u64,
+    /// How notifications are delivered: `smtp`, `file`, `log` or `memory`.
+    pub notifier: String,
+    /// Directory the `file` notifier writes `.eml` files to.
+    pub mail_spool_dir: String,
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    pub smtp_starttls: bool,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+    pub mail_from: String,
+    /// Public address of the server, used to build the links sent by email.
+    pub app_base_url: String,
+    /// OpenID Connect provider to log in with, the login is only offered when it is set.
+    pub oidc_issuer: Option<String>,
+    pub oidc_client_id: String,
+    pub oidc_client_secret: Option<String>,
+    /// Must be registered with the provider, defaults to `/oidc/callback` of `app_base_url`.
+    pub oidc_redirect_url: String,
+    pub oidc_scopes: String,
+    /// Create an account for unknown users of the provider instead of refusing them.
+    pub oidc_auto_provision: bool,
+    /// How long a started provider login or device login may take.
+    pub oidc_login_lifetime_minutes: i64,
 }
 
 pub fn from_env() -> Config {
@@ -39,11 +96,235 @@ pub fn from_env() -> Config {
         .parse::<String>()
         .unwrap();
 
+    let production = env::var("APP_ENV")
+        .map(|value| value == "production")
+        .unwrap_or(false);
+
+    let jwt_algorithm = env::var("JWT_ALGORITHM")
+        .unwrap_or_else(|_| "HS512".to_string());
+
+    let jwt_private_key = env::var("JWT_PRIVATE_KEY").ok();
+    let jwt_public_key = env::var("JWT_PUBLIC_KEY").ok();
+    let jwt_key_id = env::var("JWT_KEY_ID").ok();
+
+    let jwt_secret = match env::var("JWT_SECRET") {
+        Ok(secret) => Some(secret),
+        Err(_) if production => None,
+        Err(_) => Some("secret".to_string()),
+    };
+
+    let jwt_previous_keys = env::var("JWT_PREVIOUS_KEYS")
+        .unwrap_or_default()
+        .split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| {
+            let (kid, key) = entry.split_once('=')
+                .expect("JWT_PREVIOUS_KEYS entries should look like kid=key");
+            (kid.trim().to_string(), key.trim().to_string())
+        })
+        .collect::<Vec<_>>();
+
+    if let Err(message) = check_jwt_keys(&jwt_algorithm, jwt_secret.as_deref(), jwt_private_key.as_deref(),
+                                         jwt_public_key.as_deref(), &jwt_previous_keys) {
+        panic!("{}", message);
+    }
+
+    let jwt_lifetime_seconds = env::var("JWT_LIFETIME_SECONDS")
+        .unwrap_or_else(|_| "600".to_string())
+        .parse::<i64>()
+        .expect("JWT_LIFETIME_SECONDS environment variable should parse to an integer");
+
+    let refresh_token_lifetime_days = env::var("REFRESH_TOKEN_LIFETIME_DAYS")
+        .unwrap_or_else(|_| "30".to_string())
+        .parse::<i64>()
+        .expect("REFRESH_TOKEN_LIFETIME_DAYS environment variable should parse to an integer");
+
+    let login_max_failures = env::var("LOGIN_MAX_FAILURES")
+        .unwrap_or_else(|_| "5".to_string())
+        .parse::<i64>()
+        .expect("LOGIN_MAX_FAILURES environment variable should parse to an integer");
+
+    let login_ip_max_failures = env::var("LOGIN_IP_MAX_FAILURES")
+        .unwrap_or_else(|_| "50".to_string())
+        .parse::<i64>()
+        .expect("LOGIN_IP_MAX_FAILURES environment variable should parse to an integer");
+
+    let login_lockout_minutes = env::var("LOGIN_LOCKOUT_MINUTES")
+        .unwrap_or_else(|_| "15".to_string())
+        .parse::<i64>()
+        .expect("LOGIN_LOCKOUT_MINUTES environment variable should parse to an integer");
+
+    let trust_forwarded_for = env::var("TRUST_FORWARDED_FOR")
+        .map(|value| value == "true")
+        .unwrap_or(false);
+
+    let totp_issuer = env::var("TOTP_ISSUER")
+        .unwrap_or_else(|_| "Task Manager".to_string());
+
+    let two_factor_challenge_minutes = env::var("TWO_FACTOR_CHALLENGE_MINUTES")
+        .unwrap_or_else(|_| "5".to_string())
+        .parse::<i64>()
+        .expect("TWO_FACTOR_CHALLENGE_MINUTES environment variable should parse to an integer");
+
+    let verification_token_lifetime_minutes = env::var("VERIFICATION_TOKEN_LIFETIME_MINUTES")
+        .unwrap_or_else(|_| "30".to_string())
+        .parse::<i64>()
+        .expect("VERIFICATION_TOKEN_LIFETIME_MINUTES environment variable should parse to an integer");
+
+    let password_reset_token_lifetime_minutes = env::var("PASSWORD_RESET_TOKEN_LIFETIME_MINUTES")
+        .unwrap_or_else(|_| "60".to_string())
+        .parse::<i64>()
+        .expect("PASSWORD_RESET_TOKEN_LIFETIME_MINUTES environment variable should parse to an integer");
+
+    let project_invitation_lifetime_days = env::var("PROJECT_INVITATION_LIFETIME_DAYS")
+        .unwrap_or_else(|_| "7".to_string())
+        .parse::<i64>()
+        .expect("PROJECT_INVITATION_LIFETIME_DAYS environment variable should parse to an integer");
+
+    let token_cleanup_interval_seconds = env::var("TOKEN_CLEANUP_INTERVAL_SECONDS")
+        .unwrap_or_else(|_| "3600".to_string())
+        .parse::<u64>()
+        .expect("TOKEN_CLEANUP_INTERVAL_SECONDS environment variable should parse to an integer");
+
+    let trash_retention_days = env::var("TRASH_RETENTION_DAYS")
+        .unwrap_or_else(|_| "30".to_string())
+        .parse::<i64>()
+        .expect("TRASH_RETENTION_DAYS environment variable should parse to an integer");
+
+    let trash_purge_interval_seconds = env::var("TRASH_PURGE_INTERVAL_SECONDS")
+        .unwrap_or_else(|_| "3600".to_string())
+        .parse::<u64>()
+        .expect("TRASH_PURGE_INTERVAL_SECONDS environment variable should parse to an integer");
+
+    let notifier = env::var("NOTIFIER")
+        .unwrap_or_else(|_| if production { "smtp" } else { "log" }.to_string());
+
+    let mail_spool_dir = env::var("MAIL_SPOOL_DIR")
+        .unwrap_or_else(|_| "mail".to_string());
+
+    let smtp_host = env::var("SMTP_HOST")
+        .unwrap_or_else(|_| "localhost".to_string());
+
+    let smtp_port = env::var("SMTP_PORT")
+        .unwrap_or_else(|_| "25".to_string())
+        .parse::<u16>()
+        .expect("SMTP_PORT environment variable should parse to an integer");
+
+    let smtp_starttls = env::var("SMTP_STARTTLS")
+        .map(|value| value == "true")
+        .unwrap_or(false);
+
+    let smtp_username = env::var("SMTP_USERNAME").ok();
+    let smtp_password = env::var("SMTP_PASSWORD").ok();
+
+    let mail_from = env::var("MAIL_FROM")
+        .unwrap_or_else(|_| "Task Manager <noreply@localhost>".to_string());
+
+    let app_base_url = env::var("APP_BASE_URL")
+        .unwrap_or_else(|_| "http://localhost:8080".to_string());
+
+    let oidc_issuer = env::var("OIDC_ISSUER").ok();
+
+    let oidc_client_id = env::var("OIDC_CLIENT_ID")
+        .unwrap_or_default();
+
+    let oidc_client_secret = env::var("OIDC_CLIENT_SECRET").ok();
+
+    let oidc_redirect_url = env::var("OIDC_REDIRECT_URL")
+        .unwrap_or_else(|_| format!("{}/oidc/callback", app_base_url.trim_end_matches('/')));
+
+    let oidc_scopes = env::var("OIDC_SCOPES")
+        .unwrap_or_else(|_| "openid email profile".to_string());
+
+    let oidc_auto_provision = env::var("OIDC_AUTO_PROVISION")
+        .map(|value| value == "true")
+        .unwrap_or(false);
+
+    let oidc_login_lifetime_minutes = env::var("OIDC_LOGIN_LIFETIME_MINUTES")
+        .unwrap_or_else(|_| "10".to_string())
+        .parse::<i64>()
+        .expect("OIDC_LOGIN_LIFETIME_MINUTES environment variable should parse to an integer");
+
     Config {
         postgres_port,
         postgres_username,
         postgres_password,
         postgres_host,
         dbname,
+        jwt_algorithm,
+        jwt_secret,
+        jwt_private_key,
+        jwt_public_key,
+        jwt_key_id,
+        jwt_previous_keys,
+        jwt_lifetime_seconds,
+        refresh_token_lifetime_days,
+        login_max_failures,
+        login_ip_max_failures,
+        login_lockout_minutes,
+        trust_forwarded_for,
+        totp_issuer,
+        two_factor_challenge_minutes,
+        verification_token_lifetime_minutes,
+        password_reset_token_lifetime_minutes,
+        project_invitation_lifetime_days,
+        token_cleanup_interval_seconds,
+        trash_retention_days,
+        trash_purge_interval_seconds,
+        notifier,
+        mail_spool_dir,
+        smtp_host,
+        smtp_port,
+        smtp_starttls,
+        smtp_username,
+        smtp_password,
+        mail_from,
+        app_base_url,
+        oidc_issuer,
+        oidc_client_id,
+        oidc_client_secret,
+        oidc_redirect_url,
+        oidc_scopes,
+        oidc_auto_provision,
+        oidc_login_lifetime_minutes,
+    }
+}
+
+/// Checks that the keys the signing algorithm needs are there, before they are loaded.
+fn check_jwt_keys(algorithm: &str, secret: Option<&str>, private_key: Option<&str>, public_key: Option<&str>,
+                  previous_keys: &[(String, String)]) -> Result<(), String> {
+    match algorithm {
+        "HS256" | "HS384" | "HS512" => {
+            if secret.is_none_or(str::is_empty) {
+                return Err(format!("JWT_SECRET environment variable must be set and not empty for {}", algorithm));
+            }
+        }
+        "RS256" | "ES256" => {
+            if private_key.is_none() || public_key.is_none() {
+                return Err(format!("JWT_PRIVATE_KEY and JWT_PUBLIC_KEY environment variables must be set for {}", algorithm));
+            }
+        }
+        _ => return Err(format!("JWT_ALGORITHM {} is not supported, use HS256, HS384, HS512, RS256 or ES256", algorithm)),
+    }
+    if previous_keys.iter().any(|(kid, key)| kid.is_empty() || key.is_empty()) {
+        return Err("JWT_PREVIOUS_KEYS entries need a key id and a key".to_string());
     }
-}
\ No newline at end of file
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jwt_keys_have_to_match_the_algorithm() {
+        assert!(check_jwt_keys("HS512", Some("secret"), None, None, &[]).is_ok());
+        assert!(check_jwt_keys("HS512", Some(""), None, None, &[]).is_err());
+        assert!(check_jwt_keys("HS512", None, Some("private.pem"), None, &[]).is_err());
+        assert!(check_jwt_keys("RS256", None, Some("private.pem"), Some("public.pem"), &[]).is_ok());
+        assert!(check_jwt_keys("ES256", Some("secret"), Some("private.pem"), None, &[]).is_err());
+        assert!(check_jwt_keys("none", Some("secret"), None, None, &[]).is_err());
+        let previous = vec![("old".to_string(), String::new())];
+        assert!(check_jwt_keys("HS512", Some("secret"), None, None, &previous).is_err());
+    }
+}
@@ -1,8 +1,89 @@
 use chrono::{DateTime, Utc};
 
+use crate::access::ProjectRole;
+
 pub struct VerificationToken {
     pub id: i32,
     pub user_id: i32,
     pub token: String,
     pub expiry_date: DateTime<Utc>,
-}
\ No newline at end of file
+}
+
+pub struct PasswordResetToken {
+    pub id: i32,
+    pub user_id: i32,
+    pub expiry_date: DateTime<Utc>,
+}
+
+pub struct EmailChangeToken {
+    pub id: i32,
+    pub user_id: i32,
+    pub new_email: String,
+    pub expiry_date: DateTime<Utc>,
+}
+
+/// A personal access token together with the owner's role and state.
+pub struct PersonalAccessToken {
+    pub id: i32,
+    pub user_id: i32,
+    pub scopes: Vec<String>,
+    pub expiry_date: Option<DateTime<Utc>>,
+    pub role: String,
+    pub enabled: bool,
+}
+
+/// Two-factor state of a user, the secret is kept from enrollment on but only used once enabled.
+pub struct TwoFactor {
+    pub secret: Option<String>,
+    pub enabled: bool,
+}
+
+pub struct TwoFactorChallengeToken {
+    pub id: i32,
+    pub user_id: i32,
+    pub expiry_date: DateTime<Utc>,
+    pub attempts: i32,
+}
+
+/// A login started with the OpenID Connect provider, waiting for its callback.
+pub struct OidcState {
+    pub nonce: String,
+    pub code_verifier: String,
+    pub device_code_id: Option<i32>,
+    pub expiry_date: DateTime<Utc>,
+}
+
+/// A device login, `user_id` is set once it was completed in the browser.
+pub struct DeviceCode {
+    pub id: i32,
+    pub user_id: Option<i32>,
+    pub expiry_date: DateTime<Utc>,
+}
+
+pub struct RefreshToken {
+    pub id: i32,
+    pub user_id: i32,
+    pub family_id: String,
+    pub expiry_date: DateTime<Utc>,
+    pub revoked: bool,
+}
+
+/// The role of the user in the project, `None` if they are not a member.
+pub struct ProjectAccess {
+    pub organization_id: i32,
+    pub role: Option<ProjectRole>,
+}
+
+pub struct TaskAccess {
+    pub project_id: i32,
+    pub reporter_id: i32,
+    pub role: Option<ProjectRole>,
+}
+
+/// Recent failed logins for an email and for a client address, with the latest of each.
+pub struct LoginFailures {
+    pub account: i64,
+    pub account_last: Option<DateTime<Utc>>,
+    pub ip: i64,
+    pub ip_last: Option<DateTime<Utc>>,
+}
@@ -1,37 +1,119 @@
+use std::collections::HashMap;
 use std::time::Duration;
 
 use bcrypt::DEFAULT_COST;
 use chrono::{DateTime, Utc};
 use mobc::{Connection, Pool};
 use mobc_postgres::PgConnectionManager;
-use mobc_postgres::tokio_postgres::{Config, NoTls, Row, Transaction};
+use mobc_postgres::tokio_postgres::{Config, GenericClient, NoTls, Row, Transaction};
+use mobc_postgres::tokio_postgres::error::SqlState;
 use refinery::config::ConfigDbType;
 use uuid::Uuid;
 
-use common::data::{Pageable, Project, ProjectRequest, Task, TaskRequest, User, UserRequest};
+use common::data::{AccessToken, AccessTokenRequest, AccessTokenResponse, DueFilter, Organization, OrganizationMember, Pageable, ProfileUpdateRequest, Project, ProjectDeleteStrategy, ProjectDetails, ProjectFilter, ProjectInvitation, ProjectMember, ProjectRequest, ProjectUpdateRequest, StatusCount, StatusTransition, Task, TaskFilter, TaskRequest, TaskStatus, TaskUpdateRequest, TaskStatusRequest, TaskTree, TrashItem, TrashKind, User, UserDeleteStrategy, UserRequest, Workflow, WorkflowRequest};
 
-use crate::{DBPool, embedded, error};
-use crate::data::VerificationToken;
+use crate::{auth, DBPool, embedded, error, lockout, oidc, totp};
+use crate::access::{OrganizationRole, ProjectRole};
+use crate::data::{DeviceCode, EmailChangeToken, LoginFailures, OidcState, PasswordResetToken, PersonalAccessToken, ProjectAccess, RefreshToken, TaskAccess, TwoFactor, TwoFactorChallengeToken, VerificationToken};
 use crate::error::Error;
-use crate::error::Error::{DBInitError, DBInitErrorTest, DBPoolError, DBQueryError, EncryptPasswordError, WrongCredentialsError};
+use crate::error::Error::{AlreadyOrganizationMemberError, AlreadyProjectMemberError, DBInitError, DBInitErrorTest, DBPoolError, DBQueryError, DeviceCodeError, EmailChangeTokenError, EmailTakenError, EncryptPasswordError, IllegalStatusTransitionError, InvalidMoveTargetError, InvalidParentTaskError, InvalidReassignTargetError, InvalidScheduleError, InvalidTimeZoneError, InvalidTwoFactorCodeError, InvalidWorkflowError, InvitationError, LastAdminError, LastOrganizationAdminError, LastProjectMemberError, LastProjectOwnerError, NotFoundError, NotProjectMemberError, OidcStateError, OidcUserNotFoundError, PasswordResetTokenError, ProjectNotEmptyError, RefreshTokenError, TaskDepthExceededError, TwoFactorChallengeError, TwoFactorEnabledError, TwoFactorNotEnrolledError, VerificationTokenError, VerificationTokenExpiredError, WrongCredentialsError};
+use crate::oidc::IdTokenClaims;
 
-const USER_SELECT_FIELDS: &str = "id,first_name,last_name,email,pwd,ebabled,created_at";
-const USER_INSERT_FIELDS: &str = "first_name,last_name,email,pwd";
+const USER_SELECT_FIELDS: &str = "id,first_name,last_name,email,pwd,enabled,time_zone,role,created_at,token_version";
+const USER_INSERT_FIELDS: &str = "first_name,last_name,email,pwd,time_zone";
 const USERS_TABLE_NAME: &str = "app_users";
 
 const TOKENS_SELECT_FIELDS: &str = "id,user_id,token,expiry_date";
 const TOKENS_INSERT_FIELDS: &str = "user_id,token,expiry_date";
 const TOKENS_TABLE_NAME: &str = "verification_tokens";
 
-const TASK_SELECT_FIELDS: &str = "id,title,description,user_id,project_id,created_at";
+const RESET_TOKENS_SELECT_FIELDS: &str = "id,user_id,expiry_date";
+const RESET_TOKENS_INSERT_FIELDS: &str = "user_id,token_hash,expiry_date";
+const RESET_TOKENS_TABLE_NAME: &str = "password_reset_tokens";
+
+const EMAIL_TOKENS_SELECT_FIELDS: &str = "id,user_id,new_email,expiry_date";
+const EMAIL_TOKENS_INSERT_FIELDS: &str = "user_id,new_email,token_hash,expiry_date";
+const EMAIL_TOKENS_TABLE_NAME: &str = "email_change_tokens";
+
+const ACCESS_TOKENS_SELECT_FIELDS: &str = "id,name,scopes,expiry_date,last_used_at,created_at";
+const ACCESS_TOKENS_INSERT_FIELDS: &str = "user_id,name,token_hash,scopes,expiry_date";
+const ACCESS_TOKENS_TABLE_NAME: &str = "personal_access_tokens";
+
+const RECOVERY_CODES_INSERT_FIELDS: &str = "user_id,code_hash";
+const RECOVERY_CODES_TABLE_NAME: &str = "recovery_codes";
+
+const CHALLENGES_SELECT_FIELDS: &str = "id,user_id,expiry_date,attempts";
+const CHALLENGES_INSERT_FIELDS: &str = "user_id,token_hash,expiry_date";
+const CHALLENGES_TABLE_NAME: &str = "two_factor_challenges";
+/// Wrong answers after which a two-factor challenge is dropped.
+const TWO_FACTOR_MAX_ATTEMPTS: i32 = 5;
+
+const IDENTITIES_INSERT_FIELDS: &str = "user_id,issuer,subject";
+const IDENTITIES_TABLE_NAME: &str = "user_identities";
+
+const OIDC_STATES_SELECT_FIELDS: &str = "nonce,code_verifier,device_code_id,expiry_date";
+const OIDC_STATES_INSERT_FIELDS: &str = "state_hash,nonce,code_verifier,device_code_id,expiry_date";
+const OIDC_STATES_TABLE_NAME: &str = "oidc_states";
+
+const DEVICE_CODES_SELECT_FIELDS: &str = "id,user_id,expiry_date";
+const DEVICE_CODES_INSERT_FIELDS: &str = "device_code_hash,user_code,expiry_date";
+const DEVICE_CODES_TABLE_NAME: &str = "device_codes";
+
+const LOGIN_ATTEMPTS_INSERT_FIELDS: &str = "user_id,email,ip,success,reason";
+const LOGIN_ATTEMPTS_TABLE_NAME: &str = "login_attempts";
+
+const REFRESH_TOKENS_SELECT_FIELDS: &str = "id,user_id,family_id,expiry_date,revoked";
+const REFRESH_TOKENS_INSERT_FIELDS: &str = "user_id,token_hash,family_id,expiry_date";
+const REFRESH_TOKENS_TABLE_NAME: &str = "refresh_tokens";
+
+const TASK_SELECT_FIELDS: &str = "id,title,description,reporter_id,project_id,created_at,\
+(SELECT name FROM task_statuses WHERE task_statuses.id = tasks.status_id),start_at,due_at,\
+ARRAY(SELECT user_id FROM task_assignees WHERE task_assignees.task_id = tasks.id ORDER BY user_id),parent_id";
 const TASKS_TABLE_NAME: &str = "tasks";
-const TASK_INSERT_FIELDS: &str = "title,description,user_id,project_id";
+const TASK_INSERT_FIELDS: &str = "title,description,reporter_id,project_id,start_at,due_at,parent_id,status_id";
+/// Tasks nest at most this many levels deep, top-level tasks included.
+const MAX_TASK_DEPTH: i32 = 5;
+/// The task bound to `$1` and everything below it, trashed subtasks included.
+const SUBTREE_QUERY: &str = "WITH RECURSIVE subtree(id) AS (SELECT $1::int \
+UNION ALL SELECT t.id FROM tasks t JOIN subtree s ON t.parent_id = s.id) SELECT id FROM subtree";
+const TASK_ASSIGNEES_TABLE_NAME: &str = "task_assignees";
+const USER_TIME_ZONE: &str = "(SELECT time_zone FROM app_users WHERE id = $1)";
+
+const STATUS_SELECT_FIELDS: &str = "id,project_id,name,position,done";
+const STATUSES_TABLE_NAME: &str = "task_statuses";
+const STATUS_INSERT_FIELDS: &str = "project_id,name,position,done";
+const TRANSITIONS_TABLE_NAME: &str = "task_status_transitions";
+
+const DEFAULT_STATUSES: [(&str, bool); 4] = [
+    ("Todo", false),
+    ("In Progress", false),
+    ("Review", false),
+    ("Done", true)
+];
+const DEFAULT_TRANSITIONS: [(&str, &str); 6] = [
+    ("Todo", "In Progress"),
+    ("In Progress", "Todo"),
+    ("In Progress", "Review"),
+    ("Review", "In Progress"),
+    ("Review", "Done"),
+    ("Done", "Todo")
+];
 
-const PROJECT_SELECT_FIELDS: &str = "id,title,description,created_at";
+const PROJECT_SELECT_FIELDS: &str = "id,title,description,created_at,archived_at";
 const PROJECT_TABLE_NAME: &str = "projects";
-const PROJECT_INSERT_FIELDS: &str = "title,description";
+const PROJECT_INSERT_FIELDS: &str = "title,description,organization_id";
 
 const USERS_PROJECTS_TABLE_NAME: &str = "users_projects";
+/// Orders memberships from the highest role down, owners aside.
+const ROLE_RANK: &str = "CASE role WHEN 'maintainer' THEN 0 WHEN 'member' THEN 1 ELSE 2 END";
+
+const INVITATIONS_SELECT_FIELDS: &str = "i.id,i.project_id,p.title,i.email,i.role,i.expiry_date,i.created_at";
+const INVITATIONS_INSERT_FIELDS: &str = "project_id,email,token_hash,invited_by,expiry_date,role";
+const INVITATIONS_TABLE_NAME: &str = "project_invitations";
+
+const ORGANIZATIONS_SELECT_FIELDS: &str = "o.id,o.name,m.role,o.created_at";
+const ORGANIZATIONS_TABLE_NAME: &str = "organizations";
+const ORGANIZATION_MEMBERS_TABLE_NAME: &str = "organization_members";
 
 type DBCon = Connection<PgConnectionManager<NoTls>>;
 type Result<T> = std::result::Result<T, error::Error>;
@@ -91,6 +173,237 @@ pub async fn find_user_by_email(db_pool: DBPool, email: &str) -> Result<User> {
     Ok(row_to_user(&row))
 }
 
+pub(crate) async fn find_user_by_id(db_pool: &DBPool, user_id: i32) -> Result<User> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("SELECT {} FROM {} WHERE id = $1",
+                        USER_SELECT_FIELDS, USERS_TABLE_NAME);
+    let row = con.query_opt(query.as_str(), &[&user_id])
+        .await
+        .map_err(DBQueryError)?
+        .ok_or(NotFoundError)?;
+    Ok(row_to_user(&row))
+}
+
+/// Applies an administrator's changes to the user, refusing to demote or disable the only
+/// remaining enabled administrator. A disabled user is signed out everywhere.
+pub(crate) async fn update_user(db_pool: &DBPool, user_id: i32, role: Option<&str>,
+                                enabled: Option<bool>) -> Result<User> {
+    let mut con = get_conn(db_pool).await?;
+    let transaction = con.transaction().await.map_err(DBQueryError)?;
+    let user = lock_user(&transaction, user_id).await?;
+    let stays_admin = role.unwrap_or(&user.role) == "Admin" && enabled.unwrap_or(user.enabled);
+    if is_enabled_admin(&user) && !stays_admin && !other_admin_exists(&transaction, user_id).await? {
+        return Err(LastAdminError);
+    }
+    let query = format!("UPDATE {} SET role = COALESCE($2, role), enabled = COALESCE($3, enabled), \
+    token_version = token_version + CASE WHEN $3 = false THEN 1 ELSE 0 END WHERE id = $1 RETURNING {}",
+                        USERS_TABLE_NAME, USER_SELECT_FIELDS);
+    let row = transaction.query_one(query.as_str(), &[&user_id, &role, &enabled])
+        .await
+        .map_err(DBQueryError)?;
+    if enabled == Some(false) {
+        let query = format!("UPDATE {} SET revoked = true WHERE user_id = $1", REFRESH_TOKENS_TABLE_NAME);
+        transaction.execute(query.as_str(), &[&user_id])
+            .await
+            .map_err(DBQueryError)?;
+    }
+    transaction.commit().await.map_err(DBQueryError)?;
+    Ok(row_to_user(&row))
+}
+
+/// Deletes the user along with everything that only makes sense for them.
+///
+/// Projects nobody else is a member of, and the tasks the user reported, are either handed
+/// over to `reassign_to` or deleted, depending on the strategy.
+pub(crate) async fn delete_user(db_pool: &DBPool, user_id: i32, strategy: UserDeleteStrategy,
+                                reassign_to: Option<i32>) -> Result<()> {
+    let mut con = get_conn(db_pool).await?;
+    let transaction = con.transaction().await.map_err(DBQueryError)?;
+    let user = lock_user(&transaction, user_id).await?;
+    if is_enabled_admin(&user) && !other_admin_exists(&transaction, user_id).await? {
+        return Err(LastAdminError);
+    }
+    let query = format!("SELECT organization_id FROM {} WHERE user_id = $1", ORGANIZATION_MEMBERS_TABLE_NAME);
+    let organization_ids = transaction.query(query.as_str(), &[&user_id])
+        .await
+        .map_err(DBQueryError)?
+        .iter()
+        .map(|row| row.get(0))
+        .collect::<Vec<i32>>();
+    let sole_projects = format!("SELECT project_id FROM {table} WHERE user_id = $1 \
+    AND project_id NOT IN (SELECT project_id FROM {table} WHERE user_id <> $1)",
+                                table = USERS_PROJECTS_TABLE_NAME);
+    match strategy {
+        UserDeleteStrategy::Reassign => {
+            let successor = reassign_to
+                .filter(|successor| *successor != user_id)
+                .ok_or(InvalidReassignTargetError)?;
+            let query = format!("SELECT 1 FROM {} WHERE id = $1", USERS_TABLE_NAME);
+            transaction.query_opt(query.as_str(), &[&successor])
+                .await
+                .map_err(DBQueryError)?
+                .ok_or(InvalidReassignTargetError)?;
+            // the successor takes over the projects that would lose their last owner, and joins
+            // the projects of the tasks handed over
+            let query = format!("INSERT INTO {members} (user_id, project_id, role) \
+            SELECT $2::int, project_id, 'owner' FROM ({sole} UNION {owned}) AS p \
+            ON CONFLICT (user_id, project_id) DO UPDATE SET role = 'owner'",
+                                members = USERS_PROJECTS_TABLE_NAME, sole = sole_projects, owned = sole_owned_projects());
+            transaction.execute(query.as_str(), &[&user_id, &successor])
+                .await
+                .map_err(DBQueryError)?;
+            let query = format!("INSERT INTO {} (user_id, project_id, role) \
+            SELECT DISTINCT $2::int, project_id, 'member' FROM {} WHERE reporter_id = $1 \
+            ON CONFLICT (user_id, project_id) DO NOTHING",
+                                USERS_PROJECTS_TABLE_NAME, TASKS_TABLE_NAME);
+            transaction.execute(query.as_str(), &[&user_id, &successor])
+                .await
+                .map_err(DBQueryError)?;
+            let query = format!("UPDATE {} SET reporter_id = $2 WHERE reporter_id = $1", TASKS_TABLE_NAME);
+            transaction.execute(query.as_str(), &[&user_id, &successor])
+                .await
+                .map_err(DBQueryError)?;
+            let query = format!("INSERT INTO {} (organization_id, user_id) \
+            SELECT DISTINCT p.organization_id, $1::int FROM {} p JOIN {} m ON m.project_id = p.id WHERE m.user_id = $1 \
+            ON CONFLICT (organization_id, user_id) DO NOTHING",
+                                ORGANIZATION_MEMBERS_TABLE_NAME, PROJECT_TABLE_NAME, USERS_PROJECTS_TABLE_NAME);
+            transaction.execute(query.as_str(), &[&successor])
+                .await
+                .map_err(DBQueryError)?;
+        }
+        UserDeleteStrategy::Cascade => {
+            let project_ids = transaction.query(sole_projects.as_str(), &[&user_id])
+                .await
+                .map_err(DBQueryError)?
+                .iter()
+                .map(|row| row.get(0))
+                .collect::<Vec<i32>>();
+            let doomed_tasks = format!("SELECT id FROM {} WHERE reporter_id = $1 OR project_id = ANY($2)",
+                                       TASKS_TABLE_NAME);
+            let queries = [
+                format!("DELETE FROM {} WHERE task_id IN ({})", TASK_ASSIGNEES_TABLE_NAME, doomed_tasks),
+                format!("DELETE FROM {} WHERE id IN ({})", TASKS_TABLE_NAME, doomed_tasks),
+            ];
+            for query in queries.iter() {
+                transaction.execute(query.as_str(), &[&user_id, &project_ids])
+                    .await
+                    .map_err(DBQueryError)?;
+            }
+            let queries = [
+                format!("DELETE FROM {} WHERE from_status_id IN (SELECT id FROM {} WHERE project_id = ANY($1))",
+                        TRANSITIONS_TABLE_NAME, STATUSES_TABLE_NAME),
+                format!("DELETE FROM {} WHERE project_id = ANY($1)", STATUSES_TABLE_NAME),
+                format!("DELETE FROM {} WHERE project_id = ANY($1)", USERS_PROJECTS_TABLE_NAME),
+                format!("DELETE FROM {} WHERE project_id = ANY($1)", INVITATIONS_TABLE_NAME),
+                format!("DELETE FROM {} WHERE id = ANY($1)", PROJECT_TABLE_NAME),
+            ];
+            for query in queries.iter() {
+                transaction.execute(query.as_str(), &[&project_ids])
+                    .await
+                    .map_err(DBQueryError)?;
+            }
+        }
+    }
+    let queries = [
+        // shared projects the user owned alone pass to the highest ranking remaining member
+        format!("UPDATE {members} m SET role = 'owner' FROM (\
+        SELECT DISTINCT ON (project_id) project_id, user_id FROM {members} \
+        WHERE user_id <> $1 AND project_id IN ({owned}) \
+        ORDER BY project_id, {rank}, user_id) AS heir \
+        WHERE m.project_id = heir.project_id AND m.user_id = heir.user_id",
+                members = USERS_PROJECTS_TABLE_NAME, owned = sole_owned_projects(), rank = ROLE_RANK),
+        // and so do the organizations the user was the only admin of
+        format!("UPDATE {members} m SET role = 'admin' FROM (\
+        SELECT DISTINCT ON (organization_id) organization_id, user_id FROM {members} \
+        WHERE user_id <> $1 AND organization_id IN ({administered}) \
+        ORDER BY organization_id, user_id) AS heir \
+        WHERE m.organization_id = heir.organization_id AND m.user_id = heir.user_id",
+                members = ORGANIZATION_MEMBERS_TABLE_NAME, administered = sole_administered_organizations()),
+        format!("DELETE FROM {} WHERE user_id = $1", TASK_ASSIGNEES_TABLE_NAME),
+        format!("DELETE FROM {} WHERE user_id = $1", USERS_PROJECTS_TABLE_NAME),
+        format!("DELETE FROM {} WHERE user_id = $1", ORGANIZATION_MEMBERS_TABLE_NAME),
+        format!("DELETE FROM {} WHERE invited_by = $1", INVITATIONS_TABLE_NAME),
+        format!("DELETE FROM {} WHERE user_id = $1", TOKENS_TABLE_NAME),
+        format!("DELETE FROM {} WHERE user_id = $1", RESET_TOKENS_TABLE_NAME),
+        format!("DELETE FROM {} WHERE user_id = $1", EMAIL_TOKENS_TABLE_NAME),
+        format!("DELETE FROM {} WHERE user_id = $1", ACCESS_TOKENS_TABLE_NAME),
+        format!("DELETE FROM {} WHERE user_id = $1", REFRESH_TOKENS_TABLE_NAME),
+        format!("DELETE FROM {} WHERE user_id = $1", RECOVERY_CODES_TABLE_NAME),
+        format!("DELETE FROM {} WHERE user_id = $1", CHALLENGES_TABLE_NAME),
+        format!("DELETE FROM {} WHERE user_id = $1", IDENTITIES_TABLE_NAME),
+        format!("DELETE FROM {} WHERE device_code_id IN (SELECT id FROM {} WHERE user_id = $1)",
+                OIDC_STATES_TABLE_NAME, DEVICE_CODES_TABLE_NAME),
+        format!("DELETE FROM {} WHERE user_id = $1", DEVICE_CODES_TABLE_NAME),
+        // failed logins stay in the audit trail under the email address
+        format!("UPDATE {} SET user_id = NULL WHERE user_id = $1", LOGIN_ATTEMPTS_TABLE_NAME),
+        format!("DELETE FROM {} WHERE id = $1", USERS_TABLE_NAME),
+    ];
+    for query in queries.iter() {
+        transaction.execute(query.as_str(), &[&user_id])
+            .await
+            .map_err(DBQueryError)?;
+    }
+    // organizations the user was alone in, like their personal one, go with them
+    let query = format!("DELETE FROM {} o WHERE id = ANY($1) \
+    AND NOT EXISTS (SELECT 1 FROM {} WHERE organization_id = o.id) \
+    AND NOT EXISTS (SELECT 1 FROM {} WHERE organization_id = o.id)",
+                        ORGANIZATIONS_TABLE_NAME, ORGANIZATION_MEMBERS_TABLE_NAME, PROJECT_TABLE_NAME);
+    transaction.execute(query.as_str(), &[&organization_ids])
+        .await
+        .map_err(DBQueryError)?;
+    transaction.commit().await.map_err(DBQueryError)?;
+    Ok(())
+}
+
+/// Projects in which the user `$1` is the only owner.
+fn sole_owned_projects() -> String {
+    format!("SELECT project_id FROM {table} WHERE user_id = $1 AND role = 'owner' \
+    AND project_id NOT IN (SELECT project_id FROM {table} WHERE role = 'owner' AND user_id <> $1)",
+            table = USERS_PROJECTS_TABLE_NAME)
+}
+
+/// Organizations in which the user `$1` is the only admin.
+fn sole_administered_organizations() -> String {
+    format!("SELECT organization_id FROM {table} WHERE user_id = $1 AND role = 'admin' \
+    AND organization_id NOT IN (SELECT organization_id FROM {table} WHERE role = 'admin' AND user_id <> $1)",
+            table = ORGANIZATION_MEMBERS_TABLE_NAME)
+}
+
+async fn lock_user(transaction: &Transaction<'_>, user_id: i32) -> Result<User> {
+    let query = format!("SELECT {} FROM {} WHERE id = $1 FOR UPDATE",
+                        USER_SELECT_FIELDS, USERS_TABLE_NAME);
+    let row = transaction.query_opt(query.as_str(), &[&user_id])
+        .await
+        .map_err(DBQueryError)?
+        .ok_or(NotFoundError)?;
+    Ok(row_to_user(&row))
+}
+
+fn is_enabled_admin(user: &User) -> bool {
+    user.role == "Admin" && user.enabled
+}
+
+/// Whether an enabled administrator other than the given user is left, the rows are locked
+/// so two administrators cannot remove each other at the same time.
+async fn other_admin_exists(transaction: &Transaction<'_>, user_id: i32) -> Result<bool> {
+    let query = format!("SELECT id FROM {} WHERE role = 'Admin' AND enabled AND id <> $1 FOR UPDATE",
+                        USERS_TABLE_NAME);
+    let rows = transaction.query(query.as_str(), &[&user_id])
+        .await
+        .map_err(DBQueryError)?;
+    Ok(!rows.is_empty())
+}
+
+pub async fn promote_to_admin(db_pool: &DBPool, email: &str) -> Result<User> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("UPDATE {} SET role = 'Admin' WHERE email = $1 RETURNING {}",
+                        USERS_TABLE_NAME, USER_SELECT_FIELDS);
+    let row = con.query_opt(query.as_str(), &[&email])
+        .await
+        .map_err(DBQueryError)?
+        .ok_or(NotFoundError)?;
+    Ok(row_to_user(&row))
+}
 
 pub(crate) async fn find_users(db_pool: &DBPool, pageable: Pageable) -> Result<Vec<User>> {
     let con = get_conn(db_pool).await?;
@@ -107,13 +420,27 @@ pub(crate) async fn find_users(db_pool: &DBPool, pageable: Pageable) -> Result<V
     Ok(users)
 }
 
-pub(crate) async fn find_tasks(db_pool: &DBPool, pageable: Pageable, user_id: i32) -> Result<Vec<Task>> {
+pub(crate) async fn find_tasks(db_pool: &DBPool, pageable: Pageable, task_filter: TaskFilter,
+                               user_id: i32, organization: Option<i32>) -> Result<Vec<Task>> {
     let con = get_conn(db_pool).await?;
+    let mut where_clause = if task_filter.assigned_to_me.unwrap_or(false) {
+        String::from("WHERE id IN (SELECT task_id FROM task_assignees WHERE user_id = $1)")
+    } else {
+        String::from("WHERE (reporter_id = $1 OR id IN (SELECT task_id FROM task_assignees WHERE user_id = $1))")
+    };
+    if let Some(due) = task_filter.due {
+        where_clause.push_str(" AND ");
+        where_clause.push_str(&due_condition(due));
+    }
+    where_clause.push_str(&format!(" AND deleted_at IS NULL AND project_id IN (SELECT id FROM {} WHERE deleted_at IS NULL AND {}) \
+    AND project_id IN (SELECT project_id FROM {} WHERE user_id = $1)",
+                                   PROJECT_TABLE_NAME, organization_condition("organization_id", "$1", "$2"),
+                                   USERS_PROJECTS_TABLE_NAME));
     let query = get_select_query(TASK_SELECT_FIELDS,
                                  TASKS_TABLE_NAME,
-                                 "WHERE user_id = $1",
+                                 &where_clause,
                                  pageable);
-    let row_tasks = con.query(query.as_str(), &[&user_id])
+    let row_tasks = con.query(query.as_str(), &[&user_id, &organization])
         .await
         .map_err(DBQueryError)?;
     let tasks = row_tasks.iter().map(|row_task| row_to_task(row_task))
@@ -121,6 +448,26 @@ pub(crate) async fn find_tasks(db_pool: &DBPool, pageable: Pageable, user_id: i3
     Ok(tasks)
 }
 
+/// Builds the condition for a due date bucket, `$1` must be bound to the id of the user.
+fn due_condition(due: DueFilter) -> String {
+    match due {
+        DueFilter::Overdue => "due_at < now() AND NOT EXISTS \
+        (SELECT 1 FROM task_statuses WHERE task_statuses.id = tasks.status_id AND task_statuses.done)"
+            .to_string(),
+        DueFilter::Today => format!("(due_at AT TIME ZONE {tz})::date = (now() AT TIME ZONE {tz})::date",
+                                    tz = USER_TIME_ZONE),
+        DueFilter::Week => format!("date_trunc('week', due_at AT TIME ZONE {tz}) = \
+        date_trunc('week', now() AT TIME ZONE {tz})",
+                                   tz = USER_TIME_ZONE),
+    }
+}
+
+/// Restricts `column` to the organizations of the user, or to the requested one of them. Takes
+/// the placeholders the user id and the requested organization are bound to.
+fn organization_condition(column: &str, user_id: &str, organization: &str) -> String {
+    format!("{} IN (SELECT organization_id FROM {} WHERE user_id = {} AND ({org}::int IS NULL OR organization_id = {org}))",
+            column, ORGANIZATION_MEMBERS_TABLE_NAME, user_id, org = organization)
+}
 
 fn get_select_query(select_fields: &str,
                     table_name: &str,
@@ -138,13 +485,17 @@ fn get_select_query(select_fields: &str,
 
 pub(crate) async fn find_projects(db_pool: &DBPool,
                                   pageable: Pageable,
-                                  user_id: i32) -> Result<Vec<Project>> {
+                                  project_filter: ProjectFilter,
+                                  user_id: i32,
+                                  organization: Option<i32>) -> Result<Vec<Project>> {
     let con = get_conn(db_pool).await?;
-    let query = get_select_query("p.id,p.title,p.description,p.created_at",
+    let archived = if project_filter.archived.unwrap_or(false) { "IS NOT NULL" } else { "IS NULL" };
+    let query = get_select_query("p.id,p.title,p.description,p.created_at,p.archived_at",
                                  "projects p JOIN users_projects up ON p.id = up.project_id",
-                                 "WHERE up.user_id = $1",
+                                 &format!("WHERE up.user_id = $1 AND p.deleted_at IS NULL AND p.archived_at {} AND {}",
+                                          archived, organization_condition("p.organization_id", "$1", "$2")),
                                  pageable);
-    let row_tasks = con.query(query.as_str(), &[&user_id])
+    let row_tasks = con.query(query.as_str(), &[&user_id, &organization])
         .await
         .map_err(DBQueryError)?;
     let projects = row_tasks.iter().map(|row_project| row_to_project(row_project))
@@ -153,89 +504,318 @@ pub(crate) async fn find_projects(db_pool: &DBPool,
 }
 
 pub(crate) async fn create_user_and_verification_token(db_pool: &DBPool,
-                                                       user_request: UserRequest) -> Result<(User, VerificationToken)> {
-    let create_user_query = format!("INSERT INTO {} ({}) VALUES ($1,$2,$3,$4) RETURNING {}",
+                                                       user_request: UserRequest,
+                                                       token_lifetime: chrono::Duration) -> Result<(User, VerificationToken)> {
+    let create_user_query = format!("INSERT INTO {} ({}) VALUES ($1,$2,$3,$4,COALESCE($5,'UTC')) RETURNING {}",
                                     USERS_TABLE_NAME,
                                     USER_INSERT_FIELDS,
                                     USER_SELECT_FIELDS
     );
-    let create_token_query =
-        format!("INSERT INTO {} ({})  VALUES ($1,$2,$3) RETURNING {}",
-                TOKENS_TABLE_NAME,
-                TOKENS_INSERT_FIELDS,
-                TOKENS_SELECT_FIELDS);
     let encrypted_pwd = bcrypt::hash(user_request.pwd, DEFAULT_COST)
         .map_err(EncryptPasswordError)?;
     let mut con = get_conn(db_pool).await?;
+    if let Some(time_zone) = &user_request.time_zone {
+        if !is_known_time_zone(&con, time_zone).await? {
+            return Err(InvalidTimeZoneError);
+        }
+    }
     let transaction = con.transaction().await
         .map_err(DBQueryError)?;
     let user_row = transaction
         .query_one(create_user_query.as_str(), &[&user_request.first_name,
             &user_request.last_name,
             &user_request.email,
-            &encrypted_pwd])
+            &encrypted_pwd,
+            &user_request.time_zone])
         .await
         .map_err(DBQueryError);
     if let Err(e) = user_row {
-        transaction.rollback();
+        transaction.rollback().await.map_err(DBQueryError)?;
         return Err(e);
     }
     let user_row = user_row.unwrap();
     let user = row_to_user(&user_row);
-    let expiry_date = chrono::offset::Utc::now() + chrono::Duration::minutes(30);
-    let token_value = Uuid::new_v4().to_string();
-    let verification_token_row = transaction.query_one(create_token_query.as_str(),
-                                                       &[&user.id, &token_value, &expiry_date]).await;
-    if let Err(e) = verification_token_row {
+    create_personal_organization(&transaction, &user).await?;
+    let verification_token = insert_verification_token(&transaction, user.id, token_lifetime).await;
+    if let Err(e) = verification_token {
         transaction.rollback().await.map_err(DBQueryError)?;
-        return Err(DBQueryError(e));
+        return Err(e);
     }
-    let verification_token_row = verification_token_row.unwrap();
-    let verification_token = row_to_token(&verification_token_row);
+    let verification_token = verification_token.unwrap();
     transaction.commit().await.map_err(DBQueryError)?;
     Ok((user, verification_token))
 }
 
+async fn insert_verification_token<C: GenericClient>(con: &C,
+                                                     user_id: i32,
+                                                     lifetime: chrono::Duration) -> Result<VerificationToken> {
+    let query = format!("INSERT INTO {} ({})  VALUES ($1,$2,$3) RETURNING {}",
+                        TOKENS_TABLE_NAME,
+                        TOKENS_INSERT_FIELDS,
+                        TOKENS_SELECT_FIELDS);
+    let expiry_date = Utc::now() + lifetime;
+    let token_value = Uuid::new_v4().to_string();
+    let row = con.query_one(query.as_str(), &[&user_id, &token_value, &expiry_date])
+        .await
+        .map_err(DBQueryError)?;
+    Ok(row_to_token(&row))
+}
+
+/// Enables the owner of the verification token and consumes the token.
+///
+/// Expired tokens are consumed as well, the user has to ask for a new one.
+pub(crate) async fn confirm_registration(db_pool: &DBPool, token: &str) -> Result<User> {
+    let mut con = get_conn(db_pool).await?;
+    let transaction = con.transaction().await.map_err(DBQueryError)?;
+    let query = format!("SELECT {} FROM {} WHERE token = $1 FOR UPDATE",
+                        TOKENS_SELECT_FIELDS, TOKENS_TABLE_NAME);
+    let row = transaction.query_opt(query.as_str(), &[&token])
+        .await
+        .map_err(DBQueryError)?;
+    let verification_token = match row {
+        Some(row) => row_to_token(&row),
+        None => return Err(VerificationTokenError),
+    };
+    let query = format!("DELETE FROM {} WHERE id = $1", TOKENS_TABLE_NAME);
+    transaction.execute(query.as_str(), &[&verification_token.id])
+        .await
+        .map_err(DBQueryError)?;
+    if verification_token.expiry_date < Utc::now() {
+        transaction.commit().await.map_err(DBQueryError)?;
+        return Err(VerificationTokenExpiredError);
+    }
+    let query = format!("UPDATE {} SET enabled = true WHERE id = $1 RETURNING {}",
+                        USERS_TABLE_NAME, USER_SELECT_FIELDS);
+    let row = transaction.query_one(query.as_str(), &[&verification_token.user_id])
+        .await
+        .map_err(DBQueryError)?;
+    transaction.commit().await.map_err(DBQueryError)?;
+    Ok(row_to_user(&row))
+}
+
+/// Replaces the verification tokens of a not yet enabled user with a fresh one.
+///
+/// Returns `None` when there is no such user, so callers can answer the same way in both cases.
+pub(crate) async fn renew_verification_token(db_pool: &DBPool,
+                                             email: &str,
+                                             lifetime: chrono::Duration) -> Result<Option<(User, VerificationToken)>> {
+    let mut con = get_conn(db_pool).await?;
+    let transaction = con.transaction().await.map_err(DBQueryError)?;
+    let query = format!("SELECT {} FROM {} WHERE email = $1 AND enabled = false FOR UPDATE",
+                        USER_SELECT_FIELDS, USERS_TABLE_NAME);
+    let row = transaction.query_opt(query.as_str(), &[&email])
+        .await
+        .map_err(DBQueryError)?;
+    let user = match row {
+        Some(row) => row_to_user(&row),
+        None => return Ok(None),
+    };
+    let query = format!("DELETE FROM {} WHERE user_id = $1", TOKENS_TABLE_NAME);
+    transaction.execute(query.as_str(), &[&user.id])
+        .await
+        .map_err(DBQueryError)?;
+    let verification_token = insert_verification_token(&transaction, user.id, lifetime).await?;
+    transaction.commit().await.map_err(DBQueryError)?;
+    Ok(Some((user, verification_token)))
+}
+
+pub(crate) async fn delete_expired_verification_tokens(db_pool: &DBPool) -> Result<u64> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("DELETE FROM {} WHERE expiry_date < now()", TOKENS_TABLE_NAME);
+    con.execute(query.as_str(), &[])
+        .await
+        .map_err(DBQueryError)
+}
+
+async fn is_known_time_zone(con: &DBCon, time_zone: &str) -> Result<bool> {
+    let row = con.query_opt("SELECT 1 FROM pg_timezone_names WHERE name = $1", &[&time_zone])
+        .await
+        .map_err(DBQueryError)?;
+    Ok(row.is_some())
+}
+
 pub async fn create_task(db_pool: DBPool, task_request: TaskRequest,
                          user_id: i32) -> Result<Task> {
-    let con = get_conn(&db_pool).await?;
-    let query = format!("INSERT INTO {} ({}) VALUES ($1,$2,$3,$4) RETURNING {}",
+    if let (Some(start_at), Some(due_at)) = (task_request.start_at, task_request.due_at) {
+        if start_at > due_at {
+            return Err(InvalidScheduleError);
+        }
+    }
+    let mut con = get_conn(&db_pool).await?;
+    let transaction = con.transaction().await.map_err(DBQueryError)?;
+    if let Some(parent_id) = task_request.parent_id {
+        lock_task_hierarchy(&transaction, task_request.project_id).await?;
+        let (depth, _) = find_ancestry(&transaction, task_request.project_id, parent_id, None).await?;
+        if depth + 1 > MAX_TASK_DEPTH {
+            return Err(TaskDepthExceededError);
+        }
+    }
+    // new tasks start in the first status of the project workflow
+    let query = format!("INSERT INTO {} ({}) VALUES ($1,$2,$3,$4,$5,$6,$7,\
+    (SELECT id FROM {} WHERE project_id = $4 ORDER BY position LIMIT 1)) RETURNING {}",
                         TASKS_TABLE_NAME,
                         TASK_INSERT_FIELDS,
+                        STATUSES_TABLE_NAME,
                         TASK_SELECT_FIELDS
     );
-    let task_row = con.query_one(query.as_str(),
-                                 &[&task_request.title,
-                                     &task_request.description,
-                                     &user_id,
-                                     &task_request.project_id])
+    let task_row = transaction.query_one(query.as_str(),
+                                         &[&task_request.title,
+                                             &task_request.description,
+                                             &user_id,
+                                             &task_request.project_id,
+                                             &task_request.start_at,
+                                             &task_request.due_at,
+                                             &task_request.parent_id])
         .await
         .map_err(DBQueryError)?;
+    transaction.commit().await.map_err(DBQueryError)?;
     let task = row_to_task(&task_row);
     Ok(task)
 }
 
+/// Serializes changes of the task hierarchy within the project, so that concurrent moves cannot
+/// build a cycle or nest deeper than allowed.
+async fn lock_task_hierarchy(transaction: &Transaction<'_>, project_id: i32) -> Result<()> {
+    let query = format!("SELECT 1 FROM {} WHERE id = $1 FOR UPDATE", PROJECT_TABLE_NAME);
+    transaction.query_opt(query.as_str(), &[&project_id])
+        .await
+        .map_err(DBQueryError)?;
+    Ok(())
+}
+
+/// Checks that the parent is a live task of the project and returns its depth, with whether
+/// `task_id` is among its ancestors, which would make the parent part of the task's subtree.
+async fn find_ancestry(transaction: &Transaction<'_>,
+                       project_id: i32,
+                       parent_id: i32,
+                       task_id: Option<i32>) -> Result<(i32, bool)> {
+    let query = format!("SELECT 1 FROM {} WHERE id = $1 AND project_id = $2 AND deleted_at IS NULL",
+                        TASKS_TABLE_NAME);
+    transaction.query_opt(query.as_str(), &[&parent_id, &project_id])
+        .await
+        .map_err(DBQueryError)?
+        .ok_or(InvalidParentTaskError)?;
+    let query = format!("WITH RECURSIVE ancestors(id, parent_id, depth) AS (\
+    SELECT id, parent_id, 1 FROM {tasks} WHERE id = $1 \
+    UNION ALL SELECT t.id, t.parent_id, a.depth + 1 FROM {tasks} t JOIN ancestors a ON t.id = a.parent_id) \
+    SELECT max(depth), coalesce(bool_or(id = $2), false) FROM ancestors",
+                        tasks = TASKS_TABLE_NAME);
+    let row = transaction.query_one(query.as_str(), &[&parent_id, &task_id])
+        .await
+        .map_err(DBQueryError)?;
+    Ok((row.get(0), row.get(1)))
+}
+
+/// Moves the task with its subtasks below another task of the project, or to the top level.
+pub(crate) async fn update_task_parent(db_pool: &DBPool, task_id: i32, parent_id: Option<i32>) -> Result<Task> {
+    let mut con = get_conn(db_pool).await?;
+    let transaction = con.transaction().await.map_err(DBQueryError)?;
+    let query = format!("SELECT project_id FROM {} WHERE id = $1", TASKS_TABLE_NAME);
+    let project_id: i32 = transaction.query_opt(query.as_str(), &[&task_id])
+        .await
+        .map_err(DBQueryError)?
+        .ok_or(NotFoundError)?
+        .get(0);
+    lock_task_hierarchy(&transaction, project_id).await?;
+    if let Some(parent_id) = parent_id {
+        let (depth, cycle) = find_ancestry(&transaction, project_id, parent_id, Some(task_id)).await?;
+        if cycle {
+            return Err(InvalidParentTaskError);
+        }
+        let query = format!("WITH RECURSIVE subtree(id, depth) AS (SELECT $1::int, 1 \
+        UNION ALL SELECT t.id, s.depth + 1 FROM {} t JOIN subtree s ON t.parent_id = s.id) \
+        SELECT max(depth) FROM subtree",
+                            TASKS_TABLE_NAME);
+        let height: i32 = transaction.query_one(query.as_str(), &[&task_id])
+            .await
+            .map_err(DBQueryError)?
+            .get(0);
+        if depth + height > MAX_TASK_DEPTH {
+            return Err(TaskDepthExceededError);
+        }
+    }
+    let query = format!("UPDATE {} SET parent_id = $2 WHERE id = $1 RETURNING {}",
+                        TASKS_TABLE_NAME, TASK_SELECT_FIELDS);
+    let row = transaction.query_one(query.as_str(), &[&task_id, &parent_id])
+        .await
+        .map_err(DBQueryError)?;
+    transaction.commit().await.map_err(DBQueryError)?;
+    Ok(row_to_task(&row))
+}
+
+/// Loads the task with its subtasks, tasks in the trash are left out.
+pub(crate) async fn find_task_tree(db_pool: &DBPool, task_id: i32) -> Result<TaskTree> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("WITH RECURSIVE subtree(id) AS (SELECT $1::int \
+    UNION ALL SELECT t.id FROM {tasks} t JOIN subtree s ON t.parent_id = s.id WHERE t.deleted_at IS NULL) \
+    SELECT {fields}, coalesce((SELECT done FROM {statuses} WHERE id = {tasks}.status_id), false) \
+    FROM {tasks} WHERE id IN (SELECT id FROM subtree) ORDER BY id",
+                        tasks = TASKS_TABLE_NAME, statuses = STATUSES_TABLE_NAME, fields = TASK_SELECT_FIELDS);
+    let rows = con.query(query.as_str(), &[&task_id])
+        .await
+        .map_err(DBQueryError)?;
+    let mut subtasks: HashMap<i32, Vec<(Task, bool)>> = HashMap::new();
+    let mut root = None;
+    for row in rows.iter() {
+        let task = row_to_task(row);
+        let done: bool = row.get(11);
+        match task.parent_id {
+            _ if task.id == task_id => root = Some((task, done)),
+            Some(parent_id) => subtasks.entry(parent_id).or_default().push((task, done)),
+            None => {}
+        }
+    }
+    let (task, done) = root.ok_or(NotFoundError)?;
+    Ok(build_task_tree(task, done, &mut subtasks))
+}
+
+fn build_task_tree(task: Task, done: bool, subtasks: &mut HashMap<i32, Vec<(Task, bool)>>) -> TaskTree {
+    let children: Vec<TaskTree> = subtasks.remove(&task.id)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(subtask, subtask_done)| build_task_tree(subtask, subtask_done, subtasks))
+        .collect();
+    let subtask_count = children.iter()
+        .map(|child| 1 + child.subtask_count)
+        .sum();
+    let done_subtask_count = children.iter()
+        .map(|child| child.done as i64 + child.done_subtask_count)
+        .sum();
+    TaskTree {
+        task,
+        done,
+        subtask_count,
+        done_subtask_count,
+        subtasks: children,
+    }
+}
+
 
 pub async fn create_project(db_pool: DBPool,
                             project_request: ProjectRequest,
-                            user_id: i32) -> Result<Project> {
+                            user_id: i32,
+                            organization_id: i32) -> Result<Project> {
     let mut connection = get_conn(&db_pool).await?;
     let transaction = connection.transaction()
         .await
         .map_err(DBQueryError)?;
-    let query = format!("INSERT INTO {} ({}) VALUES ($1,$2) RETURNING {}",
+    let query = format!("INSERT INTO {} ({}) VALUES ($1,$2,$3) RETURNING {}",
                         PROJECT_TABLE_NAME,
                         PROJECT_INSERT_FIELDS,
                         PROJECT_SELECT_FIELDS
     );
     let project_row = transaction.query_one(query.as_str(),
                                             &[&project_request.title,
-                                                &project_request.description])
+                                                &project_request.description,
+                                                &organization_id])
         .await
         .map_err(DBQueryError)?;
     let project = row_to_project(&project_row);
-    let res = create_user_project_reference(&transaction, user_id, project.id)
+    let mut res = create_user_project_reference(&transaction, user_id, project.id, ProjectRole::Owner)
         .await;
+    if res.is_ok() {
+        res = create_default_workflow(&transaction, project.id).await;
+    }
     if let Err(_) = res {
         transaction.rollback()
             .await
@@ -248,93 +828,1862 @@ pub async fn create_project(db_pool: DBPool,
     Ok(project)
 }
 
-pub async fn create_user_project_reference(transaction: &Transaction<'_>, user_id: i32, project_id: i32) -> Result<()> {
-    let query = format!("INSERT INTO {} ({}) VALUES ($1,$2)",
+/// Loads the project with the number of its tasks, in total and per status.
+pub(crate) async fn find_project_details(db_pool: &DBPool, project_id: i32) -> Result<ProjectDetails> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("SELECT {} FROM {} WHERE id = $1", PROJECT_SELECT_FIELDS, PROJECT_TABLE_NAME);
+    let row = con.query_opt(query.as_str(), &[&project_id])
+        .await
+        .map_err(DBQueryError)?
+        .ok_or(NotFoundError)?;
+    let (task_count, status_counts) = count_tasks(&*con, project_id).await?;
+    Ok(ProjectDetails {
+        project: row_to_project(&row),
+        task_count,
+        status_counts,
+    })
+}
+
+async fn count_tasks<C: GenericClient>(con: &C, project_id: i32) -> Result<(i64, Vec<StatusCount>)> {
+    let query = format!("SELECT count(*) FROM {} WHERE project_id = $1 AND deleted_at IS NULL", TASKS_TABLE_NAME);
+    let task_count: i64 = con.query_one(query.as_str(), &[&project_id])
+        .await
+        .map_err(DBQueryError)?
+        .get(0);
+    let query = format!("SELECT s.name, s.done, count(t.id) FROM {} s \
+    LEFT JOIN {} t ON t.status_id = s.id AND t.deleted_at IS NULL \
+    WHERE s.project_id = $1 GROUP BY s.id ORDER BY s.position",
+                        STATUSES_TABLE_NAME, TASKS_TABLE_NAME);
+    let status_counts = con.query(query.as_str(), &[&project_id])
+        .await
+        .map_err(DBQueryError)?
+        .iter()
+        .map(|row| StatusCount {
+            status: row.get(0),
+            done: row.get(1),
+            count: row.get(2),
+        })
+        .collect();
+    Ok((task_count, status_counts))
+}
+
+pub(crate) async fn update_project(db_pool: &DBPool, project_id: i32,
+                                   request: ProjectUpdateRequest) -> Result<Project> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("UPDATE {} SET title = COALESCE($2, title), description = COALESCE($3, description) \
+    WHERE id = $1 RETURNING {}",
+                        PROJECT_TABLE_NAME, PROJECT_SELECT_FIELDS);
+    let row = con.query_opt(query.as_str(), &[&project_id, &request.title, &request.description])
+        .await
+        .map_err(DBQueryError)?
+        .ok_or(NotFoundError)?;
+    Ok(row_to_project(&row))
+}
+
+/// Archives or restores the project, archiving again keeps the original date.
+pub(crate) async fn set_project_archived(db_pool: &DBPool, project_id: i32, archived: bool) -> Result<Project> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("UPDATE {} SET archived_at = CASE WHEN $2 THEN COALESCE(archived_at, now()) END \
+    WHERE id = $1 RETURNING {}",
+                        PROJECT_TABLE_NAME, PROJECT_SELECT_FIELDS);
+    let row = con.query_opt(query.as_str(), &[&project_id, &archived])
+        .await
+        .map_err(DBQueryError)?
+        .ok_or(NotFoundError)?;
+    Ok(row_to_project(&row))
+}
+
+pub async fn create_user_project_reference(transaction: &Transaction<'_>, user_id: i32, project_id: i32,
+                                           role: ProjectRole) -> Result<()> {
+    let query = format!("INSERT INTO {} ({}) VALUES ($1,$2,$3)",
                         "users_projects",
-                        "user_id,project_id"
+                        "user_id,project_id,role"
     );
-    transaction.execute(query.as_str(), &[&user_id, &project_id])
+    transaction.execute(query.as_str(), &[&user_id, &project_id, &role.to_string()])
         .await
         .map_err(DBQueryError)?;
     Ok(())
 }
 
+async fn create_default_workflow(transaction: &Transaction<'_>, project_id: i32) -> Result<()> {
+    let request = WorkflowRequest {
+        statuses: DEFAULT_STATUSES.iter()
+            .map(|(name, done)| TaskStatusRequest { name: name.to_string(), done: *done })
+            .collect(),
+        transitions: DEFAULT_TRANSITIONS.iter()
+            .map(|(from, to)| StatusTransition { from: from.to_string(), to: to.to_string() })
+            .collect(),
+    };
+    save_workflow(transaction, project_id, &request).await?;
+    Ok(())
+}
 
-pub(crate) async fn delete_task(db_pool: DBPool, task_id: i32, user_id: i32) -> Result<u64> {
-    let con = get_conn(&db_pool).await?;
-    let query = format!("DELETE FROM {} \
-     WHERE id = $1", TASKS_TABLE_NAME);
-    con.execute(query.as_str(), &[&task_id])
+async fn is_project_member(con: &DBCon, project_id: i32, user_id: i32) -> Result<bool> {
+    let query = format!("SELECT 1 FROM {} WHERE project_id = $1 AND user_id = $2",
+                        USERS_PROJECTS_TABLE_NAME);
+    let row = con.query_opt(query.as_str(), &[&project_id, &user_id])
         .await
-        .map_err(DBQueryError)
+        .map_err(DBQueryError)?;
+    Ok(row.is_some())
 }
 
-pub(crate) async fn delete_project(db_pool: DBPool, project_id: i32, user_id: i32) -> Result<u64> {
-    let mut con = get_conn(&db_pool).await?;
+pub(crate) async fn find_workflow(db_pool: &DBPool, project_id: i32) -> Result<Workflow> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("SELECT {} FROM {} WHERE project_id = $1 ORDER BY position",
+                        STATUS_SELECT_FIELDS, STATUSES_TABLE_NAME);
+    let statuses = con.query(query.as_str(), &[&project_id])
+        .await
+        .map_err(DBQueryError)?
+        .iter()
+        .map(|row| row_to_status(row))
+        .collect::<Vec<TaskStatus>>();
+    let query = format!("SELECT f.name, t.name FROM {} tr \
+    JOIN {} f ON f.id = tr.from_status_id \
+    JOIN {} t ON t.id = tr.to_status_id \
+    WHERE f.project_id = $1 ORDER BY f.position, t.position",
+                        TRANSITIONS_TABLE_NAME, STATUSES_TABLE_NAME, STATUSES_TABLE_NAME);
+    let transitions = con.query(query.as_str(), &[&project_id])
+        .await
+        .map_err(DBQueryError)?
+        .iter()
+        .map(|row| StatusTransition { from: row.get(0), to: row.get(1) })
+        .collect::<Vec<StatusTransition>>();
+    Ok(Workflow { statuses, transitions })
+}
+
+pub(crate) async fn update_workflow(db_pool: &DBPool,
+                                   project_id: i32,
+                                   workflow_request: WorkflowRequest) -> Result<Workflow> {
+    let mut con = get_conn(db_pool).await?;
     let transaction = con.transaction().await.map_err(DBQueryError)?;
-    let query = format!("DELETE FROM {} \
-     WHERE user_id = $1 AND project_id= $2", USERS_PROJECTS_TABLE_NAME);
-    let res = transaction.execute(
-        query.as_str(),
-        &[&user_id, &project_id])
-        .await;
+    let res = save_workflow(&transaction, project_id, &workflow_request).await;
     if let Err(e) = res {
-        transaction.rollback().await
-            .map_err(DBQueryError)?;
-        return Err(DBQueryError(e));
+        transaction.rollback().await.map_err(DBQueryError)?;
+        return Err(e);
     }
-    let query = format!("DELETE FROM {} \
-     WHERE id = $1", PROJECT_TABLE_NAME);
-    let res = transaction.execute(query.as_str(), &[&project_id])
-        .await;
-    return match res {
-        Err(e) => {
-            println!("{:?}", e);
-            transaction.rollback().await
-                .map_err(DBQueryError)?;
-            Err(DBQueryError(e))
+    transaction.commit().await.map_err(DBQueryError)?;
+    find_workflow(db_pool, project_id).await
+}
+
+/// Upserts the statuses by name and replaces all transitions of the project.
+/// Statuses missing from the request are removed unless a task is still in them.
+async fn save_workflow(transaction: &Transaction<'_>,
+                       project_id: i32,
+                       workflow_request: &WorkflowRequest) -> Result<()> {
+    if workflow_request.statuses.is_empty() || !all_statuses_reachable(workflow_request) {
+        return Err(InvalidWorkflowError);
+    }
+    let upsert_query = format!("INSERT INTO {} ({}) VALUES ($1,$2,$3,$4) \
+    ON CONFLICT (project_id, name) DO UPDATE SET position = $3, done = $4 RETURNING {}",
+                               STATUSES_TABLE_NAME, STATUS_INSERT_FIELDS, STATUS_SELECT_FIELDS);
+    let mut statuses = Vec::new();
+    for (position, status_request) in workflow_request.statuses.iter().enumerate() {
+        if statuses.iter().any(|s: &TaskStatus| s.name == status_request.name) {
+            return Err(InvalidWorkflowError);
         }
-        Ok(row_count) => {
-            transaction.commit().await
-                .map_err(DBQueryError)?;
-            Ok(row_count)
+        let row = transaction.query_one(upsert_query.as_str(),
+                                        &[&project_id,
+                                            &status_request.name,
+                                            &(position as i32),
+                                            &status_request.done])
+            .await
+            .map_err(DBQueryError)?;
+        statuses.push(row_to_status(&row));
+    }
+    let status_ids = statuses.iter().map(|s| s.id).collect::<Vec<i32>>();
+
+    let query = format!("DELETE FROM {} WHERE from_status_id IN (SELECT id FROM {} WHERE project_id = $1)",
+                        TRANSITIONS_TABLE_NAME, STATUSES_TABLE_NAME);
+    transaction.execute(query.as_str(), &[&project_id])
+        .await
+        .map_err(DBQueryError)?;
+    let query = format!("SELECT 1 FROM {} WHERE project_id = $1 AND status_id <> ALL($2) LIMIT 1",
+                        TASKS_TABLE_NAME);
+    let status_in_use = transaction.query_opt(query.as_str(), &[&project_id, &status_ids])
+        .await
+        .map_err(DBQueryError)?;
+    if status_in_use.is_some() {
+        return Err(InvalidWorkflowError);
+    }
+    let query = format!("DELETE FROM {} WHERE project_id = $1 AND id <> ALL($2)",
+                        STATUSES_TABLE_NAME);
+    transaction.execute(query.as_str(), &[&project_id, &status_ids])
+        .await
+        .map_err(DBQueryError)?;
+
+    let query = format!("INSERT INTO {} (from_status_id, to_status_id) VALUES ($1,$2) ON CONFLICT DO NOTHING",
+                        TRANSITIONS_TABLE_NAME);
+    for transition in &workflow_request.transitions {
+        let from = statuses.iter().find(|s| s.name == transition.from);
+        let to = statuses.iter().find(|s| s.name == transition.to);
+        match (from, to) {
+            (Some(from), Some(to)) if from.id != to.id => {
+                transaction.execute(query.as_str(), &[&from.id, &to.id])
+                    .await
+                    .map_err(DBQueryError)?;
+            }
+            _ => return Err(InvalidWorkflowError)
         }
-    };
+    }
+    Ok(())
 }
 
-fn row_to_user(row: &Row) -> User {
-    let id: i32 = row.get(0);
-    let first_name: Option<String> = row.get(1);
-    let last_name: Option<String> = row.get(2);
-    let email: String = row.get(3);
-    let pwd: String = row.get(4);
-    let enabled: bool = row.get(5);
-    let created_at: DateTime<Utc> = row.get(6);
-    User {
-        id,
-        first_name,
-        last_name,
-        email,
-        pwd,
-        enabled,
-        created_at,
+/// Whether every status can be reached from the first one, which new tasks start in.
+fn all_statuses_reachable(workflow_request: &WorkflowRequest) -> bool {
+    let mut reached = vec![workflow_request.statuses[0].name.as_str()];
+    let mut next = 0;
+    while next < reached.len() {
+        let from = reached[next];
+        for transition in &workflow_request.transitions {
+            if transition.from == from && !reached.contains(&transition.to.as_str()) {
+                reached.push(&transition.to);
+            }
+        }
+        next += 1;
     }
+    workflow_request.statuses.iter()
+        .all(|status| reached.contains(&status.name.as_str()))
 }
 
-fn row_to_task(row: &Row) -> Task {
-    let id: i32 = row.get(0);
-    let title: String = row.get(1);
-    let description: Option<String> = row.get(2);
-    let user_id: i32 = row.get(3);
-    let project_id: i32 = row.get(4);
-    let created_at: DateTime<Utc> = row.get(5);
-    Task {
-        id,
-        title,
-        description,
-        user_id,
-        project_id,
-        created_at,
+pub(crate) async fn update_task_status(db_pool: &DBPool,
+                                       task_id: i32,
+                                       status: &str) -> Result<Task> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("SELECT status_id, project_id FROM {} WHERE id = $1",
+                        TASKS_TABLE_NAME);
+    let task_row = con.query_opt(query.as_str(), &[&task_id])
+        .await
+        .map_err(DBQueryError)?
+        .ok_or(NotFoundError)?;
+    let current_status_id: Option<i32> = task_row.get(0);
+    let project_id: i32 = task_row.get(1);
+
+    let query = format!("SELECT id FROM {} WHERE project_id = $1 AND name = $2",
+                        STATUSES_TABLE_NAME);
+    let target_status_id: i32 = con.query_opt(query.as_str(), &[&project_id, &status])
+        .await
+        .map_err(DBQueryError)?
+        .ok_or(IllegalStatusTransitionError)?
+        .get(0);
+
+    if let Some(current_status_id) = current_status_id {
+        if current_status_id != target_status_id {
+            let query = format!("SELECT 1 FROM {} WHERE from_status_id = $1 AND to_status_id = $2",
+                                TRANSITIONS_TABLE_NAME);
+            let transition = con.query_opt(query.as_str(), &[&current_status_id, &target_status_id])
+                .await
+                .map_err(DBQueryError)?;
+            if transition.is_none() {
+                return Err(IllegalStatusTransitionError);
+            }
+        }
+    }
+
+    // the status guard rejects a concurrent change that happened after the check above
+    let query = format!("UPDATE {} SET status_id = $1 \
+    WHERE id = $2 AND status_id IS NOT DISTINCT FROM $3 RETURNING {}",
+                        TASKS_TABLE_NAME, TASK_SELECT_FIELDS);
+    let task_row = con.query_opt(query.as_str(), &[&target_status_id, &task_id, &current_status_id])
+        .await
+        .map_err(DBQueryError)?
+        .ok_or(IllegalStatusTransitionError)?;
+    Ok(row_to_task(&task_row))
+}
+
+/// Applies the given changes to the task, see `move_tasks` for what a move keeps. A moved task
+/// takes its subtasks along.
+pub(crate) async fn update_task(db_pool: &DBPool,
+                                task_id: i32,
+                                request: TaskUpdateRequest) -> Result<Task> {
+    let mut con = get_conn(db_pool).await?;
+    let transaction = con.transaction().await.map_err(DBQueryError)?;
+    let query = format!("SELECT t.project_id, t.start_at, t.due_at, p.organization_id, t.description FROM {} t \
+    JOIN {} p ON p.id = t.project_id WHERE t.id = $1 FOR UPDATE OF t",
+                        TASKS_TABLE_NAME, PROJECT_TABLE_NAME);
+    let row = transaction.query_opt(query.as_str(), &[&task_id])
+        .await
+        .map_err(DBQueryError)?
+        .ok_or(NotFoundError)?;
+    let project_id: i32 = row.get(0);
+    let start_at: Option<DateTime<Utc>> = request.start_at.unwrap_or_else(|| row.get(1));
+    let due_at: Option<DateTime<Utc>> = request.due_at.unwrap_or_else(|| row.get(2));
+    let organization_id: i32 = row.get(3);
+    let description: Option<String> = request.description.unwrap_or_else(|| row.get(4));
+    if let (Some(start_at), Some(due_at)) = (start_at, due_at) {
+        if start_at > due_at {
+            return Err(InvalidScheduleError);
+        }
+    }
+
+    if let Some(target_id) = request.project_id.filter(|target_id| *target_id != project_id) {
+        // tasks stay within their organization
+        let query = format!("SELECT 1 FROM {} WHERE id = $1 AND organization_id = $2 AND deleted_at IS NULL",
+                            PROJECT_TABLE_NAME);
+        transaction.query_opt(query.as_str(), &[&target_id, &organization_id])
+            .await
+            .map_err(DBQueryError)?
+            .ok_or(NotFoundError)?;
+        // the subtasks come along, the task itself leaves its parent behind
+        lock_task_hierarchy(&transaction, project_id).await?;
+        move_tasks(&transaction, &format!("id IN ({})", SUBTREE_QUERY), task_id, target_id).await?;
+        let query = format!("UPDATE {} SET parent_id = NULL WHERE id = $1", TASKS_TABLE_NAME);
+        transaction.execute(query.as_str(), &[&task_id])
+            .await
+            .map_err(DBQueryError)?;
+    }
+
+    let query = format!("UPDATE {} SET title = COALESCE($2, title), description = $3, \
+    start_at = $4, due_at = $5 WHERE id = $1 RETURNING {}",
+                        TASKS_TABLE_NAME, TASK_SELECT_FIELDS);
+    let row = transaction.query_one(query.as_str(), &[&task_id, &request.title, &description,
+        &start_at, &due_at])
+        .await
+        .map_err(DBQueryError)?;
+    transaction.commit().await.map_err(DBQueryError)?;
+    Ok(row_to_task(&row))
+}
+
+/// Moves the tasks matching `condition`, with `$1` bound to `id`, to the target project.
+///
+/// A task keeps its status when the target workflow has one of the same name and starts over
+/// in the first status otherwise, assignees who are not members of the target are dropped.
+async fn move_tasks(transaction: &Transaction<'_>, condition: &str, id: i32, target_id: i32) -> Result<()> {
+    let query = format!("DELETE FROM {} WHERE task_id IN (SELECT id FROM {} WHERE {}) \
+    AND user_id NOT IN (SELECT user_id FROM {} WHERE project_id = $2)",
+                        TASK_ASSIGNEES_TABLE_NAME, TASKS_TABLE_NAME, condition, USERS_PROJECTS_TABLE_NAME);
+    transaction.execute(query.as_str(), &[&id, &target_id])
+        .await
+        .map_err(DBQueryError)?;
+    let query = format!("UPDATE {tasks} SET project_id = $2, status_id = COALESCE(\
+    (SELECT id FROM {statuses} WHERE project_id = $2 AND name = \
+    (SELECT name FROM {statuses} WHERE id = {tasks}.status_id)), \
+    (SELECT id FROM {statuses} WHERE project_id = $2 ORDER BY position LIMIT 1)) WHERE {condition}",
+                        tasks = TASKS_TABLE_NAME, statuses = STATUSES_TABLE_NAME, condition = condition);
+    transaction.execute(query.as_str(), &[&id, &target_id])
+        .await
+        .map_err(DBQueryError)?;
+    Ok(())
+}
+
+async fn find_task(con: &DBCon, task_id: i32) -> Result<Task> {
+    let query = format!("SELECT {} FROM {} WHERE id = $1", TASK_SELECT_FIELDS, TASKS_TABLE_NAME);
+    let row = con.query_opt(query.as_str(), &[&task_id])
+        .await
+        .map_err(DBQueryError)?
+        .ok_or(NotFoundError)?;
+    Ok(row_to_task(&row))
+}
+
+pub(crate) async fn assign_task(db_pool: &DBPool,
+                                task_id: i32,
+                                project_id: i32,
+                                assignee_id: i32) -> Result<Task> {
+    let con = get_conn(db_pool).await?;
+    if !is_project_member(&con, project_id, assignee_id).await? {
+        return Err(NotProjectMemberError);
+    }
+    let query = format!("INSERT INTO {} (task_id, user_id) VALUES ($1,$2) ON CONFLICT DO NOTHING",
+                        TASK_ASSIGNEES_TABLE_NAME);
+    con.execute(query.as_str(), &[&task_id, &assignee_id])
+        .await
+        .map_err(DBQueryError)?;
+    find_task(&con, task_id).await
+}
+
+pub(crate) async fn unassign_task(db_pool: &DBPool,
+                                  task_id: i32,
+                                  assignee_id: i32) -> Result<Task> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("DELETE FROM {} WHERE task_id = $1 AND user_id = $2",
+                        TASK_ASSIGNEES_TABLE_NAME);
+    con.execute(query.as_str(), &[&task_id, &assignee_id])
+        .await
+        .map_err(DBQueryError)?;
+    find_task(&con, task_id).await
+}
+
+/// Moves the task with its subtasks to the trash, see [`purge_trash`].
+pub(crate) async fn delete_task(db_pool: DBPool, task_id: i32) -> Result<u64> {
+    let con = get_conn(&db_pool).await?;
+    let query = format!("UPDATE {} SET deleted_at = now() WHERE id IN ({}) AND deleted_at IS NULL",
+                        TASKS_TABLE_NAME, SUBTREE_QUERY);
+    con.execute(query.as_str(), &[&task_id])
+        .await
+        .map_err(DBQueryError)
+}
+
+/// Moves the project to the trash, its tasks are refused, trashed with it or moved as the
+/// strategy says. Pending invitations are dropped, everything else waits for [`purge_trash`].
+pub(crate) async fn delete_project(db_pool: DBPool,
+                                   project_id: i32,
+                                   strategy: ProjectDeleteStrategy,
+                                   move_to: Option<i32>) -> Result<u64> {
+    let mut con = get_conn(&db_pool).await?;
+    let transaction = con.transaction().await.map_err(DBQueryError)?;
+    // the lock keeps tasks from being added while the existing ones are dealt with
+    let query = format!("SELECT organization_id FROM {} WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
+                        PROJECT_TABLE_NAME);
+    let organization_id: i32 = transaction.query_opt(query.as_str(), &[&project_id])
+        .await
+        .map_err(DBQueryError)?
+        .ok_or(NotFoundError)?
+        .get(0);
+    match strategy {
+        ProjectDeleteStrategy::Refuse => {
+            let (task_count, status_counts) = count_tasks(&transaction, project_id).await?;
+            if task_count > 0 {
+                return Err(ProjectNotEmptyError(task_count, status_counts));
+            }
+        }
+        ProjectDeleteStrategy::Cascade => {
+            // the tasks share the deletion time of the project, that is how they come back with it
+            let query = format!("UPDATE {} SET deleted_at = now() WHERE project_id = $1 AND deleted_at IS NULL",
+                                TASKS_TABLE_NAME);
+            transaction.execute(query.as_str(), &[&project_id])
+                .await
+                .map_err(DBQueryError)?;
+        }
+        ProjectDeleteStrategy::Move => {
+            let target_id = move_to.filter(|target_id| *target_id != project_id)
+                .ok_or(InvalidMoveTargetError)?;
+            // tasks stay within their organization
+            let query = format!("SELECT 1 FROM {} WHERE id = $1 AND organization_id = $2 AND deleted_at IS NULL",
+                                PROJECT_TABLE_NAME);
+            transaction.query_opt(query.as_str(), &[&target_id, &organization_id])
+                .await
+                .map_err(DBQueryError)?
+                .ok_or(InvalidMoveTargetError)?;
+            move_tasks(&transaction, "project_id = $1 AND deleted_at IS NULL", project_id, target_id).await?;
+        }
+    }
+    let query = format!("DELETE FROM {} WHERE project_id = $1", INVITATIONS_TABLE_NAME);
+    transaction.execute(query.as_str(), &[&project_id])
+        .await
+        .map_err(DBQueryError)?;
+    let query = format!("UPDATE {} SET deleted_at = now() WHERE id = $1", PROJECT_TABLE_NAME);
+    let row_count = transaction.execute(query.as_str(), &[&project_id])
+        .await
+        .map_err(DBQueryError)?;
+    transaction.commit().await.map_err(DBQueryError)?;
+    Ok(row_count)
+}
+
+/// Deleted projects of the user and deleted tasks of the projects they are members of, the most
+/// recently deleted first. Tasks deleted with their project or parent are only listed as those.
+pub(crate) async fn find_trash(db_pool: &DBPool, user_id: i32, organization: Option<i32>) -> Result<Vec<TrashItem>> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("SELECT 'project', p.id, p.title, p.deleted_at FROM {projects} p \
+    JOIN {members} up ON up.project_id = p.id \
+    WHERE up.user_id = $1 AND p.deleted_at IS NOT NULL AND {organization} \
+    UNION ALL \
+    SELECT 'task', t.id, t.title, t.deleted_at FROM {tasks} t JOIN {projects} p ON p.id = t.project_id \
+    JOIN {members} up ON up.project_id = p.id \
+    WHERE up.user_id = $1 AND t.deleted_at IS NOT NULL AND p.deleted_at IS NULL AND {organization} \
+    AND NOT EXISTS (SELECT 1 FROM {tasks} pt WHERE pt.id = t.parent_id AND pt.deleted_at = t.deleted_at) \
+    ORDER BY 4 DESC, 2",
+                        projects = PROJECT_TABLE_NAME, tasks = TASKS_TABLE_NAME, members = USERS_PROJECTS_TABLE_NAME,
+                        organization = organization_condition("p.organization_id", "$1", "$2"));
+    let rows = con.query(query.as_str(), &[&user_id, &organization])
+        .await
+        .map_err(DBQueryError)?;
+    Ok(rows.iter().map(row_to_trash_item).collect())
+}
+
+/// Takes the task out of the trash together with the subtasks that were deleted with it. A
+/// parent that is still in the trash is left, the task becomes a top-level task.
+pub(crate) async fn restore_task(db_pool: &DBPool, task_id: i32) -> Result<Task> {
+    let mut con = get_conn(db_pool).await?;
+    let transaction = con.transaction().await.map_err(DBQueryError)?;
+    let query = format!("SELECT deleted_at FROM {} WHERE id = $1 AND deleted_at IS NOT NULL FOR UPDATE",
+                        TASKS_TABLE_NAME);
+    let deleted_at: DateTime<Utc> = transaction.query_opt(query.as_str(), &[&task_id])
+        .await
+        .map_err(DBQueryError)?
+        .ok_or(NotFoundError)?
+        .get(0);
+    let query = format!("UPDATE {} SET deleted_at = NULL WHERE id IN ({}) AND deleted_at = $2",
+                        TASKS_TABLE_NAME, SUBTREE_QUERY);
+    transaction.execute(query.as_str(), &[&task_id, &deleted_at])
+        .await
+        .map_err(DBQueryError)?;
+    let query = format!("UPDATE {tasks} SET parent_id = NULL WHERE id = $1 \
+    AND parent_id IN (SELECT id FROM {tasks} WHERE deleted_at IS NOT NULL)",
+                        tasks = TASKS_TABLE_NAME);
+    transaction.execute(query.as_str(), &[&task_id])
+        .await
+        .map_err(DBQueryError)?;
+    let query = format!("SELECT {} FROM {} WHERE id = $1", TASK_SELECT_FIELDS, TASKS_TABLE_NAME);
+    let row = transaction.query_one(query.as_str(), &[&task_id])
+        .await
+        .map_err(DBQueryError)?;
+    transaction.commit().await.map_err(DBQueryError)?;
+    Ok(row_to_task(&row))
+}
+
+/// Takes the project out of the trash together with the tasks that were deleted with it.
+pub(crate) async fn restore_project(db_pool: &DBPool, project_id: i32) -> Result<Project> {
+    let mut con = get_conn(db_pool).await?;
+    let transaction = con.transaction().await.map_err(DBQueryError)?;
+    let query = format!("SELECT deleted_at FROM {} WHERE id = $1 AND deleted_at IS NOT NULL FOR UPDATE",
+                        PROJECT_TABLE_NAME);
+    let deleted_at: DateTime<Utc> = transaction.query_opt(query.as_str(), &[&project_id])
+        .await
+        .map_err(DBQueryError)?
+        .ok_or(NotFoundError)?
+        .get(0);
+    let query = format!("UPDATE {} SET deleted_at = NULL WHERE project_id = $1 AND deleted_at = $2",
+                        TASKS_TABLE_NAME);
+    transaction.execute(query.as_str(), &[&project_id, &deleted_at])
+        .await
+        .map_err(DBQueryError)?;
+    let query = format!("UPDATE {} SET deleted_at = NULL WHERE id = $1 RETURNING {}",
+                        PROJECT_TABLE_NAME, PROJECT_SELECT_FIELDS);
+    let row = transaction.query_one(query.as_str(), &[&project_id])
+        .await
+        .map_err(DBQueryError)?;
+    transaction.commit().await.map_err(DBQueryError)?;
+    Ok(row_to_project(&row))
+}
+
+/// Removes tasks and projects for good once they have been in the trash for longer than
+/// `retention`, a purged project takes all of its tasks, statuses and members along.
+pub(crate) async fn purge_trash(db_pool: &DBPool, retention: chrono::Duration) -> Result<u64> {
+    let mut con = get_conn(db_pool).await?;
+    let transaction = con.transaction().await.map_err(DBQueryError)?;
+    let purged_before = Utc::now() - retention;
+    let purged_projects = format!("SELECT id FROM {} WHERE deleted_at < $1", PROJECT_TABLE_NAME);
+    let purged_tasks = format!("SELECT id FROM {} WHERE deleted_at < $1 OR project_id IN ({})",
+                               TASKS_TABLE_NAME, purged_projects);
+    let queries = [
+        format!("DELETE FROM {} WHERE task_id IN ({})", TASK_ASSIGNEES_TABLE_NAME, purged_tasks),
+        format!("DELETE FROM {} WHERE id IN ({})", TASKS_TABLE_NAME, purged_tasks),
+        format!("DELETE FROM {} WHERE from_status_id IN (SELECT id FROM {} WHERE project_id IN ({}))",
+                TRANSITIONS_TABLE_NAME, STATUSES_TABLE_NAME, purged_projects),
+        format!("DELETE FROM {} WHERE project_id IN ({})", USERS_PROJECTS_TABLE_NAME, purged_projects),
+        format!("DELETE FROM {} WHERE project_id IN ({})", INVITATIONS_TABLE_NAME, purged_projects),
+        format!("DELETE FROM {} WHERE project_id IN ({})", STATUSES_TABLE_NAME, purged_projects),
+    ];
+    for query in queries.iter() {
+        transaction.execute(query.as_str(), &[&purged_before])
+            .await
+            .map_err(DBQueryError)?;
+    }
+    let query = format!("DELETE FROM {} WHERE deleted_at < $1", PROJECT_TABLE_NAME);
+    let row_count = transaction.execute(query.as_str(), &[&purged_before])
+        .await
+        .map_err(DBQueryError)?;
+    transaction.commit().await.map_err(DBQueryError)?;
+    Ok(row_count)
+}
+
+pub(crate) async fn find_project_members(db_pool: &DBPool, project_id: i32) -> Result<Vec<ProjectMember>> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("SELECT u.id, u.email, u.first_name, u.last_name, m.role FROM {} m \
+    JOIN {} u ON u.id = m.user_id WHERE m.project_id = $1 ORDER BY u.id",
+                        USERS_PROJECTS_TABLE_NAME, USERS_TABLE_NAME);
+    let rows = con.query(query.as_str(), &[&project_id])
+        .await
+        .map_err(DBQueryError)?;
+    Ok(rows.iter().map(row_to_project_member).collect())
+}
+
+/// Invites the owner of the email address into the project, an earlier invitation of the same
+/// address is replaced. The address does not need to belong to a registered user yet.
+pub(crate) async fn create_project_invitation(db_pool: &DBPool,
+                                              project_id: i32,
+                                              email: &str,
+                                              role: ProjectRole,
+                                              invited_by: i32,
+                                              lifetime: chrono::Duration) -> Result<(ProjectInvitation, String)> {
+    let mut con = get_conn(db_pool).await?;
+    let transaction = con.transaction().await.map_err(DBQueryError)?;
+    let query = format!("SELECT 1 FROM {} m JOIN {} u ON u.id = m.user_id \
+    WHERE m.project_id = $1 AND lower(u.email) = lower($2)",
+                        USERS_PROJECTS_TABLE_NAME, USERS_TABLE_NAME);
+    let member = transaction.query_opt(query.as_str(), &[&project_id, &email])
+        .await
+        .map_err(DBQueryError)?;
+    if member.is_some() {
+        return Err(AlreadyProjectMemberError);
+    }
+    let query = format!("DELETE FROM {} WHERE project_id = $1 AND lower(email) = lower($2)",
+                        INVITATIONS_TABLE_NAME);
+    transaction.execute(query.as_str(), &[&project_id, &email])
+        .await
+        .map_err(DBQueryError)?;
+    let token = auth::generate_token();
+    let expiry_date = Utc::now() + lifetime;
+    let query = format!("WITH i AS (INSERT INTO {} ({}) VALUES ($1,$2,$3,$4,$5,$6) RETURNING *) \
+    SELECT {} FROM i JOIN {} p ON p.id = i.project_id",
+                        INVITATIONS_TABLE_NAME, INVITATIONS_INSERT_FIELDS,
+                        INVITATIONS_SELECT_FIELDS, PROJECT_TABLE_NAME);
+    let row = transaction.query_one(query.as_str(),
+                                    &[&project_id, &email, &auth::hash_token(&token), &invited_by, &expiry_date,
+                                        &role.to_string()])
+        .await
+        .map_err(DBQueryError)?;
+    transaction.commit().await.map_err(DBQueryError)?;
+    Ok((row_to_project_invitation(&row), token))
+}
+
+/// Invitations that wait for the user, matched by the current email address.
+pub(crate) async fn find_user_invitations(db_pool: &DBPool, user_id: i32) -> Result<Vec<ProjectInvitation>> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("SELECT {} FROM {} i JOIN {} p ON p.id = i.project_id \
+    WHERE lower(i.email) = (SELECT lower(email) FROM {} WHERE id = $1) AND i.expiry_date > now() \
+    ORDER BY i.id",
+                        INVITATIONS_SELECT_FIELDS, INVITATIONS_TABLE_NAME, PROJECT_TABLE_NAME, USERS_TABLE_NAME);
+    let rows = con.query(query.as_str(), &[&user_id])
+        .await
+        .map_err(DBQueryError)?;
+    Ok(rows.iter().map(row_to_project_invitation).collect())
+}
+
+/// Makes the user a member of the project the invitation is for and consumes it.
+///
+/// Only the user the invitation was sent to may accept it, the token alone is not enough.
+pub(crate) async fn accept_project_invitation(db_pool: &DBPool, token: &str, user_id: i32) -> Result<Project> {
+    let mut con = get_conn(db_pool).await?;
+    let transaction = con.transaction().await.map_err(DBQueryError)?;
+    let query = format!("SELECT i.id, i.project_id, i.role FROM {} i \
+    WHERE i.token_hash = $1 AND i.expiry_date > now() \
+    AND lower(i.email) = (SELECT lower(email) FROM {} WHERE id = $2) FOR UPDATE",
+                        INVITATIONS_TABLE_NAME, USERS_TABLE_NAME);
+    let row = transaction.query_opt(query.as_str(), &[&auth::hash_token(token), &user_id])
+        .await
+        .map_err(DBQueryError)?
+        .ok_or(InvitationError)?;
+    let invitation_id: i32 = row.get(0);
+    let project_id: i32 = row.get(1);
+    let role: String = row.get(2);
+    let query = format!("DELETE FROM {} WHERE id = $1", INVITATIONS_TABLE_NAME);
+    transaction.execute(query.as_str(), &[&invitation_id])
+        .await
+        .map_err(DBQueryError)?;
+    let query = format!("INSERT INTO {} (user_id, project_id, role) VALUES ($1, $2, $3) \
+    ON CONFLICT (user_id, project_id) DO NOTHING",
+                        USERS_PROJECTS_TABLE_NAME);
+    transaction.execute(query.as_str(), &[&user_id, &project_id, &role])
+        .await
+        .map_err(DBQueryError)?;
+    // project members always belong to the organization of the project
+    let query = format!("INSERT INTO {} (organization_id, user_id) \
+    SELECT organization_id, $1 FROM {} WHERE id = $2 \
+    ON CONFLICT (organization_id, user_id) DO NOTHING",
+                        ORGANIZATION_MEMBERS_TABLE_NAME, PROJECT_TABLE_NAME);
+    transaction.execute(query.as_str(), &[&user_id, &project_id])
+        .await
+        .map_err(DBQueryError)?;
+    let query = format!("SELECT {} FROM {} WHERE id = $1", PROJECT_SELECT_FIELDS, PROJECT_TABLE_NAME);
+    let row = transaction.query_one(query.as_str(), &[&project_id])
+        .await
+        .map_err(DBQueryError)?;
+    transaction.commit().await.map_err(DBQueryError)?;
+    Ok(row_to_project(&row))
+}
+
+pub(crate) async fn decline_project_invitation(db_pool: &DBPool, token: &str) -> Result<()> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("DELETE FROM {} WHERE token_hash = $1", INVITATIONS_TABLE_NAME);
+    let deleted = con.execute(query.as_str(), &[&auth::hash_token(token)])
+        .await
+        .map_err(DBQueryError)?;
+    if deleted == 0 {
+        return Err(InvitationError);
+    }
+    Ok(())
+}
+
+/// Takes the user out of the project and off its tasks, the tasks they reported stay.
+///
+/// The last member cannot go, the project would be left without anybody who can see it, and
+/// neither can the last owner while others remain.
+pub(crate) async fn remove_project_member(db_pool: &DBPool, project_id: i32, user_id: i32) -> Result<()> {
+    let mut con = get_conn(db_pool).await?;
+    let transaction = con.transaction().await.map_err(DBQueryError)?;
+    let members = lock_project_members(&transaction, project_id).await?;
+    let role = members.iter()
+        .find(|(member, _)| *member == user_id)
+        .map(|(_, role)| *role)
+        .ok_or(NotFoundError)?;
+    if members.len() == 1 {
+        return Err(LastProjectMemberError);
+    }
+    if role == ProjectRole::Owner && count_owners(&members) == 1 {
+        return Err(LastProjectOwnerError);
+    }
+    let query = format!("DELETE FROM {} WHERE user_id = $1 AND task_id IN (SELECT id FROM {} WHERE project_id = $2)",
+                        TASK_ASSIGNEES_TABLE_NAME, TASKS_TABLE_NAME);
+    transaction.execute(query.as_str(), &[&user_id, &project_id])
+        .await
+        .map_err(DBQueryError)?;
+    let query = format!("DELETE FROM {} WHERE user_id = $1 AND project_id = $2", USERS_PROJECTS_TABLE_NAME);
+    transaction.execute(query.as_str(), &[&user_id, &project_id])
+        .await
+        .map_err(DBQueryError)?;
+    transaction.commit().await.map_err(DBQueryError)?;
+    Ok(())
+}
+
+/// Gives a member another role, the last owner keeps theirs until the project is transferred.
+pub(crate) async fn update_member_role(db_pool: &DBPool, project_id: i32, user_id: i32,
+                                       role: ProjectRole) -> Result<ProjectMember> {
+    let mut con = get_conn(db_pool).await?;
+    let transaction = con.transaction().await.map_err(DBQueryError)?;
+    let members = lock_project_members(&transaction, project_id).await?;
+    let current = members.iter()
+        .find(|(member, _)| *member == user_id)
+        .map(|(_, role)| *role)
+        .ok_or(NotFoundError)?;
+    if current == ProjectRole::Owner && role != ProjectRole::Owner && count_owners(&members) == 1 {
+        return Err(LastProjectOwnerError);
+    }
+    let member = set_member_role(&transaction, project_id, user_id, role).await?;
+    transaction.commit().await.map_err(DBQueryError)?;
+    Ok(member)
+}
+
+/// Makes `new_owner` the owner of the project, the previous owner stays on as a maintainer.
+pub(crate) async fn transfer_project(db_pool: &DBPool, project_id: i32, owner: i32,
+                                     new_owner: i32) -> Result<ProjectMember> {
+    let mut con = get_conn(db_pool).await?;
+    let transaction = con.transaction().await.map_err(DBQueryError)?;
+    let members = lock_project_members(&transaction, project_id).await?;
+    if !members.iter().any(|(member, _)| *member == new_owner) {
+        return Err(NotProjectMemberError);
+    }
+    let member = set_member_role(&transaction, project_id, new_owner, ProjectRole::Owner).await?;
+    if owner != new_owner {
+        set_member_role(&transaction, project_id, owner, ProjectRole::Maintainer).await?;
+    }
+    transaction.commit().await.map_err(DBQueryError)?;
+    Ok(member)
+}
+
+async fn lock_project_members(transaction: &Transaction<'_>, project_id: i32) -> Result<Vec<(i32, ProjectRole)>> {
+    let query = format!("SELECT user_id, role FROM {} WHERE project_id = $1 FOR UPDATE", USERS_PROJECTS_TABLE_NAME);
+    let rows = transaction.query(query.as_str(), &[&project_id])
+        .await
+        .map_err(DBQueryError)?;
+    Ok(rows.iter()
+        .map(|row| {
+            let role: String = row.get(1);
+            (row.get(0), ProjectRole::parse(&role).unwrap_or(ProjectRole::Viewer))
+        })
+        .collect())
+}
+
+fn count_owners(members: &[(i32, ProjectRole)]) -> usize {
+    members.iter()
+        .filter(|(_, role)| *role == ProjectRole::Owner)
+        .count()
+}
+
+async fn set_member_role(transaction: &Transaction<'_>, project_id: i32, user_id: i32,
+                         role: ProjectRole) -> Result<ProjectMember> {
+    let query = format!("WITH m AS (UPDATE {} SET role = $3 WHERE project_id = $1 AND user_id = $2 RETURNING *) \
+    SELECT u.id, u.email, u.first_name, u.last_name, m.role FROM m JOIN {} u ON u.id = m.user_id",
+                        USERS_PROJECTS_TABLE_NAME, USERS_TABLE_NAME);
+    let row = transaction.query_one(query.as_str(), &[&project_id, &user_id, &role.to_string()])
+        .await
+        .map_err(DBQueryError)?;
+    Ok(row_to_project_member(&row))
+}
+
+pub(crate) async fn delete_expired_project_invitations(db_pool: &DBPool) -> Result<u64> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("DELETE FROM {} WHERE expiry_date < now()", INVITATIONS_TABLE_NAME);
+    con.execute(query.as_str(), &[])
+        .await
+        .map_err(DBQueryError)
+}
+
+/// Loads what the authorization layer needs to know about the project, `None` if it does not
+/// exist in the organizations the request works in. Projects in the trash are only found with
+/// `trashed`, and then only they are.
+pub(crate) async fn find_project_access(db_pool: &DBPool,
+                                        project_id: i32,
+                                        user_id: i32,
+                                        organization: Option<i32>,
+                                        trashed: bool) -> Result<Option<ProjectAccess>> {
+    let con = get_conn(db_pool).await?;
+    let deleted = if trashed { "IS NOT NULL" } else { "IS NULL" };
+    let query = format!("SELECT p.organization_id, (SELECT role FROM {} WHERE project_id = p.id AND user_id = $2) \
+    FROM {} p WHERE p.id = $1 AND p.deleted_at {} AND {}",
+                        USERS_PROJECTS_TABLE_NAME, PROJECT_TABLE_NAME, deleted,
+                        organization_condition("p.organization_id", "$2", "$3"));
+    let row = con.query_opt(query.as_str(), &[&project_id, &user_id, &organization])
+        .await
+        .map_err(DBQueryError)?;
+    Ok(row.map(|row| ProjectAccess {
+        organization_id: row.get(0),
+        role: row_to_project_role(&row, 1),
+    }))
+}
+
+/// Loads what the authorization layer needs to know about the task, see [`find_project_access`].
+/// Tasks of a project in the trash are never found, they come back with the project.
+pub(crate) async fn find_task_access(db_pool: &DBPool,
+                                     task_id: i32,
+                                     user_id: i32,
+                                     organization: Option<i32>,
+                                     trashed: bool) -> Result<Option<TaskAccess>> {
+    let con = get_conn(db_pool).await?;
+    let deleted = if trashed { "IS NOT NULL" } else { "IS NULL" };
+    let query = format!("SELECT t.project_id, t.reporter_id, \
+    (SELECT role FROM {} WHERE project_id = t.project_id AND user_id = $2) \
+    FROM {} t JOIN {} p ON p.id = t.project_id WHERE t.id = $1 AND t.deleted_at {} AND p.deleted_at IS NULL AND {}",
+                        USERS_PROJECTS_TABLE_NAME, TASKS_TABLE_NAME, PROJECT_TABLE_NAME, deleted,
+                        organization_condition("p.organization_id", "$2", "$3"));
+    let row = con.query_opt(query.as_str(), &[&task_id, &user_id, &organization])
+        .await
+        .map_err(DBQueryError)?;
+    Ok(row.map(|row| TaskAccess {
+        project_id: row.get(0),
+        reporter_id: row.get(1),
+        role: row_to_project_role(&row, 2),
+    }))
+}
+
+/// Resolves the organization new projects go to: the requested one, otherwise the one the user
+/// joined first. `NotFoundError` if the user is not a member.
+pub(crate) async fn find_current_organization(db_pool: &DBPool,
+                                              user_id: i32,
+                                              organization: Option<i32>) -> Result<i32> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("SELECT organization_id FROM {} WHERE user_id = $1 AND ($2::int IS NULL OR organization_id = $2) \
+    ORDER BY created_at, organization_id LIMIT 1",
+                        ORGANIZATION_MEMBERS_TABLE_NAME);
+    let row = con.query_opt(query.as_str(), &[&user_id, &organization])
+        .await
+        .map_err(DBQueryError)?
+        .ok_or(NotFoundError)?;
+    Ok(row.get(0))
+}
+
+/// The role of the user in the organization, `None` if they are not a member.
+pub(crate) async fn find_organization_role(db_pool: &DBPool,
+                                           organization_id: i32,
+                                           user_id: i32) -> Result<Option<OrganizationRole>> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("SELECT role FROM {} WHERE organization_id = $1 AND user_id = $2",
+                        ORGANIZATION_MEMBERS_TABLE_NAME);
+    let row = con.query_opt(query.as_str(), &[&organization_id, &user_id])
+        .await
+        .map_err(DBQueryError)?;
+    Ok(row.and_then(|row| {
+        let role: String = row.get(0);
+        OrganizationRole::parse(&role)
+    }))
+}
+
+pub(crate) async fn find_organizations(db_pool: &DBPool, user_id: i32) -> Result<Vec<Organization>> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("SELECT {} FROM {} o JOIN {} m ON m.organization_id = o.id \
+    WHERE m.user_id = $1 ORDER BY o.id",
+                        ORGANIZATIONS_SELECT_FIELDS, ORGANIZATIONS_TABLE_NAME, ORGANIZATION_MEMBERS_TABLE_NAME);
+    let rows = con.query(query.as_str(), &[&user_id])
+        .await
+        .map_err(DBQueryError)?;
+    Ok(rows.iter().map(|row| row_to_organization(row)).collect())
+}
+
+/// Creates an organization with the user as its admin.
+pub(crate) async fn create_organization(db_pool: &DBPool, user_id: i32, name: &str) -> Result<Organization> {
+    let mut con = get_conn(db_pool).await?;
+    let transaction = con.transaction().await.map_err(DBQueryError)?;
+    let organization = insert_organization(&transaction, user_id, name).await?;
+    transaction.commit().await.map_err(DBQueryError)?;
+    Ok(organization)
+}
+
+/// Every new user starts out in an organization of their own, named after them.
+async fn create_personal_organization(transaction: &Transaction<'_>, user: &User) -> Result<Organization> {
+    insert_organization(transaction, user.id, &user.email).await
+}
+
+async fn insert_organization(transaction: &Transaction<'_>, user_id: i32, name: &str) -> Result<Organization> {
+    let query = format!("WITH o AS (INSERT INTO {} (name) VALUES ($2) RETURNING *), \
+    m AS (INSERT INTO {} (organization_id, user_id, role) SELECT id, $1, 'admin' FROM o RETURNING *) \
+    SELECT {} FROM o JOIN m ON m.organization_id = o.id",
+                        ORGANIZATIONS_TABLE_NAME, ORGANIZATION_MEMBERS_TABLE_NAME, ORGANIZATIONS_SELECT_FIELDS);
+    let row = transaction.query_one(query.as_str(), &[&user_id, &name])
+        .await
+        .map_err(DBQueryError)?;
+    Ok(row_to_organization(&row))
+}
+
+pub(crate) async fn find_organization_members(db_pool: &DBPool, organization_id: i32) -> Result<Vec<OrganizationMember>> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("SELECT u.id, u.email, u.first_name, u.last_name, m.role FROM {} m \
+    JOIN {} u ON u.id = m.user_id WHERE m.organization_id = $1 ORDER BY u.id",
+                        ORGANIZATION_MEMBERS_TABLE_NAME, USERS_TABLE_NAME);
+    let rows = con.query(query.as_str(), &[&organization_id])
+        .await
+        .map_err(DBQueryError)?;
+    Ok(rows.iter().map(|row| row_to_organization_member(row)).collect())
+}
+
+/// Adds a registered user to the organization.
+pub(crate) async fn add_organization_member(db_pool: &DBPool, organization_id: i32, email: &str,
+                                            role: OrganizationRole) -> Result<OrganizationMember> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("SELECT id FROM {} WHERE lower(email) = lower($1)", USERS_TABLE_NAME);
+    let user_id: i32 = con.query_opt(query.as_str(), &[&email])
+        .await
+        .map_err(DBQueryError)?
+        .ok_or(NotFoundError)?
+        .get(0);
+    let query = format!("WITH m AS (INSERT INTO {} (organization_id, user_id, role) VALUES ($1,$2,$3) \
+    ON CONFLICT (organization_id, user_id) DO NOTHING RETURNING *) \
+    SELECT u.id, u.email, u.first_name, u.last_name, m.role FROM m JOIN {} u ON u.id = m.user_id",
+                        ORGANIZATION_MEMBERS_TABLE_NAME, USERS_TABLE_NAME);
+    let row = con.query_opt(query.as_str(), &[&organization_id, &user_id, &role.to_string()])
+        .await
+        .map_err(DBQueryError)?
+        .ok_or(AlreadyOrganizationMemberError)?;
+    Ok(row_to_organization_member(&row))
+}
+
+/// Gives a member another role, the last admin keeps theirs.
+pub(crate) async fn update_organization_member_role(db_pool: &DBPool, organization_id: i32, user_id: i32,
+                                                    role: OrganizationRole) -> Result<OrganizationMember> {
+    let mut con = get_conn(db_pool).await?;
+    let transaction = con.transaction().await.map_err(DBQueryError)?;
+    let members = lock_organization_members(&transaction, organization_id).await?;
+    let current = members.iter()
+        .find(|(member, _)| *member == user_id)
+        .map(|(_, role)| *role)
+        .ok_or(NotFoundError)?;
+    if current == OrganizationRole::Admin && role != OrganizationRole::Admin && count_admins(&members) == 1 {
+        return Err(LastOrganizationAdminError);
+    }
+    let query = format!("WITH m AS (UPDATE {} SET role = $3 WHERE organization_id = $1 AND user_id = $2 RETURNING *) \
+    SELECT u.id, u.email, u.first_name, u.last_name, m.role FROM m JOIN {} u ON u.id = m.user_id",
+                        ORGANIZATION_MEMBERS_TABLE_NAME, USERS_TABLE_NAME);
+    let row = transaction.query_one(query.as_str(), &[&organization_id, &user_id, &role.to_string()])
+        .await
+        .map_err(DBQueryError)?;
+    transaction.commit().await.map_err(DBQueryError)?;
+    Ok(row_to_organization_member(&row))
+}
+
+/// Takes the user out of the organization and out of all of its projects.
+///
+/// Refused while the user is the last admin of the organization, or the last member or owner
+/// of one of its projects.
+pub(crate) async fn remove_organization_member(db_pool: &DBPool, organization_id: i32, user_id: i32) -> Result<()> {
+    let mut con = get_conn(db_pool).await?;
+    let transaction = con.transaction().await.map_err(DBQueryError)?;
+    let members = lock_organization_members(&transaction, organization_id).await?;
+    let role = members.iter()
+        .find(|(member, _)| *member == user_id)
+        .map(|(_, role)| *role)
+        .ok_or(NotFoundError)?;
+    if role == OrganizationRole::Admin && count_admins(&members) == 1 {
+        return Err(LastOrganizationAdminError);
+    }
+    let projects = format!("SELECT id FROM {} WHERE organization_id = $2", PROJECT_TABLE_NAME);
+    let query = format!("SELECT m.project_id, m.role, \
+    EXISTS (SELECT 1 FROM {table} WHERE project_id = m.project_id AND user_id <> $1), \
+    EXISTS (SELECT 1 FROM {table} WHERE project_id = m.project_id AND user_id <> $1 AND role = 'owner') \
+    FROM {table} m WHERE m.user_id = $1 AND m.project_id IN ({projects}) FOR UPDATE",
+                        table = USERS_PROJECTS_TABLE_NAME, projects = projects);
+    let rows = transaction.query(query.as_str(), &[&user_id, &organization_id])
+        .await
+        .map_err(DBQueryError)?;
+    for row in rows.iter() {
+        let role: String = row.get(1);
+        let others: bool = row.get(2);
+        let other_owners: bool = row.get(3);
+        if !others {
+            return Err(LastProjectMemberError);
+        }
+        if role == ProjectRole::Owner.to_string() && !other_owners {
+            return Err(LastProjectOwnerError);
+        }
+    }
+    let queries = [
+        format!("DELETE FROM {} WHERE user_id = $1 AND task_id IN (SELECT id FROM {} WHERE project_id IN ({}))",
+                TASK_ASSIGNEES_TABLE_NAME, TASKS_TABLE_NAME, projects),
+        format!("DELETE FROM {} WHERE user_id = $1 AND project_id IN ({})", USERS_PROJECTS_TABLE_NAME, projects),
+        format!("DELETE FROM {} WHERE user_id = $1 AND organization_id = $2", ORGANIZATION_MEMBERS_TABLE_NAME),
+    ];
+    for query in queries.iter() {
+        transaction.execute(query.as_str(), &[&user_id, &organization_id])
+            .await
+            .map_err(DBQueryError)?;
+    }
+    transaction.commit().await.map_err(DBQueryError)?;
+    Ok(())
+}
+
+async fn lock_organization_members(transaction: &Transaction<'_>,
+                                   organization_id: i32) -> Result<Vec<(i32, OrganizationRole)>> {
+    let query = format!("SELECT user_id, role FROM {} WHERE organization_id = $1 FOR UPDATE",
+                        ORGANIZATION_MEMBERS_TABLE_NAME);
+    let rows = transaction.query(query.as_str(), &[&organization_id])
+        .await
+        .map_err(DBQueryError)?;
+    Ok(rows.iter()
+        .map(|row| {
+            let role: String = row.get(1);
+            (row.get(0), OrganizationRole::parse(&role).unwrap_or(OrganizationRole::Member))
+        })
+        .collect())
+}
+
+fn count_admins(members: &[(i32, OrganizationRole)]) -> usize {
+    members.iter()
+        .filter(|(_, role)| *role == OrganizationRole::Admin)
+        .count()
+}
+
+/// Issues a refresh token, a new login starts a new family that all its rotations share.
+pub(crate) async fn create_refresh_token(db_pool: &DBPool,
+                                         user_id: i32,
+                                         lifetime: chrono::Duration) -> Result<String> {
+    let con = get_conn(db_pool).await?;
+    let family_id = Uuid::new_v4().to_string();
+    insert_refresh_token(&*con, user_id, &family_id, lifetime).await
+}
+
+async fn insert_refresh_token<C: GenericClient>(con: &C,
+                              user_id: i32,
+                              family_id: &str,
+                              lifetime: chrono::Duration) -> Result<String> {
+    let token = auth::generate_token();
+    let query = format!("INSERT INTO {} ({}) VALUES ($1,$2,$3,$4)",
+                        REFRESH_TOKENS_TABLE_NAME, REFRESH_TOKENS_INSERT_FIELDS);
+    let expiry_date = Utc::now() + lifetime;
+    con.execute(query.as_str(), &[&user_id, &auth::hash_token(&token), &family_id, &expiry_date])
+        .await
+        .map_err(DBQueryError)?;
+    Ok(token)
+}
+
+/// Exchanges a refresh token for a new one of the same family and returns it with the user id.
+///
+/// A token that was already exchanged means it leaked, the whole family is revoked then.
+pub(crate) async fn rotate_refresh_token(db_pool: &DBPool,
+                                         token: &str,
+                                         lifetime: chrono::Duration) -> Result<(i32, String)> {
+    let mut con = get_conn(db_pool).await?;
+    let transaction = con.transaction().await.map_err(DBQueryError)?;
+    let query = format!("SELECT {} FROM {} WHERE token_hash = $1 FOR UPDATE",
+                        REFRESH_TOKENS_SELECT_FIELDS, REFRESH_TOKENS_TABLE_NAME);
+    let row = transaction.query_opt(query.as_str(), &[&auth::hash_token(token)])
+        .await
+        .map_err(DBQueryError)?;
+    let refresh_token = match row {
+        Some(row) => row_to_refresh_token(&row),
+        None => return Err(RefreshTokenError),
+    };
+    if refresh_token.revoked {
+        let query = format!("UPDATE {} SET revoked = true WHERE family_id = $1",
+                            REFRESH_TOKENS_TABLE_NAME);
+        transaction.execute(query.as_str(), &[&refresh_token.family_id])
+            .await
+            .map_err(DBQueryError)?;
+        transaction.commit().await.map_err(DBQueryError)?;
+        return Err(RefreshTokenError);
+    }
+    if refresh_token.expiry_date < Utc::now() {
+        transaction.rollback().await.map_err(DBQueryError)?;
+        return Err(RefreshTokenError);
+    }
+    let query = format!("UPDATE {} SET revoked = true WHERE id = $1", REFRESH_TOKENS_TABLE_NAME);
+    transaction.execute(query.as_str(), &[&refresh_token.id])
+        .await
+        .map_err(DBQueryError)?;
+    let new_token = insert_refresh_token(&transaction, refresh_token.user_id,
+                                         &refresh_token.family_id, lifetime).await?;
+    transaction.commit().await.map_err(DBQueryError)?;
+    Ok((refresh_token.user_id, new_token))
+}
+
+/// Revokes the family of the given refresh token, unknown tokens are ignored.
+pub(crate) async fn revoke_refresh_token(db_pool: &DBPool, token: &str) -> Result<u64> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("UPDATE {table} SET revoked = true WHERE family_id = \
+    (SELECT family_id FROM {table} WHERE token_hash = $1)",
+                        table = REFRESH_TOKENS_TABLE_NAME);
+    con.execute(query.as_str(), &[&auth::hash_token(token)])
+        .await
+        .map_err(DBQueryError)
+}
+
+/// Issues a reset token for the user with the given email, replacing earlier ones.
+///
+/// Returns `None` when there is no such user, so callers can answer the same way in both cases.
+pub(crate) async fn create_password_reset_token(db_pool: &DBPool,
+                                                email: &str,
+                                                lifetime: chrono::Duration) -> Result<Option<(User, String, DateTime<Utc>)>> {
+    let mut con = get_conn(db_pool).await?;
+    let transaction = con.transaction().await.map_err(DBQueryError)?;
+    let query = format!("SELECT {} FROM {} WHERE email = $1 FOR UPDATE",
+                        USER_SELECT_FIELDS, USERS_TABLE_NAME);
+    let row = transaction.query_opt(query.as_str(), &[&email])
+        .await
+        .map_err(DBQueryError)?;
+    let user = match row {
+        Some(row) => row_to_user(&row),
+        None => return Ok(None),
+    };
+    let (token, expiry_date) = replace_password_reset_token(&transaction, user.id, lifetime).await?;
+    transaction.commit().await.map_err(DBQueryError)?;
+    Ok(Some((user, token, expiry_date)))
+}
+
+/// Makes the user choose a new password: the current one stops working, every session is
+/// revoked and a reset token is issued for the notification.
+pub(crate) async fn force_password_reset(db_pool: &DBPool,
+                                         user_id: i32,
+                                         lifetime: chrono::Duration) -> Result<(User, String, DateTime<Utc>)> {
+    let encrypted_pwd = bcrypt::hash(auth::generate_token(), DEFAULT_COST)
+        .map_err(EncryptPasswordError)?;
+    let mut con = get_conn(db_pool).await?;
+    let transaction = con.transaction().await.map_err(DBQueryError)?;
+    let user = lock_user(&transaction, user_id).await?;
+    let query = format!("UPDATE {} SET pwd = $1, token_version = token_version + 1 WHERE id = $2", USERS_TABLE_NAME);
+    transaction.execute(query.as_str(), &[&encrypted_pwd, &user_id])
+        .await
+        .map_err(DBQueryError)?;
+    let query = format!("UPDATE {} SET revoked = true WHERE user_id = $1", REFRESH_TOKENS_TABLE_NAME);
+    transaction.execute(query.as_str(), &[&user_id])
+        .await
+        .map_err(DBQueryError)?;
+    let (token, expiry_date) = replace_password_reset_token(&transaction, user_id, lifetime).await?;
+    transaction.commit().await.map_err(DBQueryError)?;
+    Ok((user, token, expiry_date))
+}
+
+async fn replace_password_reset_token(transaction: &Transaction<'_>,
+                                      user_id: i32,
+                                      lifetime: chrono::Duration) -> Result<(String, DateTime<Utc>)> {
+    let query = format!("DELETE FROM {} WHERE user_id = $1", RESET_TOKENS_TABLE_NAME);
+    transaction.execute(query.as_str(), &[&user_id])
+        .await
+        .map_err(DBQueryError)?;
+    let token = auth::generate_token();
+    let expiry_date = Utc::now() + lifetime;
+    let query = format!("INSERT INTO {} ({}) VALUES ($1,$2,$3)",
+                        RESET_TOKENS_TABLE_NAME, RESET_TOKENS_INSERT_FIELDS);
+    transaction.execute(query.as_str(), &[&user_id, &auth::hash_token(&token), &expiry_date])
+        .await
+        .map_err(DBQueryError)?;
+    Ok((token, expiry_date))
+}
+
+/// Sets a new password with a reset token and signs the user out everywhere.
+///
+/// The token is consumed even when it turns out to be expired.
+pub(crate) async fn reset_password(db_pool: &DBPool, token: &str, pwd: &str) -> Result<()> {
+    let encrypted_pwd = bcrypt::hash(pwd, DEFAULT_COST)
+        .map_err(EncryptPasswordError)?;
+    let mut con = get_conn(db_pool).await?;
+    let transaction = con.transaction().await.map_err(DBQueryError)?;
+    let query = format!("SELECT {} FROM {} WHERE token_hash = $1 FOR UPDATE",
+                        RESET_TOKENS_SELECT_FIELDS, RESET_TOKENS_TABLE_NAME);
+    let row = transaction.query_opt(query.as_str(), &[&auth::hash_token(token)])
+        .await
+        .map_err(DBQueryError)?;
+    let reset_token = match row {
+        Some(row) => row_to_password_reset_token(&row),
+        None => return Err(PasswordResetTokenError),
+    };
+    let query = format!("DELETE FROM {} WHERE id = $1", RESET_TOKENS_TABLE_NAME);
+    transaction.execute(query.as_str(), &[&reset_token.id])
+        .await
+        .map_err(DBQueryError)?;
+    if reset_token.expiry_date < Utc::now() {
+        transaction.commit().await.map_err(DBQueryError)?;
+        return Err(PasswordResetTokenError);
+    }
+    let query = format!("UPDATE {} SET pwd = $1, token_version = token_version + 1 WHERE id = $2", USERS_TABLE_NAME);
+    transaction.execute(query.as_str(), &[&encrypted_pwd, &reset_token.user_id])
+        .await
+        .map_err(DBQueryError)?;
+    let query = format!("UPDATE {} SET revoked = true WHERE user_id = $1", REFRESH_TOKENS_TABLE_NAME);
+    transaction.execute(query.as_str(), &[&reset_token.user_id])
+        .await
+        .map_err(DBQueryError)?;
+    transaction.commit().await.map_err(DBQueryError)?;
+    Ok(())
+}
+
+pub(crate) async fn delete_expired_password_reset_tokens(db_pool: &DBPool) -> Result<u64> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("DELETE FROM {} WHERE expiry_date < now()", RESET_TOKENS_TABLE_NAME);
+    con.execute(query.as_str(), &[])
+        .await
+        .map_err(DBQueryError)
+}
+
+pub(crate) async fn update_profile(db_pool: &DBPool, user_id: i32, request: ProfileUpdateRequest) -> Result<User> {
+    let con = get_conn(db_pool).await?;
+    if let Some(time_zone) = &request.time_zone {
+        if !is_known_time_zone(&con, time_zone).await? {
+            return Err(InvalidTimeZoneError);
+        }
+    }
+    let query = format!("UPDATE {} SET first_name = COALESCE($2, first_name), \
+    last_name = COALESCE($3, last_name), time_zone = COALESCE($4, time_zone) WHERE id = $1 RETURNING {}",
+                        USERS_TABLE_NAME, USER_SELECT_FIELDS);
+    let row = con.query_opt(query.as_str(), &[&user_id, &request.first_name, &request.last_name, &request.time_zone])
+        .await
+        .map_err(DBQueryError)?
+        .ok_or(NotFoundError)?;
+    Ok(row_to_user(&row))
+}
+
+/// Stores a new password and ends every session of the user, access and refresh tokens alike.
+pub(crate) async fn update_password(db_pool: &DBPool, user_id: i32, pwd: &str) -> Result<User> {
+    let encrypted_pwd = bcrypt::hash(pwd, DEFAULT_COST)
+        .map_err(EncryptPasswordError)?;
+    let mut con = get_conn(db_pool).await?;
+    let transaction = con.transaction().await.map_err(DBQueryError)?;
+    let query = format!("UPDATE {} SET pwd = $1, token_version = token_version + 1 WHERE id = $2 RETURNING {}",
+                        USERS_TABLE_NAME, USER_SELECT_FIELDS);
+    let row = transaction.query_opt(query.as_str(), &[&encrypted_pwd, &user_id])
+        .await
+        .map_err(DBQueryError)?
+        .ok_or(NotFoundError)?;
+    let query = format!("UPDATE {} SET revoked = true WHERE user_id = $1", REFRESH_TOKENS_TABLE_NAME);
+    transaction.execute(query.as_str(), &[&user_id])
+        .await
+        .map_err(DBQueryError)?;
+    transaction.commit().await.map_err(DBQueryError)?;
+    Ok(row_to_user(&row))
+}
+
+/// Remembers the requested address until the user proves to own it, replacing earlier requests.
+pub(crate) async fn create_email_change_token(db_pool: &DBPool,
+                                              user_id: i32,
+                                              new_email: &str,
+                                              lifetime: chrono::Duration) -> Result<(String, DateTime<Utc>)> {
+    let mut con = get_conn(db_pool).await?;
+    let transaction = con.transaction().await.map_err(DBQueryError)?;
+    let query = format!("SELECT 1 FROM {} WHERE email = $1", USERS_TABLE_NAME);
+    let taken = transaction.query_opt(query.as_str(), &[&new_email])
+        .await
+        .map_err(DBQueryError)?;
+    if taken.is_some() {
+        return Err(EmailTakenError);
+    }
+    let query = format!("DELETE FROM {} WHERE user_id = $1", EMAIL_TOKENS_TABLE_NAME);
+    transaction.execute(query.as_str(), &[&user_id])
+        .await
+        .map_err(DBQueryError)?;
+    let token = auth::generate_token();
+    let expiry_date = Utc::now() + lifetime;
+    let query = format!("INSERT INTO {} ({}) VALUES ($1,$2,$3,$4)",
+                        EMAIL_TOKENS_TABLE_NAME, EMAIL_TOKENS_INSERT_FIELDS);
+    transaction.execute(query.as_str(), &[&user_id, &new_email, &auth::hash_token(&token), &expiry_date])
+        .await
+        .map_err(DBQueryError)?;
+    transaction.commit().await.map_err(DBQueryError)?;
+    Ok((token, expiry_date))
+}
+
+/// Swaps in the address the token was issued for and consumes the token.
+pub(crate) async fn confirm_email_change(db_pool: &DBPool, token: &str) -> Result<User> {
+    let mut con = get_conn(db_pool).await?;
+    let transaction = con.transaction().await.map_err(DBQueryError)?;
+    let query = format!("SELECT {} FROM {} WHERE token_hash = $1 FOR UPDATE",
+                        EMAIL_TOKENS_SELECT_FIELDS, EMAIL_TOKENS_TABLE_NAME);
+    let row = transaction.query_opt(query.as_str(), &[&auth::hash_token(token)])
+        .await
+        .map_err(DBQueryError)?;
+    let email_token = match row {
+        Some(row) => row_to_email_change_token(&row),
+        None => return Err(EmailChangeTokenError),
+    };
+    let query = format!("DELETE FROM {} WHERE id = $1", EMAIL_TOKENS_TABLE_NAME);
+    transaction.execute(query.as_str(), &[&email_token.id])
+        .await
+        .map_err(DBQueryError)?;
+    if email_token.expiry_date < Utc::now() {
+        transaction.commit().await.map_err(DBQueryError)?;
+        return Err(EmailChangeTokenError);
+    }
+    let query = format!("UPDATE {} SET email = $1 WHERE id = $2 RETURNING {}",
+                        USERS_TABLE_NAME, USER_SELECT_FIELDS);
+    let row = transaction.query_one(query.as_str(), &[&email_token.new_email, &email_token.user_id])
+        .await
+        .map_err(|e| match e.code() {
+            Some(&SqlState::UNIQUE_VIOLATION) => EmailTakenError,
+            _ => DBQueryError(e),
+        })?;
+    transaction.commit().await.map_err(DBQueryError)?;
+    Ok(row_to_user(&row))
+}
+
+pub(crate) async fn delete_expired_email_change_tokens(db_pool: &DBPool) -> Result<u64> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("DELETE FROM {} WHERE expiry_date < now()", EMAIL_TOKENS_TABLE_NAME);
+    con.execute(query.as_str(), &[])
+        .await
+        .map_err(DBQueryError)
+}
+
+pub(crate) async fn create_access_token(db_pool: &DBPool,
+                                        user_id: i32,
+                                        request: AccessTokenRequest) -> Result<AccessTokenResponse> {
+    let con = get_conn(db_pool).await?;
+    let token = auth::generate_access_token();
+    let expiry_date = request.expires_in_days
+        .map(|days| Utc::now() + chrono::Duration::days(days));
+    let query = format!("INSERT INTO {} ({}) VALUES ($1,$2,$3,$4,$5) RETURNING {}",
+                        ACCESS_TOKENS_TABLE_NAME, ACCESS_TOKENS_INSERT_FIELDS, ACCESS_TOKENS_SELECT_FIELDS);
+    let row = con.query_one(query.as_str(), &[&user_id, &request.name, &auth::hash_token(&token),
+        &request.scopes, &expiry_date])
+        .await
+        .map_err(DBQueryError)?;
+    Ok(AccessTokenResponse {
+        token,
+        access_token: row_to_access_token(&row),
+    })
+}
+
+pub(crate) async fn find_access_tokens(db_pool: &DBPool, user_id: i32) -> Result<Vec<AccessToken>> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("SELECT {} FROM {} WHERE user_id = $1 ORDER BY id",
+                        ACCESS_TOKENS_SELECT_FIELDS, ACCESS_TOKENS_TABLE_NAME);
+    let rows = con.query(query.as_str(), &[&user_id])
+        .await
+        .map_err(DBQueryError)?;
+    Ok(rows.iter().map(row_to_access_token).collect())
+}
+
+pub(crate) async fn delete_access_token(db_pool: &DBPool, user_id: i32, token_id: i32) -> Result<()> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("DELETE FROM {} WHERE id = $1 AND user_id = $2", ACCESS_TOKENS_TABLE_NAME);
+    let deleted = con.execute(query.as_str(), &[&token_id, &user_id])
+        .await
+        .map_err(DBQueryError)?;
+    if deleted == 0 {
+        return Err(NotFoundError);
+    }
+    Ok(())
+}
+
+pub(crate) async fn find_personal_access_token(db_pool: &DBPool, token: &str) -> Result<Option<PersonalAccessToken>> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("SELECT t.id, t.user_id, t.scopes, t.expiry_date, u.role, u.enabled \
+    FROM {} t JOIN {} u ON u.id = t.user_id WHERE t.token_hash = $1",
+                        ACCESS_TOKENS_TABLE_NAME, USERS_TABLE_NAME);
+    let row = con.query_opt(query.as_str(), &[&auth::hash_token(token)])
+        .await
+        .map_err(DBQueryError)?;
+    Ok(row.map(|row| PersonalAccessToken {
+        id: row.get(0),
+        user_id: row.get(1),
+        scopes: row.get(2),
+        expiry_date: row.get(3),
+        role: row.get(4),
+        enabled: row.get(5),
+    }))
+}
+
+pub(crate) async fn touch_personal_access_token(db_pool: &DBPool, token_id: i32) -> Result<()> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("UPDATE {} SET last_used_at = now() WHERE id = $1", ACCESS_TOKENS_TABLE_NAME);
+    con.execute(query.as_str(), &[&token_id])
+        .await
+        .map_err(DBQueryError)?;
+    Ok(())
+}
+
+pub(crate) async fn find_two_factor(db_pool: &DBPool, user_id: i32) -> Result<TwoFactor> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("SELECT totp_secret,totp_enabled FROM {} WHERE id = $1", USERS_TABLE_NAME);
+    let row = con.query_opt(query.as_str(), &[&user_id])
+        .await
+        .map_err(DBQueryError)?
+        .ok_or(NotFoundError)?;
+    Ok(row_to_two_factor(&row))
+}
+
+/// Stores a new secret and recovery codes, two-factor authentication is only switched on
+/// once the user proves with a code that the authenticator app was set up.
+pub(crate) async fn start_two_factor_enrollment(db_pool: &DBPool,
+                                                user_id: i32,
+                                                secret: &str,
+                                                recovery_codes: &[String]) -> Result<()> {
+    let mut con = get_conn(db_pool).await?;
+    let transaction = con.transaction().await.map_err(DBQueryError)?;
+    let query = format!("SELECT totp_secret,totp_enabled FROM {} WHERE id = $1 FOR UPDATE", USERS_TABLE_NAME);
+    let row = transaction.query_opt(query.as_str(), &[&user_id])
+        .await
+        .map_err(DBQueryError)?
+        .ok_or(NotFoundError)?;
+    if row_to_two_factor(&row).enabled {
+        return Err(TwoFactorEnabledError);
+    }
+    let query = format!("UPDATE {} SET totp_secret = $1, totp_last_step = NULL WHERE id = $2", USERS_TABLE_NAME);
+    transaction.execute(query.as_str(), &[&secret, &user_id])
+        .await
+        .map_err(DBQueryError)?;
+    let query = format!("DELETE FROM {} WHERE user_id = $1", RECOVERY_CODES_TABLE_NAME);
+    transaction.execute(query.as_str(), &[&user_id])
+        .await
+        .map_err(DBQueryError)?;
+    let query = format!("INSERT INTO {} ({}) VALUES ($1,$2)",
+                        RECOVERY_CODES_TABLE_NAME, RECOVERY_CODES_INSERT_FIELDS);
+    for code in recovery_codes {
+        transaction.execute(query.as_str(), &[&user_id, &auth::hash_recovery_code(code)])
+            .await
+            .map_err(DBQueryError)?;
+    }
+    transaction.commit().await.map_err(DBQueryError)?;
+    Ok(())
+}
+
+pub(crate) async fn enable_two_factor(db_pool: &DBPool, user_id: i32, code: &str) -> Result<()> {
+    let mut con = get_conn(db_pool).await?;
+    let transaction = con.transaction().await.map_err(DBQueryError)?;
+    let query = format!("SELECT totp_secret,totp_enabled FROM {} WHERE id = $1 FOR UPDATE", USERS_TABLE_NAME);
+    let row = transaction.query_opt(query.as_str(), &[&user_id])
+        .await
+        .map_err(DBQueryError)?
+        .ok_or(NotFoundError)?;
+    let two_factor = row_to_two_factor(&row);
+    if two_factor.enabled {
+        return Err(TwoFactorEnabledError);
+    }
+    let secret = two_factor.secret.ok_or(TwoFactorNotEnrolledError)?;
+    let step = totp::verify(&secret, code, Utc::now()).ok_or(InvalidTwoFactorCodeError)?;
+    let query = format!("UPDATE {} SET totp_enabled = true, totp_last_step = $1 WHERE id = $2", USERS_TABLE_NAME);
+    transaction.execute(query.as_str(), &[&step, &user_id])
+        .await
+        .map_err(DBQueryError)?;
+    transaction.commit().await.map_err(DBQueryError)?;
+    Ok(())
+}
+
+/// Switches two-factor authentication off and forgets the secret, recovery codes and open challenges.
+pub(crate) async fn disable_two_factor(db_pool: &DBPool, user_id: i32) -> Result<()> {
+    let mut con = get_conn(db_pool).await?;
+    let transaction = con.transaction().await.map_err(DBQueryError)?;
+    let query = format!("UPDATE {} SET totp_secret = NULL, totp_enabled = false, totp_last_step = NULL WHERE id = $1",
+                        USERS_TABLE_NAME);
+    transaction.execute(query.as_str(), &[&user_id])
+        .await
+        .map_err(DBQueryError)?;
+    for table in [RECOVERY_CODES_TABLE_NAME, CHALLENGES_TABLE_NAME] {
+        let query = format!("DELETE FROM {} WHERE user_id = $1", table);
+        transaction.execute(query.as_str(), &[&user_id])
+            .await
+            .map_err(DBQueryError)?;
+    }
+    transaction.commit().await.map_err(DBQueryError)?;
+    Ok(())
+}
+
+pub(crate) async fn create_two_factor_challenge(db_pool: &DBPool,
+                                                user_id: i32,
+                                                lifetime: chrono::Duration) -> Result<(String, DateTime<Utc>)> {
+    let con = get_conn(db_pool).await?;
+    let challenge = auth::generate_token();
+    let expiry_date = Utc::now() + lifetime;
+    let query = format!("INSERT INTO {} ({}) VALUES ($1,$2,$3)",
+                        CHALLENGES_TABLE_NAME, CHALLENGES_INSERT_FIELDS);
+    con.execute(query.as_str(), &[&user_id, &auth::hash_token(&challenge), &expiry_date])
+        .await
+        .map_err(DBQueryError)?;
+    Ok((challenge, expiry_date))
+}
+
+/// The user a challenge was issued to, as long as it can still be answered.
+pub(crate) async fn find_two_factor_challenge_user(db_pool: &DBPool, challenge: &str) -> Result<User> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("SELECT {} FROM {} WHERE token_hash = $1",
+                        CHALLENGES_SELECT_FIELDS, CHALLENGES_TABLE_NAME);
+    let challenge = con.query_opt(query.as_str(), &[&auth::hash_token(challenge)])
+        .await
+        .map_err(DBQueryError)?
+        .map(|row| row_to_two_factor_challenge(&row))
+        .filter(|challenge| challenge.expiry_date > Utc::now() && challenge.attempts < TWO_FACTOR_MAX_ATTEMPTS)
+        .ok_or(TwoFactorChallengeError)?;
+    find_user_by_id(db_pool, challenge.user_id).await
+}
+
+/// Answers a challenge with a code from the authenticator app or an unused recovery code.
+///
+/// A code is accepted once only, and a challenge is dropped after a few wrong answers, so the
+/// password has to be entered again.
+pub(crate) async fn complete_two_factor_challenge(db_pool: &DBPool,
+                                                  challenge: &str,
+                                                  code: Option<&str>,
+                                                  recovery_code: Option<&str>) -> Result<i32> {
+    let mut con = get_conn(db_pool).await?;
+    let transaction = con.transaction().await.map_err(DBQueryError)?;
+    let query = format!("SELECT {} FROM {} WHERE token_hash = $1 FOR UPDATE",
+                        CHALLENGES_SELECT_FIELDS, CHALLENGES_TABLE_NAME);
+    let row = transaction.query_opt(query.as_str(), &[&auth::hash_token(challenge)])
+        .await
+        .map_err(DBQueryError)?;
+    let challenge = match row {
+        Some(row) => row_to_two_factor_challenge(&row),
+        None => return Err(TwoFactorChallengeError),
+    };
+    if challenge.expiry_date < Utc::now() || challenge.attempts >= TWO_FACTOR_MAX_ATTEMPTS {
+        let query = format!("DELETE FROM {} WHERE id = $1", CHALLENGES_TABLE_NAME);
+        transaction.execute(query.as_str(), &[&challenge.id])
+            .await
+            .map_err(DBQueryError)?;
+        transaction.commit().await.map_err(DBQueryError)?;
+        return Err(TwoFactorChallengeError);
+    }
+    let query = format!("SELECT totp_secret,totp_last_step FROM {} WHERE id = $1 AND totp_enabled FOR UPDATE",
+                        USERS_TABLE_NAME);
+    let row = transaction.query_opt(query.as_str(), &[&challenge.user_id])
+        .await
+        .map_err(DBQueryError)?
+        .ok_or(TwoFactorChallengeError)?;
+    let secret: String = row.get(0);
+    let last_step: Option<i64> = row.get(1);
+    let accepted = match (code, recovery_code) {
+        (Some(code), _) => {
+            match totp::verify(&secret, code, Utc::now()).filter(|step| Some(*step) > last_step) {
+                Some(step) => {
+                    let query = format!("UPDATE {} SET totp_last_step = $1 WHERE id = $2", USERS_TABLE_NAME);
+                    transaction.execute(query.as_str(), &[&step, &challenge.user_id])
+                        .await
+                        .map_err(DBQueryError)?;
+                    true
+                }
+                None => false,
+            }
+        }
+        (None, Some(recovery_code)) => {
+            let query = format!("UPDATE {} SET used_at = now() WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL",
+                                RECOVERY_CODES_TABLE_NAME);
+            transaction.execute(query.as_str(), &[&challenge.user_id, &auth::hash_recovery_code(recovery_code)])
+                .await
+                .map_err(DBQueryError)? > 0
+        }
+        (None, None) => false,
+    };
+    let query = if accepted {
+        format!("DELETE FROM {} WHERE id = $1", CHALLENGES_TABLE_NAME)
+    } else {
+        format!("UPDATE {} SET attempts = attempts + 1 WHERE id = $1", CHALLENGES_TABLE_NAME)
+    };
+    transaction.execute(query.as_str(), &[&challenge.id])
+        .await
+        .map_err(DBQueryError)?;
+    transaction.commit().await.map_err(DBQueryError)?;
+    if !accepted {
+        return Err(InvalidTwoFactorCodeError);
+    }
+    Ok(challenge.user_id)
+}
+
+pub(crate) async fn delete_expired_two_factor_challenges(db_pool: &DBPool) -> Result<u64> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("DELETE FROM {} WHERE expiry_date < now()", CHALLENGES_TABLE_NAME);
+    con.execute(query.as_str(), &[])
+        .await
+        .map_err(DBQueryError)
+}
+
+pub(crate) async fn create_oidc_state(db_pool: &DBPool,
+                                      device_code_id: Option<i32>,
+                                      lifetime: chrono::Duration) -> Result<(String, OidcState)> {
+    let con = get_conn(db_pool).await?;
+    let state = auth::generate_token();
+    let nonce = auth::generate_token();
+    let code_verifier = auth::generate_token();
+    let expiry_date = Utc::now() + lifetime;
+    let query = format!("INSERT INTO {} ({}) VALUES ($1,$2,$3,$4,$5) RETURNING {}",
+                        OIDC_STATES_TABLE_NAME, OIDC_STATES_INSERT_FIELDS, OIDC_STATES_SELECT_FIELDS);
+    let row = con.query_one(query.as_str(), &[&auth::hash_token(&state), &nonce, &code_verifier,
+        &device_code_id, &expiry_date])
+        .await
+        .map_err(DBQueryError)?;
+    Ok((state, row_to_oidc_state(&row)))
+}
+
+/// Consumes the state the provider sent back, each one completes a single login.
+pub(crate) async fn take_oidc_state(db_pool: &DBPool, state: &str) -> Result<OidcState> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("DELETE FROM {} WHERE state_hash = $1 RETURNING {}",
+                        OIDC_STATES_TABLE_NAME, OIDC_STATES_SELECT_FIELDS);
+    let oidc_state = con.query_opt(query.as_str(), &[&auth::hash_token(state)])
+        .await
+        .map_err(DBQueryError)?
+        .map(|row| row_to_oidc_state(&row))
+        .ok_or(OidcStateError)?;
+    if oidc_state.expiry_date < Utc::now() {
+        return Err(OidcStateError);
+    }
+    Ok(oidc_state)
+}
+
+/// Finds the user behind a provider identity.
+///
+/// An identity seen for the first time is linked to the account with the same, verified,
+/// email, or gets a new account when `auto_provision` is on.
+pub(crate) async fn find_or_create_oidc_user(db_pool: &DBPool,
+                                             claims: &IdTokenClaims,
+                                             auto_provision: bool) -> Result<User> {
+    let mut con = get_conn(db_pool).await?;
+    let transaction = con.transaction().await.map_err(DBQueryError)?;
+    let query = format!("SELECT {} FROM {} WHERE id = (SELECT user_id FROM {} WHERE issuer = $1 AND subject = $2)",
+                        USER_SELECT_FIELDS, USERS_TABLE_NAME, IDENTITIES_TABLE_NAME);
+    let row = transaction.query_opt(query.as_str(), &[&claims.iss, &claims.sub])
+        .await
+        .map_err(DBQueryError)?;
+    if let Some(row) = row {
+        return Ok(row_to_user(&row));
+    }
+    let email = claims.email.as_ref()
+        .filter(|_| claims.email_verified == Some(true))
+        .ok_or(OidcUserNotFoundError)?;
+    let query = format!("SELECT {}, totp_enabled FROM {} WHERE email = $1 FOR UPDATE",
+                        USER_SELECT_FIELDS, USERS_TABLE_NAME);
+    let row = transaction.query_opt(query.as_str(), &[email])
+        .await
+        .map_err(DBQueryError)?;
+    let user = match row {
+        // an unconfirmed account may have been registered by someone else, and a second factor
+        // must not be skipped, so only plain enabled accounts are linked by their email
+        Some(row) => {
+            let user = row_to_user(&row);
+            let totp_enabled: bool = row.get(10);
+            if !user.enabled || totp_enabled {
+                return Err(OidcUserNotFoundError);
+            }
+            user
+        }
+        None if auto_provision => {
+            // nobody knows this password, the account is only used through the provider
+            let encrypted_pwd = bcrypt::hash(auth::generate_token(), DEFAULT_COST)
+                .map_err(EncryptPasswordError)?;
+            let query = format!("INSERT INTO {} ({},enabled) VALUES ($1,$2,$3,$4,'UTC',true) RETURNING {}",
+                                USERS_TABLE_NAME, USER_INSERT_FIELDS, USER_SELECT_FIELDS);
+            let row = transaction.query_one(query.as_str(), &[&claims.given_name, &claims.family_name, email, &encrypted_pwd])
+                .await
+                .map_err(DBQueryError)?;
+            let user = row_to_user(&row);
+            create_personal_organization(&transaction, &user).await?;
+            user
+        }
+        None => return Err(OidcUserNotFoundError),
+    };
+    let query = format!("INSERT INTO {} ({}) VALUES ($1,$2,$3)", IDENTITIES_TABLE_NAME, IDENTITIES_INSERT_FIELDS);
+    transaction.execute(query.as_str(), &[&user.id, &claims.iss, &claims.sub])
+        .await
+        .map_err(DBQueryError)?;
+    transaction.commit().await.map_err(DBQueryError)?;
+    Ok(user)
+}
+
+/// Starts a device login, the device code stays with the client, the user code is typed in
+/// or compared in the browser.
+pub(crate) async fn create_device_code(db_pool: &DBPool,
+                                       lifetime: chrono::Duration) -> Result<(String, String, DateTime<Utc>)> {
+    let con = get_conn(db_pool).await?;
+    let device_code = auth::generate_token();
+    let user_code = oidc::generate_user_code();
+    let expiry_date = Utc::now() + lifetime;
+    let query = format!("INSERT INTO {} ({}) VALUES ($1,$2,$3)",
+                        DEVICE_CODES_TABLE_NAME, DEVICE_CODES_INSERT_FIELDS);
+    con.execute(query.as_str(), &[&auth::hash_token(&device_code), &user_code, &expiry_date])
+        .await
+        .map_err(DBQueryError)?;
+    Ok((device_code, user_code, expiry_date))
+}
+
+/// The pending device login shown with `user_code`.
+pub(crate) async fn find_device_code(db_pool: &DBPool, user_code: &str) -> Result<DeviceCode> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("SELECT {} FROM {} WHERE user_code = $1 AND user_id IS NULL AND expiry_date > now()",
+                        DEVICE_CODES_SELECT_FIELDS, DEVICE_CODES_TABLE_NAME);
+    con.query_opt(query.as_str(), &[&user_code.trim().to_uppercase()])
+        .await
+        .map_err(DBQueryError)?
+        .map(|row| row_to_device_code(&row))
+        .ok_or(DeviceCodeError)
+}
+
+pub(crate) async fn authorize_device_code(db_pool: &DBPool, device_code_id: i32, user_id: i32) -> Result<()> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("UPDATE {} SET user_id = $1 WHERE id = $2 AND user_id IS NULL AND expiry_date > now()",
+                        DEVICE_CODES_TABLE_NAME);
+    let updated = con.execute(query.as_str(), &[&user_id, &device_code_id])
+        .await
+        .map_err(DBQueryError)?;
+    if updated == 0 {
+        return Err(DeviceCodeError);
+    }
+    Ok(())
+}
+
+/// Polled by the client, returns the user once the login was completed in the browser and
+/// consumes the device code with it.
+pub(crate) async fn take_device_code(db_pool: &DBPool, device_code: &str) -> Result<Option<i32>> {
+    let mut con = get_conn(db_pool).await?;
+    let transaction = con.transaction().await.map_err(DBQueryError)?;
+    let query = format!("SELECT {} FROM {} WHERE device_code_hash = $1 FOR UPDATE",
+                        DEVICE_CODES_SELECT_FIELDS, DEVICE_CODES_TABLE_NAME);
+    let device_code = transaction.query_opt(query.as_str(), &[&auth::hash_token(device_code)])
+        .await
+        .map_err(DBQueryError)?
+        .map(|row| row_to_device_code(&row))
+        .filter(|device_code| device_code.expiry_date > Utc::now())
+        .ok_or(DeviceCodeError)?;
+    if device_code.user_id.is_none() {
+        return Ok(None);
+    }
+    let query = format!("DELETE FROM {} WHERE device_code_id = $1", OIDC_STATES_TABLE_NAME);
+    transaction.execute(query.as_str(), &[&device_code.id])
+        .await
+        .map_err(DBQueryError)?;
+    let query = format!("DELETE FROM {} WHERE id = $1", DEVICE_CODES_TABLE_NAME);
+    transaction.execute(query.as_str(), &[&device_code.id])
+        .await
+        .map_err(DBQueryError)?;
+    transaction.commit().await.map_err(DBQueryError)?;
+    Ok(device_code.user_id)
+}
+
+pub(crate) async fn delete_expired_oidc_logins(db_pool: &DBPool) -> Result<u64> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("DELETE FROM {} WHERE expiry_date < now() \
+    OR device_code_id IN (SELECT id FROM {} WHERE expiry_date < now())",
+                        OIDC_STATES_TABLE_NAME, DEVICE_CODES_TABLE_NAME);
+    let states = con.execute(query.as_str(), &[])
+        .await
+        .map_err(DBQueryError)?;
+    let query = format!("DELETE FROM {} WHERE expiry_date < now()", DEVICE_CODES_TABLE_NAME);
+    let device_codes = con.execute(query.as_str(), &[])
+        .await
+        .map_err(DBQueryError)?;
+    Ok(states + device_codes)
+}
+
+/// Audit record of a login, `reason` tells why a failed one was refused.
+pub(crate) async fn record_login_attempt(db_pool: &DBPool,
+                                         user_id: Option<i32>,
+                                         email: &str,
+                                         ip: &str,
+                                         success: bool,
+                                         reason: Option<&str>) -> Result<()> {
+    let con = get_conn(db_pool).await?;
+    let query = format!("INSERT INTO {} ({}) VALUES ($1,$2,$3,$4,$5)",
+                        LOGIN_ATTEMPTS_TABLE_NAME, LOGIN_ATTEMPTS_INSERT_FIELDS);
+    con.execute(query.as_str(), &[&user_id, &email, &ip, &success, &reason])
+        .await
+        .map_err(DBQueryError)?;
+    Ok(())
+}
+
+/// Counts logins with wrong credentials within the window, for the email only those after
+/// its last successful login.
+pub(crate) async fn count_login_failures(db_pool: &DBPool,
+                                         email: &str,
+                                         ip: &str,
+                                         window: chrono::Duration) -> Result<LoginFailures> {
+    let con = get_conn(db_pool).await?;
+    let since = Utc::now() - window;
+    let query = format!("SELECT count(*), max(created_at) FROM {table} \
+    WHERE email = $1 AND reason = $2 AND created_at > $3 \
+    AND created_at > COALESCE((SELECT max(created_at) FROM {table} WHERE email = $1 AND success), '-infinity')",
+                        table = LOGIN_ATTEMPTS_TABLE_NAME);
+    let account = con.query_one(query.as_str(), &[&email, &lockout::WRONG_CREDENTIALS, &since])
+        .await
+        .map_err(DBQueryError)?;
+    let query = format!("SELECT count(*), max(created_at) FROM {} \
+    WHERE ip = $1 AND reason = $2 AND created_at > $3",
+                        LOGIN_ATTEMPTS_TABLE_NAME);
+    let ip = con.query_one(query.as_str(), &[&ip, &lockout::WRONG_CREDENTIALS, &since])
+        .await
+        .map_err(DBQueryError)?;
+    Ok(LoginFailures {
+        account: account.get(0),
+        account_last: account.get(1),
+        ip: ip.get(0),
+        ip_last: ip.get(1),
+    })
+}
+
+fn row_to_user(row: &Row) -> User {
+    let id: i32 = row.get(0);
+    let first_name: Option<String> = row.get(1);
+    let last_name: Option<String> = row.get(2);
+    let email: String = row.get(3);
+    let pwd: String = row.get(4);
+    let enabled: bool = row.get(5);
+    let time_zone: String = row.get(6);
+    let role: String = row.get(7);
+    let created_at: DateTime<Utc> = row.get(8);
+    let token_version: i32 = row.get(9);
+    User {
+        id,
+        first_name,
+        last_name,
+        email,
+        pwd,
+        enabled,
+        time_zone,
+        role,
+        created_at,
+        token_version,
+    }
+}
+
+fn row_to_task(row: &Row) -> Task {
+    let id: i32 = row.get(0);
+    let title: String = row.get(1);
+    let description: Option<String> = row.get(2);
+    let reporter_id: i32 = row.get(3);
+    let project_id: i32 = row.get(4);
+    let created_at: DateTime<Utc> = row.get(5);
+    let status: Option<String> = row.get(6);
+    let start_at: Option<DateTime<Utc>> = row.get(7);
+    let due_at: Option<DateTime<Utc>> = row.get(8);
+    let assignees: Vec<i32> = row.get(9);
+    let parent_id: Option<i32> = row.get(10);
+    Task {
+        id,
+        title,
+        description,
+        reporter_id,
+        project_id,
+        created_at,
+        status,
+        start_at,
+        due_at,
+        assignees,
+        parent_id,
+    }
+}
+
+fn row_to_status(row: &Row) -> TaskStatus {
+    let id: i32 = row.get(0);
+    let project_id: i32 = row.get(1);
+    let name: String = row.get(2);
+    let position: i32 = row.get(3);
+    let done: bool = row.get(4);
+    TaskStatus {
+        id,
+        project_id,
+        name,
+        position,
+        done,
+    }
+}
+
+fn row_to_trash_item(row: &Row) -> TrashItem {
+    let kind: &str = row.get(0);
+    TrashItem {
+        kind: if kind == "project" { TrashKind::Project } else { TrashKind::Task },
+        id: row.get(1),
+        title: row.get(2),
+        deleted_at: row.get(3),
     }
 }
 
@@ -343,11 +2692,167 @@ fn row_to_project(row: &Row) -> Project {
     let title: String = row.get(1);
     let description: Option<String> = row.get(2);
     let created_at: DateTime<Utc> = row.get(3);
+    let archived_at: Option<DateTime<Utc>> = row.get(4);
     Project {
         id,
         title,
         description,
         created_at,
+        archived_at,
+    }
+}
+
+fn row_to_project_role(row: &Row, index: usize) -> Option<ProjectRole> {
+    let role: Option<String> = row.get(index);
+    role.as_deref().and_then(ProjectRole::parse)
+}
+
+fn row_to_organization(row: &Row) -> Organization {
+    let id: i32 = row.get(0);
+    let name: String = row.get(1);
+    let role: String = row.get(2);
+    let created_at: DateTime<Utc> = row.get(3);
+    Organization {
+        id,
+        name,
+        role,
+        created_at,
+    }
+}
+
+fn row_to_organization_member(row: &Row) -> OrganizationMember {
+    let user_id: i32 = row.get(0);
+    let email: String = row.get(1);
+    let first_name: Option<String> = row.get(2);
+    let last_name: Option<String> = row.get(3);
+    let role: String = row.get(4);
+    OrganizationMember {
+        user_id,
+        email,
+        first_name,
+        last_name,
+        role,
+    }
+}
+
+fn row_to_project_member(row: &Row) -> ProjectMember {
+    let user_id: i32 = row.get(0);
+    let email: String = row.get(1);
+    let first_name: Option<String> = row.get(2);
+    let last_name: Option<String> = row.get(3);
+    let role: String = row.get(4);
+    ProjectMember {
+        user_id,
+        email,
+        first_name,
+        last_name,
+        role,
+    }
+}
+
+fn row_to_project_invitation(row: &Row) -> ProjectInvitation {
+    let id: i32 = row.get(0);
+    let project_id: i32 = row.get(1);
+    let project_title: String = row.get(2);
+    let email: String = row.get(3);
+    let role: String = row.get(4);
+    let expiry_date: DateTime<Utc> = row.get(5);
+    let created_at: DateTime<Utc> = row.get(6);
+    ProjectInvitation {
+        id,
+        project_id,
+        project_title,
+        email,
+        role,
+        expiry_date,
+        created_at,
+    }
+}
+
+fn row_to_password_reset_token(row: &Row) -> PasswordResetToken {
+    let id: i32 = row.get(0);
+    let user_id: i32 = row.get(1);
+    let expiry_date: DateTime<Utc> = row.get(2);
+    PasswordResetToken {
+        id,
+        user_id,
+        expiry_date,
+    }
+}
+
+fn row_to_email_change_token(row: &Row) -> EmailChangeToken {
+    let id: i32 = row.get(0);
+    let user_id: i32 = row.get(1);
+    let new_email: String = row.get(2);
+    let expiry_date: DateTime<Utc> = row.get(3);
+    EmailChangeToken {
+        id,
+        user_id,
+        new_email,
+        expiry_date,
+    }
+}
+
+fn row_to_two_factor(row: &Row) -> TwoFactor {
+    let secret: Option<String> = row.get(0);
+    let enabled: bool = row.get(1);
+    TwoFactor {
+        secret,
+        enabled,
+    }
+}
+
+fn row_to_two_factor_challenge(row: &Row) -> TwoFactorChallengeToken {
+    let id: i32 = row.get(0);
+    let user_id: i32 = row.get(1);
+    let expiry_date: DateTime<Utc> = row.get(2);
+    let attempts: i32 = row.get(3);
+    TwoFactorChallengeToken {
+        id,
+        user_id,
+        expiry_date,
+        attempts,
+    }
+}
+
+fn row_to_oidc_state(row: &Row) -> OidcState {
+    let nonce: String = row.get(0);
+    let code_verifier: String = row.get(1);
+    let device_code_id: Option<i32> = row.get(2);
+    let expiry_date: DateTime<Utc> = row.get(3);
+    OidcState {
+        nonce,
+        code_verifier,
+        device_code_id,
+        expiry_date,
+    }
+}
+
+fn row_to_device_code(row: &Row) -> DeviceCode {
+    let id: i32 = row.get(0);
+    let user_id: Option<i32> = row.get(1);
+    let expiry_date: DateTime<Utc> = row.get(2);
+    DeviceCode {
+        id,
+        user_id,
+        expiry_date,
+    }
+}
+
+fn row_to_access_token(row: &Row) -> AccessToken {
+    let id: i32 = row.get(0);
+    let name: String = row.get(1);
+    let scopes: Vec<String> = row.get(2);
+    let expiry_date: Option<DateTime<Utc>> = row.get(3);
+    let last_used_at: Option<DateTime<Utc>> = row.get(4);
+    let created_at: DateTime<Utc> = row.get(5);
+    AccessToken {
+        id,
+        name,
+        scopes,
+        expiry_date,
+        last_used_at,
+        created_at,
     }
 }
 
@@ -363,3 +2868,18 @@ fn row_to_token(row: &Row) -> VerificationToken {
         expiry_date,
     }
 }
+
+fn row_to_refresh_token(row: &Row) -> RefreshToken {
+    let id: i32 = row.get(0);
+    let user_id: i32 = row.get(1);
+    let family_id: String = row.get(2);
+    let expiry_date: DateTime<Utc> = row.get(3);
+    let revoked: bool = row.get(4);
+    RefreshToken {
+        id,
+        user_id,
+        family_id,
+        expiry_date,
+        revoked,
+    }
+}
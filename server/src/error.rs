@@ -4,7 +4,10 @@ use mobc_postgres::tokio_postgres;
 use serde_derive::Serialize;
 use thiserror::Error;
 use warp::{Rejection, Reply};
-use warp::http::StatusCode;
+use warp::http::{HeaderValue, StatusCode};
+use warp::http::header::RETRY_AFTER;
+
+use common::data::StatusCount;
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -28,6 +31,48 @@ pub enum Error {
     JWTTokenError,
     #[error("jwt token creation error")]
     JWTTokenCreationError,
+    #[error("jwt key not valid")]
+    JWTKeyError,
+    #[error("refresh token not valid")]
+    RefreshTokenError,
+    #[error("too many login attempts, retry in {0} seconds")]
+    TooManyLoginAttemptsError(i64),
+    #[error("two-factor challenge not valid")]
+    TwoFactorChallengeError,
+    #[error("two-factor code not valid")]
+    InvalidTwoFactorCodeError,
+    #[error("two-factor authentication already enabled")]
+    TwoFactorEnabledError,
+    #[error("two-factor authentication not set up")]
+    TwoFactorNotEnrolledError,
+    #[error("OpenID Connect login not configured")]
+    OidcNotConfiguredError,
+    #[error("OpenID Connect provider error")]
+    OidcProviderError,
+    #[error("OpenID Connect login not valid")]
+    OidcStateError,
+    #[error("OpenID Connect login refused by the provider: {0}")]
+    OidcLoginRefusedError(String),
+    #[error("no account for the OpenID Connect identity")]
+    OidcUserNotFoundError,
+    #[error("device code not valid")]
+    DeviceCodeError,
+    #[error("personal access token not valid")]
+    AccessTokenError,
+    #[error("unknown scope")]
+    InvalidScopeError,
+    #[error("access token lifetime out of range")]
+    InvalidTokenLifetimeError,
+    #[error("verification token not valid")]
+    VerificationTokenError,
+    #[error("verification token expired")]
+    VerificationTokenExpiredError,
+    #[error("password reset token not valid")]
+    PasswordResetTokenError,
+    #[error("email change token not valid")]
+    EmailChangeTokenError,
+    #[error("email already in use")]
+    EmailTakenError,
     #[error("no auth header")]
     NoAuthHeaderError,
     #[error("invalid auth header")]
@@ -38,6 +83,48 @@ pub enum Error {
     UserNotEnabledError,
     #[error("error send notification")]
     NotificationError,
+    #[error("resource not found")]
+    NotFoundError,
+    #[error("illegal status transition")]
+    IllegalStatusTransitionError,
+    #[error("invalid workflow")]
+    InvalidWorkflowError,
+    #[error("unknown time zone")]
+    InvalidTimeZoneError,
+    #[error("task starts after it is due")]
+    InvalidScheduleError,
+    #[error("user is not a member of the project")]
+    NotProjectMemberError,
+    #[error("unknown role")]
+    InvalidRoleError,
+    #[error("the last administrator cannot be demoted, disabled or deleted")]
+    LastAdminError,
+    #[error("tasks cannot be reassigned to that user")]
+    InvalidReassignTargetError,
+    #[error("user is already a member of the project")]
+    AlreadyProjectMemberError,
+    #[error("the last member cannot leave the project")]
+    LastProjectMemberError,
+    #[error("invitation token not valid")]
+    InvitationError,
+    #[error("the last owner cannot leave the project")]
+    LastProjectOwnerError,
+    #[error("unknown project role")]
+    InvalidProjectRoleError,
+    #[error("user is already a member of the organization")]
+    AlreadyOrganizationMemberError,
+    #[error("the last admin cannot leave the organization")]
+    LastOrganizationAdminError,
+    #[error("unknown organization role")]
+    InvalidOrganizationRoleError,
+    #[error("project still has {0} tasks")]
+    ProjectNotEmptyError(i64, Vec<StatusCount>),
+    #[error("tasks cannot be moved to that project")]
+    InvalidMoveTargetError,
+    #[error("parent task not valid")]
+    InvalidParentTaskError,
+    #[error("subtasks nested too deep")]
+    TaskDepthExceededError,
 }
 
 impl From<mobc::Error<mobc_postgres::tokio_postgres::Error>> for Error {
@@ -61,6 +148,14 @@ impl From<std::io::Error> for Error {
 #[derive(Serialize)]
 struct ErrorResponse {
     message: String,
+    /// Seconds until the request may be repeated, also sent as `Retry-After`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry_after: Option<i64>,
+    /// Tasks that keep a project from being deleted, in total and per status.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    task_count: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status_counts: Option<Vec<StatusCount>>,
 }
 
 impl warp::reject::Reject for Error {}
@@ -68,6 +163,9 @@ impl warp::reject::Reject for Error {}
 pub async fn handle_rejection(err: Rejection) -> std::result::Result<impl Reply, Infallible> {
     let code;
     let message;
+    let mut retry_after = None;
+    let mut task_count = None;
+    let mut status_counts = None;
 
     if err.is_not_found() {
         code = StatusCode::NOT_FOUND;
@@ -105,10 +203,177 @@ pub async fn handle_rejection(err: Rejection) -> std::result::Result<impl Reply,
                 code = StatusCode::UNAUTHORIZED;
                 message = "Unauthorized";
             }
+            Error::RefreshTokenError => {
+                code = StatusCode::UNAUTHORIZED;
+                message = "Unauthorized";
+            }
+            Error::VerificationTokenError => {
+                code = StatusCode::BAD_REQUEST;
+                message = "Verification token not valid";
+            }
+            Error::VerificationTokenExpiredError => {
+                code = StatusCode::GONE;
+                message = "Verification token expired";
+            }
+            Error::PasswordResetTokenError => {
+                code = StatusCode::BAD_REQUEST;
+                message = "Password reset token not valid";
+            }
+            Error::EmailChangeTokenError => {
+                code = StatusCode::BAD_REQUEST;
+                message = "Email change token not valid";
+            }
+            Error::EmailTakenError => {
+                code = StatusCode::CONFLICT;
+                message = "Email already in use";
+            }
+            Error::TooManyLoginAttemptsError(seconds) => {
+                code = StatusCode::TOO_MANY_REQUESTS;
+                message = "Too many login attempts";
+                retry_after = Some(*seconds);
+            }
+            Error::TwoFactorChallengeError => {
+                code = StatusCode::UNAUTHORIZED;
+                message = "Two-factor challenge not valid";
+            }
+            Error::InvalidTwoFactorCodeError => {
+                code = StatusCode::BAD_REQUEST;
+                message = "Two-factor code not valid";
+            }
+            Error::TwoFactorEnabledError => {
+                code = StatusCode::CONFLICT;
+                message = "Two-factor authentication already enabled";
+            }
+            Error::TwoFactorNotEnrolledError => {
+                code = StatusCode::BAD_REQUEST;
+                message = "Two-factor authentication not set up";
+            }
+            Error::OidcNotConfiguredError => {
+                code = StatusCode::NOT_FOUND;
+                message = "Not Found";
+            }
+            Error::OidcProviderError => {
+                code = StatusCode::BAD_GATEWAY;
+                message = "Identity provider error";
+            }
+            Error::OidcStateError => {
+                code = StatusCode::BAD_REQUEST;
+                message = "Login not valid or expired";
+            }
+            Error::OidcLoginRefusedError(_) => {
+                code = StatusCode::UNAUTHORIZED;
+                message = "Login refused by the identity provider";
+            }
+            Error::OidcUserNotFoundError => {
+                code = StatusCode::FORBIDDEN;
+                message = "No account for this identity";
+            }
+            Error::DeviceCodeError => {
+                code = StatusCode::BAD_REQUEST;
+                message = "Device code not valid or expired";
+            }
+            Error::AccessTokenError => {
+                code = StatusCode::UNAUTHORIZED;
+                message = "Unauthorized";
+            }
+            Error::InvalidScopeError => {
+                code = StatusCode::BAD_REQUEST;
+                message = "Unknown scope";
+            }
+            Error::InvalidTokenLifetimeError => {
+                code = StatusCode::BAD_REQUEST;
+                message = "Access tokens expire after 1 to 3650 days";
+            }
             Error::UserNotEnabledError => {
                 code = StatusCode::BAD_REQUEST;
                 message = "User not enabled"
             }
+            Error::NotFoundError => {
+                code = StatusCode::NOT_FOUND;
+                message = "Not Found";
+            }
+            Error::IllegalStatusTransitionError => {
+                code = StatusCode::CONFLICT;
+                message = "Illegal status transition";
+            }
+            Error::InvalidWorkflowError => {
+                code = StatusCode::BAD_REQUEST;
+                message = "Invalid workflow";
+            }
+            Error::InvalidTimeZoneError => {
+                code = StatusCode::BAD_REQUEST;
+                message = "Unknown time zone";
+            }
+            Error::InvalidScheduleError => {
+                code = StatusCode::BAD_REQUEST;
+                message = "Start date is after due date";
+            }
+            Error::NotProjectMemberError => {
+                code = StatusCode::BAD_REQUEST;
+                message = "User is not a member of the project";
+            }
+            Error::InvalidRoleError => {
+                code = StatusCode::BAD_REQUEST;
+                message = "Unknown role";
+            }
+            Error::LastAdminError => {
+                code = StatusCode::CONFLICT;
+                message = "The last administrator cannot be demoted, disabled or deleted";
+            }
+            Error::InvalidReassignTargetError => {
+                code = StatusCode::BAD_REQUEST;
+                message = "Tasks cannot be reassigned to that user";
+            }
+            Error::AlreadyProjectMemberError => {
+                code = StatusCode::CONFLICT;
+                message = "User is already a member of the project";
+            }
+            Error::LastProjectMemberError => {
+                code = StatusCode::CONFLICT;
+                message = "The last member cannot leave the project, delete it instead";
+            }
+            Error::InvitationError => {
+                code = StatusCode::BAD_REQUEST;
+                message = "Invitation not valid, expired or meant for another email address";
+            }
+            Error::LastProjectOwnerError => {
+                code = StatusCode::CONFLICT;
+                message = "The project needs an owner, transfer the ownership first";
+            }
+            Error::InvalidProjectRoleError => {
+                code = StatusCode::BAD_REQUEST;
+                message = "Unknown project role, ownership is handed over with a transfer";
+            }
+            Error::AlreadyOrganizationMemberError => {
+                code = StatusCode::CONFLICT;
+                message = "User is already a member of the organization";
+            }
+            Error::LastOrganizationAdminError => {
+                code = StatusCode::CONFLICT;
+                message = "The organization needs an admin, appoint another one first";
+            }
+            Error::InvalidOrganizationRoleError => {
+                code = StatusCode::BAD_REQUEST;
+                message = "Unknown organization role";
+            }
+            Error::ProjectNotEmptyError(count, counts) => {
+                code = StatusCode::CONFLICT;
+                message = "The project still has tasks, delete them with strategy=cascade or move them with strategy=move";
+                task_count = Some(*count);
+                status_counts = Some(counts.clone());
+            }
+            Error::InvalidMoveTargetError => {
+                code = StatusCode::BAD_REQUEST;
+                message = "Tasks cannot be moved to that project";
+            }
+            Error::InvalidParentTaskError => {
+                code = StatusCode::BAD_REQUEST;
+                message = "The parent must be a task of the same project outside of the subtree";
+            }
+            Error::TaskDepthExceededError => {
+                code = StatusCode::BAD_REQUEST;
+                message = "Subtasks are nested too deep";
+            }
             _ => {
                 eprintln!("unhandled application error: {:?}", err);
                 code = StatusCode::INTERNAL_SERVER_ERROR;
@@ -126,7 +391,14 @@ pub async fn handle_rejection(err: Rejection) -> std::result::Result<impl Reply,
 
     let json = warp::reply::json(&ErrorResponse {
         message: message.into(),
+        retry_after,
+        task_count,
+        status_counts,
     });
 
-    Ok(warp::reply::with_status(json, code))
+    let mut response = warp::reply::with_status(json, code).into_response();
+    if let Some(seconds) = retry_after {
+        response.headers_mut().insert(RETRY_AFTER, HeaderValue::from(seconds));
+    }
+    Ok(response)
 }
\ No newline at end of file
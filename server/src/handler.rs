@@ -1,12 +1,26 @@
+use std::sync::Arc;
+
 use warp::{reject, Reply};
-use warp::http::StatusCode;
-use warp::reply::json;
+use warp::http::{StatusCode, Uri};
+use warp::reply::{json, Response};
 
-use common::data::{LoginRequest, LoginResponse, Pageable, ProjectRequest, TaskRequest, User, UserDto, UserRequest};
+use common::data::{AccessTokenRequest, AssigneeRequest, ConfirmationQuery, DeviceAuthorization, DeviceTokenRequest, DeviceVerificationQuery, EmailChangeRequest, ForgotPasswordRequest, InvitationRequest, InvitationTokenRequest, LoginRequest, LoginResponse, MemberRoleRequest, OidcCallbackQuery, OrganizationMemberRequest, OrganizationRequest, Pageable, PasswordChangeRequest, ProfileUpdateRequest, ProjectDeleteQuery, ProjectDeleteStrategy, ProjectFilter, ProjectRequest, ProjectTransferRequest, ProjectUpdateRequest, RefreshRequest, ResendVerificationRequest, ResetPasswordRequest, RoleRequest, TaskFilter, TaskParentRequest, TaskRequest, TaskStatusChangeRequest, TaskUpdateRequest, TwoFactorChallenge, TwoFactorCodeRequest, TwoFactorDisableRequest, TwoFactorEnrollment, TwoFactorLoginRequest, User, UserDeleteQuery, UserDto, UserRequest, UserUpdateRequest, WorkflowRequest};
 
-use crate::{auth, db, notification, Result};
+use crate::{access, auth, db, lockout, oidc, Result, totp};
+use crate::access::{OrganizationAction, OrganizationRole, ProjectAction, ProjectRole, TaskAction};
+use crate::auth::{JwtKeys, Role, Scope};
+use crate::config::Config;
+use crate::data::{OidcState, ProjectAccess};
 use crate::DBPool;
 use crate::error::Error::*;
+use crate::notification;
+use crate::notification::Notifier;
+
+const RECOVERY_CODE_COUNT: usize = 10;
+const DEVICE_POLL_INTERVAL_SECONDS: u64 = 5;
+/// Longest lifetime an expiring personal access token may be given.
+const MAX_ACCESS_TOKEN_DAYS: i64 = 3650;
+const DEVICE_LOGIN_HTML: &str = include_str!("../templates/device_login.html");
 
 pub async fn health_handler(db_pool: DBPool) -> Result<impl Reply> {
     let db = db::get_conn(&db_pool)
@@ -18,21 +32,123 @@ pub async fn health_handler(db_pool: DBPool) -> Result<impl Reply> {
     Ok(StatusCode::OK)
 }
 
-pub async fn login_handler(login_request: LoginRequest, db_pool: DBPool) -> Result<impl Reply> {
-    let user = db::find_user_by_email(db_pool, &login_request.email)
+pub async fn login_handler(login_request: LoginRequest, client_ip: String, db_pool: DBPool,
+                           jwt_keys: Arc<JwtKeys>, config: Arc<Config>) -> Result<impl Reply> {
+    let email = login_request.email.as_str();
+    if let Err(e) = lockout::check_login_allowed(&db_pool, &config, email, &client_ip).await {
+        db::record_login_attempt(&db_pool, None, email, &client_ip, false, Some(lockout::LOCKED))
+            .await
+            .map_err(|e| reject::custom(e))?;
+        return Err(reject::custom(e));
+    }
+    let user = match db::find_user_by_email(db_pool.clone(), email).await {
+        Ok(user) => user,
+        Err(e) => {
+            db::record_login_attempt(&db_pool, None, email, &client_ip, false, Some(lockout::WRONG_CREDENTIALS))
+                .await
+                .map_err(|e| reject::custom(e))?;
+            return Err(reject::custom(e));
+        }
+    };
+    let is_right_pwd = bcrypt::verify(&login_request.pwd, &user.pwd)
+        .map_err(|e| reject::custom(VerifyPasswordError(e)))?;
+    if !is_right_pwd {
+        db::record_login_attempt(&db_pool, Some(user.id), email, &client_ip, false, Some(lockout::WRONG_CREDENTIALS))
+            .await
+            .map_err(|e| reject::custom(e))?;
+        return Err(reject::custom(WrongCredentialsError));
+    }
+    if !user.enabled {
+        db::record_login_attempt(&db_pool, Some(user.id), email, &client_ip, false, Some(lockout::NOT_ENABLED))
+            .await
+            .map_err(|e| reject::custom(e))?;
+        return Err(reject::custom(UserNotEnabledError));
+    }
+    let two_factor = db::find_two_factor(&db_pool, user.id)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    if two_factor.enabled {
+        db::record_login_attempt(&db_pool, Some(user.id), email, &client_ip, false, Some(lockout::SECOND_FACTOR))
+            .await
+            .map_err(|e| reject::custom(e))?;
+        let (challenge, expiry_date) = db::create_two_factor_challenge(&db_pool, user.id,
+                                                                       chrono::Duration::minutes(config.two_factor_challenge_minutes))
+            .await
+            .map_err(|e| reject::custom(e))?;
+        return Ok(json(&TwoFactorChallenge { challenge, expiry_date }));
+    }
+    db::record_login_attempt(&db_pool, Some(user.id), email, &client_ip, true, None)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let login_response = issue_tokens(&user, &db_pool, &jwt_keys, &config).await?;
+    Ok(json(&login_response))
+}
+
+/// Second login step, wrong codes count as failed logins of the account.
+pub async fn two_factor_login_handler(request: TwoFactorLoginRequest, client_ip: String, db_pool: DBPool,
+                                      jwt_keys: Arc<JwtKeys>, config: Arc<Config>) -> Result<impl Reply> {
+    let user = db::find_two_factor_challenge_user(&db_pool, &request.challenge)
         .await
         .map_err(|e| reject::custom(e))?;
+    if let Err(e) = lockout::check_login_allowed(&db_pool, &config, &user.email, &client_ip).await {
+        db::record_login_attempt(&db_pool, Some(user.id), &user.email, &client_ip, false, Some(lockout::LOCKED))
+            .await
+            .map_err(|e| reject::custom(e))?;
+        return Err(reject::custom(e));
+    }
+    let completed = db::complete_two_factor_challenge(&db_pool, &request.challenge,
+                                                      request.code.as_deref(), request.recovery_code.as_deref())
+        .await;
+    if let Err(e) = completed {
+        if let InvalidTwoFactorCodeError = e {
+            db::record_login_attempt(&db_pool, Some(user.id), &user.email, &client_ip, false, Some(lockout::WRONG_CREDENTIALS))
+                .await
+                .map_err(|e| reject::custom(e))?;
+        }
+        return Err(reject::custom(e));
+    }
     if !user.enabled {
         return Err(reject::custom(UserNotEnabledError));
     }
-    let is_right_pwd = bcrypt::verify(login_request.pwd, &user.pwd)
-        .map_err(|e| reject::custom(VerifyPasswordError(e)))?;
-    if !is_right_pwd {
-        return Err(reject::custom(WrongCredentialsError));
+    db::record_login_attempt(&db_pool, Some(user.id), &user.email, &client_ip, true, None)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let login_response = issue_tokens(&user, &db_pool, &jwt_keys, &config).await?;
+    Ok(json(&login_response))
+}
+
+async fn issue_tokens(user: &User, db_pool: &DBPool, jwt_keys: &JwtKeys, config: &Config) -> Result<LoginResponse> {
+    let token = auth::create_token(user, jwt_keys)
+        .map_err(|e| reject::custom(e))?;
+    let refresh_token = db::create_refresh_token(db_pool, user.id,
+                                                 chrono::Duration::days(config.refresh_token_lifetime_days))
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(LoginResponse { token, refresh_token })
+}
+
+pub async fn refresh_handler(refresh_request: RefreshRequest, db_pool: DBPool,
+                             jwt_keys: Arc<JwtKeys>, config: Arc<Config>) -> Result<impl Reply> {
+    let (user_id, refresh_token) = db::rotate_refresh_token(&db_pool, &refresh_request.refresh_token,
+                                                            chrono::Duration::days(config.refresh_token_lifetime_days))
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let user = db::find_user_by_id(&db_pool, user_id)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    if !user.enabled {
+        return Err(reject::custom(UserNotEnabledError));
     }
-    let token = auth::create_token(&user)
+    let token = auth::create_token(&user, &jwt_keys)
+        .map_err(|e| reject::custom(e))?;
+    Ok(json(&LoginResponse { token, refresh_token }))
+}
+
+pub async fn logout_handler(refresh_request: RefreshRequest, db_pool: DBPool) -> Result<impl Reply> {
+    db::revoke_refresh_token(&db_pool, &refresh_request.refresh_token)
+        .await
         .map_err(|e| reject::custom(e))?;
-    Ok(json(&LoginResponse { token }))
+    Ok(StatusCode::OK)
 }
 
 pub async fn get_users(pageable: Pageable, db_pool: DBPool, user_id: i32) -> Result<impl Reply> {
@@ -46,59 +162,797 @@ pub async fn get_users(pageable: Pageable, db_pool: DBPool, user_id: i32) -> Res
     ))
 }
 
-pub async fn register_user(user_request: UserRequest, db_pool: DBPool) -> Result<impl Reply> {
-    let (created_user, verification_token) = db::create_user_and_verification_token(&db_pool, user_request)
+pub async fn update_user_role(user_id: i32, role_request: RoleRequest,
+                              db_pool: DBPool, _admin_id: i32) -> Result<impl Reply> {
+    let role = Role::parse(&role_request.role)
+        .ok_or_else(|| reject::custom(InvalidRoleError))?;
+    let user = db::update_user(&db_pool, user_id, Some(&role.to_string()), None)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let dto: UserDto = user.into();
+    Ok(json(&dto))
+}
+
+pub async fn get_user(user_id: i32, db_pool: DBPool, _admin_id: i32) -> Result<impl Reply> {
+    let user = db::find_user_by_id(&db_pool, user_id)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let dto: UserDto = user.into();
+    Ok(json(&dto))
+}
+
+pub async fn update_user(user_id: i32, request: UserUpdateRequest,
+                         db_pool: DBPool, _admin_id: i32) -> Result<impl Reply> {
+    let role = match &request.role {
+        Some(role) => Some(Role::parse(role).ok_or_else(|| reject::custom(InvalidRoleError))?.to_string()),
+        None => None,
+    };
+    let user = db::update_user(&db_pool, user_id, role.as_deref(), request.enabled)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let dto: UserDto = user.into();
+    Ok(json(&dto))
+}
+
+pub async fn delete_user(user_id: i32, query: UserDeleteQuery,
+                         db_pool: DBPool, _admin_id: i32) -> Result<impl Reply> {
+    db::delete_user(&db_pool, user_id, query.strategy, query.reassign_to)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Signs the user out and mails a reset token, the old password no longer works.
+pub async fn force_password_reset(user_id: i32, db_pool: DBPool, config: Arc<Config>,
+                                  notifier: Arc<dyn Notifier>, _admin_id: i32) -> Result<impl Reply> {
+    let (user, token, expiry_date) = db::force_password_reset(&db_pool, user_id,
+                                                              chrono::Duration::minutes(config.password_reset_token_lifetime_minutes))
+        .await
+        .map_err(|e| reject::custom(e))?;
+    notifier.send(notification::password_reset_notification(&user, &token, expiry_date))
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(StatusCode::ACCEPTED)
+}
+
+pub async fn register_user(user_request: UserRequest, db_pool: DBPool, config: Arc<Config>,
+                           notifier: Arc<dyn Notifier>) -> Result<impl Reply> {
+    let (created_user, verification_token) = db::create_user_and_verification_token(&db_pool, user_request,
+                                                                                    chrono::Duration::minutes(config.verification_token_lifetime_minutes))
+        .await
+        .map_err(|e| reject::custom(e))?;
+    notifier.send(notification::registration_notification(&config.app_base_url, &created_user, &verification_token))
         .await
         .map_err(|e| reject::custom(e))?;
     let dto: UserDto = created_user.into();
-    notification::send_registration_email(&verification_token)
-        .map_err(|e| reject::custom(NotificationError))?;
     Ok(json(
         &dto
     ))
 }
 
-pub async fn get_tasks(pageable: Pageable, db_pool: DBPool,
-                       user_id: i32) -> Result<impl Reply> {
-    let found_tasks = db::find_tasks(&db_pool, pageable, user_id)
+pub async fn confirm_registration(confirmation: ConfirmationQuery, db_pool: DBPool) -> Result<impl Reply> {
+    let user = db::confirm_registration(&db_pool, &confirmation.token)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let dto: UserDto = user.into();
+    Ok(json(&dto))
+}
+
+/// Always answers `202 Accepted` so the endpoint does not reveal which emails are registered.
+pub async fn resend_verification(request: ResendVerificationRequest, db_pool: DBPool,
+                                 config: Arc<Config>, notifier: Arc<dyn Notifier>) -> Result<impl Reply> {
+    let renewed = db::renew_verification_token(&db_pool, &request.email,
+                                               chrono::Duration::minutes(config.verification_token_lifetime_minutes))
+        .await
+        .map_err(|e| reject::custom(e))?;
+    if let Some((user, verification_token)) = renewed {
+        notifier.send(notification::registration_notification(&config.app_base_url, &user, &verification_token))
+            .await
+            .map_err(|e| reject::custom(e))?;
+    }
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Always answers `202 Accepted` so the endpoint does not reveal which emails are registered.
+pub async fn forgot_password(request: ForgotPasswordRequest, db_pool: DBPool,
+                             config: Arc<Config>, notifier: Arc<dyn Notifier>) -> Result<impl Reply> {
+    let created = db::create_password_reset_token(&db_pool, &request.email,
+                                                  chrono::Duration::minutes(config.password_reset_token_lifetime_minutes))
+        .await
+        .map_err(|e| reject::custom(e))?;
+    if let Some((user, token, expiry_date)) = created {
+        notifier.send(notification::password_reset_notification(&user, &token, expiry_date))
+            .await
+            .map_err(|e| reject::custom(e))?;
+    }
+    Ok(StatusCode::ACCEPTED)
+}
+
+pub async fn reset_password(request: ResetPasswordRequest, db_pool: DBPool) -> Result<impl Reply> {
+    db::reset_password(&db_pool, &request.token, &request.pwd)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(StatusCode::OK)
+}
+
+pub async fn get_profile(db_pool: DBPool, user_id: i32) -> Result<impl Reply> {
+    let user = db::find_user_by_id(&db_pool, user_id)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let dto: UserDto = user.into();
+    Ok(json(&dto))
+}
+
+pub async fn update_profile(request: ProfileUpdateRequest, db_pool: DBPool, user_id: i32) -> Result<impl Reply> {
+    let user = db::update_profile(&db_pool, user_id, request)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let dto: UserDto = user.into();
+    Ok(json(&dto))
+}
+
+/// Signs the user out everywhere and answers with a fresh session for the caller.
+pub async fn change_password(request: PasswordChangeRequest, db_pool: DBPool, jwt_keys: Arc<JwtKeys>,
+                             config: Arc<Config>, user_id: i32) -> Result<impl Reply> {
+    let user = db::find_user_by_id(&db_pool, user_id)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    check_password(&user, &request.current_pwd)?;
+    let user = db::update_password(&db_pool, user_id, &request.new_pwd)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let login_response = issue_tokens(&user, &db_pool, &jwt_keys, &config).await?;
+    Ok(json(&login_response))
+}
+
+pub async fn request_email_change(request: EmailChangeRequest, db_pool: DBPool, config: Arc<Config>,
+                                  notifier: Arc<dyn Notifier>, user_id: i32) -> Result<impl Reply> {
+    let user = db::find_user_by_id(&db_pool, user_id)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    check_password(&user, &request.pwd)?;
+    let (token, expiry_date) = db::create_email_change_token(&db_pool, user_id, &request.email,
+                                                             chrono::Duration::minutes(config.verification_token_lifetime_minutes))
+        .await
+        .map_err(|e| reject::custom(e))?;
+    notifier.send(notification::email_change_notification(&config.app_base_url, &user, &request.email,
+                                                          &token, expiry_date))
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(StatusCode::ACCEPTED)
+}
+
+pub async fn confirm_email_change(confirmation: ConfirmationQuery, db_pool: DBPool) -> Result<impl Reply> {
+    let user = db::confirm_email_change(&db_pool, &confirmation.token)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let dto: UserDto = user.into();
+    Ok(json(&dto))
+}
+
+pub async fn create_access_token(request: AccessTokenRequest, db_pool: DBPool, user_id: i32) -> Result<impl Reply> {
+    if request.scopes.is_empty() || request.scopes.iter().any(|scope| Scope::parse(scope).is_none()) {
+        return Err(reject::custom(InvalidScopeError));
+    }
+    if request.expires_in_days.is_some_and(|days| !(1..=MAX_ACCESS_TOKEN_DAYS).contains(&days)) {
+        return Err(reject::custom(InvalidTokenLifetimeError));
+    }
+    let created = db::create_access_token(&db_pool, user_id, request)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(json(&created))
+}
+
+pub async fn get_access_tokens(db_pool: DBPool, user_id: i32) -> Result<impl Reply> {
+    let tokens = db::find_access_tokens(&db_pool, user_id)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(json(&tokens))
+}
+
+pub async fn delete_access_token(token_id: i32, db_pool: DBPool, user_id: i32) -> Result<impl Reply> {
+    db::delete_access_token(&db_pool, user_id, token_id)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(StatusCode::OK)
+}
+
+/// Sends the browser to the OpenID Connect provider to log in there.
+pub async fn oidc_login(db_pool: DBPool, config: Arc<Config>) -> Result<impl Reply> {
+    let provider = oidc::discover(&config)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let (state, oidc_state) = db::create_oidc_state(&db_pool, None,
+                                                    chrono::Duration::minutes(config.oidc_login_lifetime_minutes))
+        .await
+        .map_err(|e| reject::custom(e))?;
+    redirect_to_provider(&provider, &config, &state, &oidc_state)
+}
+
+/// Where the provider sends the browser back to. A browser login gets the usual tokens,
+/// a device login is completed for the client that polls for it.
+pub async fn oidc_callback(query: OidcCallbackQuery, client_ip: String, db_pool: DBPool,
+                           jwt_keys: Arc<JwtKeys>, config: Arc<Config>) -> Result<Response> {
+    let oidc_state = db::take_oidc_state(&db_pool, &query.state)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    if let Some(error) = query.error {
+        return Err(reject::custom(OidcLoginRefusedError(error)));
+    }
+    let code = query.code.ok_or_else(|| reject::custom(OidcStateError))?;
+    let provider = oidc::discover(&config)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let claims = provider.exchange_code(&config, &code, &oidc_state.code_verifier, &oidc_state.nonce)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let user = db::find_or_create_oidc_user(&db_pool, &claims, config.oidc_auto_provision)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    if !user.enabled {
+        db::record_login_attempt(&db_pool, Some(user.id), &user.email, &client_ip, false, Some(lockout::NOT_ENABLED))
+            .await
+            .map_err(|e| reject::custom(e))?;
+        return Err(reject::custom(UserNotEnabledError));
+    }
+    db::record_login_attempt(&db_pool, Some(user.id), &user.email, &client_ip, true, None)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    match oidc_state.device_code_id {
+        Some(device_code_id) => {
+            db::authorize_device_code(&db_pool, device_code_id, user.id)
+                .await
+                .map_err(|e| reject::custom(e))?;
+            Ok(warp::reply::html(DEVICE_LOGIN_HTML).into_response())
+        }
+        None => {
+            let login_response = issue_tokens(&user, &db_pool, &jwt_keys, &config).await?;
+            Ok(json(&login_response).into_response())
+        }
+    }
+}
+
+pub async fn oidc_device_start(db_pool: DBPool, config: Arc<Config>) -> Result<impl Reply> {
+    if config.oidc_issuer.is_none() {
+        return Err(reject::custom(OidcNotConfiguredError));
+    }
+    let lifetime = chrono::Duration::minutes(config.oidc_login_lifetime_minutes);
+    let (device_code, user_code, _) = db::create_device_code(&db_pool, lifetime)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let verification_uri = format!("{}/oidc/device", config.app_base_url.trim_end_matches('/'));
+    let verification_uri_complete = format!("{}?user_code={}", verification_uri, user_code);
+    Ok(json(&DeviceAuthorization {
+        device_code,
+        user_code,
+        verification_uri,
+        verification_uri_complete,
+        expires_in: lifetime.num_seconds(),
+        interval: DEVICE_POLL_INTERVAL_SECONDS,
+    }))
+}
+
+/// Opened in the browser with the code the client shows, continues with the provider login.
+pub async fn oidc_device_verify(query: DeviceVerificationQuery, db_pool: DBPool,
+                                config: Arc<Config>) -> Result<impl Reply> {
+    let device_code = db::find_device_code(&db_pool, &query.user_code)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let provider = oidc::discover(&config)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let (state, oidc_state) = db::create_oidc_state(&db_pool, Some(device_code.id),
+                                                    chrono::Duration::minutes(config.oidc_login_lifetime_minutes))
+        .await
+        .map_err(|e| reject::custom(e))?;
+    redirect_to_provider(&provider, &config, &state, &oidc_state)
+}
+
+/// Polled by the client, answers `202 Accepted` until the login was completed in the browser.
+pub async fn oidc_device_token(request: DeviceTokenRequest, db_pool: DBPool,
+                               jwt_keys: Arc<JwtKeys>, config: Arc<Config>) -> Result<Response> {
+    let user_id = match db::take_device_code(&db_pool, &request.device_code)
+        .await
+        .map_err(|e| reject::custom(e))? {
+        Some(user_id) => user_id,
+        None => return Ok(StatusCode::ACCEPTED.into_response()),
+    };
+    let user = db::find_user_by_id(&db_pool, user_id)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    if !user.enabled {
+        return Err(reject::custom(UserNotEnabledError));
+    }
+    let login_response = issue_tokens(&user, &db_pool, &jwt_keys, &config).await?;
+    Ok(json(&login_response).into_response())
+}
+
+fn redirect_to_provider(provider: &oidc::Provider, config: &Config, state: &str,
+                        oidc_state: &OidcState) -> Result<impl Reply> {
+    let url = provider.authorization_url(config, state, &oidc_state.nonce, &oidc_state.code_verifier)
+        .map_err(|e| reject::custom(e))?;
+    let uri: Uri = url.parse()
+        .map_err(|_| reject::custom(OidcProviderError))?;
+    Ok(warp::redirect::found(uri))
+}
+
+/// Starts over with a new secret and recovery codes, as long as two-factor authentication is off.
+pub async fn enroll_two_factor(db_pool: DBPool, config: Arc<Config>, user_id: i32) -> Result<impl Reply> {
+    let user = db::find_user_by_id(&db_pool, user_id)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let secret = totp::generate_secret();
+    let recovery_codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| auth::generate_recovery_code())
+        .collect();
+    db::start_two_factor_enrollment(&db_pool, user_id, &secret, &recovery_codes)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let otpauth_uri = totp::otpauth_uri(&config.totp_issuer, &user.email, &secret);
+    Ok(json(&TwoFactorEnrollment { secret, otpauth_uri, recovery_codes }))
+}
+
+pub async fn confirm_two_factor(request: TwoFactorCodeRequest, db_pool: DBPool, user_id: i32) -> Result<impl Reply> {
+    db::enable_two_factor(&db_pool, user_id, &request.code)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(StatusCode::OK)
+}
+
+pub async fn disable_two_factor(request: TwoFactorDisableRequest, db_pool: DBPool, user_id: i32) -> Result<impl Reply> {
+    let user = db::find_user_by_id(&db_pool, user_id)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    check_password(&user, &request.pwd)?;
+    db::disable_two_factor(&db_pool, user_id)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(StatusCode::OK)
+}
+
+fn check_password(user: &User, pwd: &str) -> Result<()> {
+    let is_right_pwd = bcrypt::verify(pwd, &user.pwd)
+        .map_err(|e| reject::custom(VerifyPasswordError(e)))?;
+    if !is_right_pwd {
+        return Err(reject::custom(WrongCredentialsError));
+    }
+    Ok(())
+}
+
+pub async fn get_tasks(pageable: Pageable, task_filter: TaskFilter, db_pool: DBPool,
+                       organization: Option<i32>, user_id: i32) -> Result<impl Reply> {
+    let found_tasks = db::find_tasks(&db_pool, pageable, task_filter, user_id, organization)
         .await
         .map_err(|e| reject::custom(e))?;
     Ok(json(&found_tasks))
 }
 
 pub async fn create_task(task_request: TaskRequest, db_pool: DBPool,
-                         user_id: i32) -> Result<impl Reply> {
+                         organization: Option<i32>, user_id: i32) -> Result<impl Reply> {
+    access::authorize_project(&db_pool, user_id, organization, task_request.project_id, ProjectAction::CreateTask)
+        .await
+        .map_err(|e| reject::custom(e))?;
     let created_task = db::create_task(db_pool, task_request, user_id).await
         .map_err(|e| reject::custom(e))?;
     Ok(json(&created_task))
 }
 
-pub async fn delete_task(task_id: i32, db_pool: DBPool, user_id: i32) -> Result<impl Reply> {
-    db::delete_task(db_pool, task_id, user_id)
+pub async fn delete_task(task_id: i32, db_pool: DBPool,
+                         organization: Option<i32>, user_id: i32) -> Result<impl Reply> {
+    access::authorize_task(&db_pool, user_id, organization, task_id, TaskAction::Delete)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    db::delete_task(db_pool, task_id)
         .await
         .map_err(|e| reject::custom(e))?;
     Ok(StatusCode::OK)
 }
 
-pub async fn get_projects(pageable: Pageable, db_pool: DBPool,
-                          user_id: i32) -> Result<impl Reply> {
-    let found_tasks = db::find_projects(&db_pool, pageable, user_id).await
+/// The task with its subtasks and how many of them are done.
+pub async fn get_task_tree(task_id: i32, db_pool: DBPool,
+                           organization: Option<i32>, user_id: i32) -> Result<impl Reply> {
+    access::authorize_task(&db_pool, user_id, organization, task_id, TaskAction::Read)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let tree = db::find_task_tree(&db_pool, task_id)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(json(&tree))
+}
+
+pub async fn update_task_parent(task_id: i32, request: TaskParentRequest, db_pool: DBPool,
+                                organization: Option<i32>, user_id: i32) -> Result<impl Reply> {
+    access::authorize_task(&db_pool, user_id, organization, task_id, TaskAction::Update)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let task = db::update_task_parent(&db_pool, task_id, request.parent_id)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(json(&task))
+}
+
+/// Changes the task, moving it also needs the right to create tasks in the target project.
+pub async fn update_task(task_id: i32, request: TaskUpdateRequest, db_pool: DBPool,
+                         organization: Option<i32>, user_id: i32) -> Result<impl Reply> {
+    access::authorize_task(&db_pool, user_id, organization, task_id, TaskAction::Update)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    if let Some(project_id) = request.project_id {
+        access::authorize_project(&db_pool, user_id, organization, project_id, ProjectAction::CreateTask)
+            .await
+            .map_err(|e| reject::custom(e))?;
+    }
+    let task = db::update_task(&db_pool, task_id, request)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(json(&task))
+}
+
+pub async fn update_task_status(task_id: i32, status_request: TaskStatusChangeRequest,
+                                db_pool: DBPool, organization: Option<i32>, user_id: i32) -> Result<impl Reply> {
+    access::authorize_task(&db_pool, user_id, organization, task_id, TaskAction::Update)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let updated_task = db::update_task_status(&db_pool, task_id, &status_request.status)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(json(&updated_task))
+}
+
+pub async fn assign_task(task_id: i32, assignee_request: AssigneeRequest,
+                         db_pool: DBPool, organization: Option<i32>, user_id: i32) -> Result<impl Reply> {
+    let task_access = access::authorize_task(&db_pool, user_id, organization, task_id, TaskAction::Update)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let task = db::assign_task(&db_pool, task_id, task_access.project_id, assignee_request.user_id)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(json(&task))
+}
+
+pub async fn unassign_task(task_id: i32, assignee_id: i32,
+                           db_pool: DBPool, organization: Option<i32>, user_id: i32) -> Result<impl Reply> {
+    access::authorize_task(&db_pool, user_id, organization, task_id, TaskAction::Update)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let task = db::unassign_task(&db_pool, task_id, assignee_id)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(json(&task))
+}
+
+pub async fn get_projects(pageable: Pageable, project_filter: ProjectFilter, db_pool: DBPool,
+                          organization: Option<i32>, user_id: i32) -> Result<impl Reply> {
+    let found_tasks = db::find_projects(&db_pool, pageable, project_filter, user_id, organization).await
         .map_err(|e| reject::custom(e))?;
     Ok(json(&found_tasks))
 }
 
 pub async fn create_project(project_request: ProjectRequest,
-                            db_pool: DBPool, user_id: i32) -> Result<impl Reply> {
-    let created_project = db::create_project(db_pool, project_request, user_id).await
+                            db_pool: DBPool, organization: Option<i32>, user_id: i32) -> Result<impl Reply> {
+    let organization_id = db::find_current_organization(&db_pool, user_id, organization)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let created_project = db::create_project(db_pool, project_request, user_id, organization_id).await
         .map_err(|e| reject::custom(e))?;
     Ok(json(&created_project))
 }
 
-pub async fn delete_project(project_id: i32, db_pool: DBPool, user_id: i32) -> Result<impl Reply> {
-    db::delete_project(db_pool, project_id, user_id)
+pub async fn get_project(project_id: i32, db_pool: DBPool,
+                         organization: Option<i32>, user_id: i32) -> Result<impl Reply> {
+    access::authorize_project(&db_pool, user_id, organization, project_id, ProjectAction::Read)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let project = db::find_project_details(&db_pool, project_id)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(json(&project))
+}
+
+pub async fn update_project(project_id: i32, request: ProjectUpdateRequest, db_pool: DBPool,
+                            organization: Option<i32>, user_id: i32) -> Result<impl Reply> {
+    access::authorize_project(&db_pool, user_id, organization, project_id, ProjectAction::Update)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let project = db::update_project(&db_pool, project_id, request)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(json(&project))
+}
+
+/// Hides the project from the project list, its tasks stay as they are.
+pub async fn archive_project(project_id: i32, db_pool: DBPool,
+                             organization: Option<i32>, user_id: i32) -> Result<impl Reply> {
+    set_project_archived(project_id, true, db_pool, organization, user_id).await
+}
+
+pub async fn unarchive_project(project_id: i32, db_pool: DBPool,
+                               organization: Option<i32>, user_id: i32) -> Result<impl Reply> {
+    set_project_archived(project_id, false, db_pool, organization, user_id).await
+}
+
+async fn set_project_archived(project_id: i32, archived: bool, db_pool: DBPool,
+                              organization: Option<i32>, user_id: i32) -> Result<impl Reply> {
+    access::authorize_project(&db_pool, user_id, organization, project_id, ProjectAction::Update)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let project = db::set_project_archived(&db_pool, project_id, archived)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(json(&project))
+}
+
+/// Deleted tasks and projects of the user that can still be restored.
+pub async fn get_trash(db_pool: DBPool, organization: Option<i32>, user_id: i32) -> Result<impl Reply> {
+    let trash = db::find_trash(&db_pool, user_id, organization)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(json(&trash))
+}
+
+/// Restoring takes the same permission as deleting.
+pub async fn restore_task(task_id: i32, db_pool: DBPool,
+                          organization: Option<i32>, user_id: i32) -> Result<impl Reply> {
+    access::authorize_trashed_task(&db_pool, user_id, organization, task_id, TaskAction::Delete)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let task = db::restore_task(&db_pool, task_id)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(json(&task))
+}
+
+pub async fn restore_project(project_id: i32, db_pool: DBPool,
+                             organization: Option<i32>, user_id: i32) -> Result<impl Reply> {
+    access::authorize_trashed_project(&db_pool, user_id, organization, project_id, ProjectAction::Delete)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let project = db::restore_project(&db_pool, project_id)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(json(&project))
+}
+
+/// Deletes the project, one that still has tasks only with an explicit strategy for them.
+pub async fn delete_project(project_id: i32, query: ProjectDeleteQuery, db_pool: DBPool,
+                            organization: Option<i32>, user_id: i32) -> Result<impl Reply> {
+    access::authorize_project(&db_pool, user_id, organization, project_id, ProjectAction::Delete)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let strategy = query.strategy.unwrap_or(ProjectDeleteStrategy::Refuse);
+    if let (ProjectDeleteStrategy::Move, Some(move_to)) = (strategy, query.move_to) {
+        access::authorize_project(&db_pool, user_id, organization, move_to, ProjectAction::CreateTask)
+            .await
+            .map_err(|e| reject::custom(e))?;
+    }
+    db::delete_project(db_pool, project_id, strategy, query.move_to)
         .await
         .map_err(|e| reject::custom(e))?;
     Ok(StatusCode::OK)
 }
 
 
+
+
+pub async fn get_project_members(project_id: i32, db_pool: DBPool,
+                                 organization: Option<i32>, user_id: i32) -> Result<impl Reply> {
+    access::authorize_project(&db_pool, user_id, organization, project_id, ProjectAction::Read)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let members = db::find_project_members(&db_pool, project_id)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(json(&members))
+}
+
+/// Invites with the given role, nobody hands out more than they have and ownership is only
+/// ever transferred.
+pub async fn invite_to_project(project_id: i32, request: InvitationRequest, db_pool: DBPool,
+                               config: Arc<Config>, notifier: Arc<dyn Notifier>,
+                               organization: Option<i32>, user_id: i32) -> Result<impl Reply> {
+    let access = access::authorize_project(&db_pool, user_id, organization, project_id, ProjectAction::ManageMembers)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let role = match request.role.as_deref() {
+        Some(role) => parse_member_role(role)?,
+        None => ProjectRole::Member,
+    };
+    if access.role < Some(role) {
+        return Err(reject::custom(NoPermissionError));
+    }
+    let inviter = db::find_user_by_id(&db_pool, user_id)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let (invitation, token) = db::create_project_invitation(&db_pool, project_id, request.email.trim(), role, user_id,
+                                                            chrono::Duration::days(config.project_invitation_lifetime_days))
+        .await
+        .map_err(|e| reject::custom(e))?;
+    notifier.send(notification::project_invitation_notification(&inviter, &invitation, &token))
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(json(&invitation))
+}
+
+/// Removes a member, everybody may remove themselves, see [`leave_project`].
+pub async fn remove_project_member(project_id: i32, member_id: i32, db_pool: DBPool,
+                                   organization: Option<i32>, user_id: i32) -> Result<impl Reply> {
+    if member_id != user_id {
+        authorize_member_management(&db_pool, project_id, member_id, organization, user_id).await?;
+    } else {
+        access::authorize_project(&db_pool, user_id, organization, project_id, ProjectAction::Read)
+            .await
+            .map_err(|e| reject::custom(e))?;
+    }
+    db::remove_project_member(&db_pool, project_id, member_id)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn update_member_role(project_id: i32, member_id: i32, request: MemberRoleRequest,
+                                db_pool: DBPool, organization: Option<i32>, user_id: i32) -> Result<impl Reply> {
+    let role = parse_member_role(&request.role)?;
+    let access = authorize_member_management(&db_pool, project_id, member_id, organization, user_id).await?;
+    if access.role < Some(role) {
+        return Err(reject::custom(NoPermissionError));
+    }
+    let member = db::update_member_role(&db_pool, project_id, member_id, role)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(json(&member))
+}
+
+/// Hands the project over to another member, the current owner becomes a maintainer.
+pub async fn transfer_project(project_id: i32, request: ProjectTransferRequest,
+                              db_pool: DBPool, organization: Option<i32>, user_id: i32) -> Result<impl Reply> {
+    access::authorize_project(&db_pool, user_id, organization, project_id, ProjectAction::Transfer)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let member = db::transfer_project(&db_pool, project_id, user_id, request.user_id)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(json(&member))
+}
+
+/// Maintainers manage the members up to their own role, nobody outranks the one managing them.
+async fn authorize_member_management(db_pool: &DBPool, project_id: i32, member_id: i32,
+                                     organization: Option<i32>, user_id: i32) -> Result<ProjectAccess> {
+    let access = access::authorize_project(db_pool, user_id, organization, project_id, ProjectAction::ManageMembers)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let member = db::find_project_access(db_pool, project_id, member_id, Some(access.organization_id), false)
+        .await
+        .map_err(|e| reject::custom(e))?
+        .and_then(|member| member.role)
+        .ok_or_else(|| reject::custom(NotFoundError))?;
+    if access.role < Some(member) {
+        return Err(reject::custom(NoPermissionError));
+    }
+    Ok(access)
+}
+
+/// Parses a role that can be given to a member directly, which excludes `owner`.
+fn parse_member_role(role: &str) -> Result<ProjectRole> {
+    match ProjectRole::parse(role.trim()) {
+        Some(ProjectRole::Owner) | None => Err(reject::custom(InvalidProjectRoleError)),
+        Some(role) => Ok(role),
+    }
+}
+
+pub async fn leave_project(project_id: i32, db_pool: DBPool,
+                           organization: Option<i32>, user_id: i32) -> Result<impl Reply> {
+    remove_project_member(project_id, user_id, db_pool, organization, user_id).await
+}
+
+pub async fn get_invitations(db_pool: DBPool, user_id: i32) -> Result<impl Reply> {
+    let invitations = db::find_user_invitations(&db_pool, user_id)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(json(&invitations))
+}
+
+pub async fn accept_invitation(request: InvitationTokenRequest, db_pool: DBPool, user_id: i32) -> Result<impl Reply> {
+    let project = db::accept_project_invitation(&db_pool, request.token.trim(), user_id)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(json(&project))
+}
+
+/// Needs no login, the invitation may be for somebody who does not want to register at all.
+pub async fn decline_invitation(request: InvitationTokenRequest, db_pool: DBPool) -> Result<impl Reply> {
+    db::decline_project_invitation(&db_pool, request.token.trim())
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn get_organizations(db_pool: DBPool, user_id: i32) -> Result<impl Reply> {
+    let organizations = db::find_organizations(&db_pool, user_id)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(json(&organizations))
+}
+
+pub async fn create_organization(request: OrganizationRequest, db_pool: DBPool, user_id: i32) -> Result<impl Reply> {
+    let organization = db::create_organization(&db_pool, user_id, request.name.trim())
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(json(&organization))
+}
+
+pub async fn get_organization_members(organization_id: i32, db_pool: DBPool, user_id: i32) -> Result<impl Reply> {
+    access::authorize_organization(&db_pool, user_id, organization_id, OrganizationAction::Read)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let members = db::find_organization_members(&db_pool, organization_id)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(json(&members))
+}
+
+pub async fn add_organization_member(organization_id: i32, request: OrganizationMemberRequest,
+                                     db_pool: DBPool, user_id: i32) -> Result<impl Reply> {
+    access::authorize_organization(&db_pool, user_id, organization_id, OrganizationAction::ManageMembers)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let role = match request.role.as_deref() {
+        Some(role) => parse_organization_role(role)?,
+        None => OrganizationRole::Member,
+    };
+    let member = db::add_organization_member(&db_pool, organization_id, request.email.trim(), role)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(json(&member))
+}
+
+pub async fn update_organization_member_role(organization_id: i32, member_id: i32, request: MemberRoleRequest,
+                                             db_pool: DBPool, user_id: i32) -> Result<impl Reply> {
+    access::authorize_organization(&db_pool, user_id, organization_id, OrganizationAction::ManageMembers)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let role = parse_organization_role(&request.role)?;
+    let member = db::update_organization_member_role(&db_pool, organization_id, member_id, role)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(json(&member))
+}
+
+/// Removes a member from the organization and its projects, everybody may remove themselves.
+pub async fn remove_organization_member(organization_id: i32, member_id: i32,
+                                        db_pool: DBPool, user_id: i32) -> Result<impl Reply> {
+    let action = if member_id == user_id { OrganizationAction::Read } else { OrganizationAction::ManageMembers };
+    access::authorize_organization(&db_pool, user_id, organization_id, action)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    db::remove_organization_member(&db_pool, organization_id, member_id)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn parse_organization_role(role: &str) -> Result<OrganizationRole> {
+    OrganizationRole::parse(role.trim())
+        .ok_or_else(|| reject::custom(InvalidOrganizationRoleError))
+}
+
+pub async fn get_workflow(project_id: i32, db_pool: DBPool,
+                          organization: Option<i32>, user_id: i32) -> Result<impl Reply> {
+    access::authorize_project(&db_pool, user_id, organization, project_id, ProjectAction::Read)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let workflow = db::find_workflow(&db_pool, project_id)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(json(&workflow))
+}
+
+pub async fn update_workflow(project_id: i32, workflow_request: WorkflowRequest,
+                             db_pool: DBPool, organization: Option<i32>, user_id: i32) -> Result<impl Reply> {
+    access::authorize_project(&db_pool, user_id, organization, project_id, ProjectAction::ManageWorkflow)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    let workflow = db::update_workflow(&db_pool, project_id, workflow_request)
+        .await
+        .map_err(|e| reject::custom(e))?;
+    Ok(json(&workflow))
+}
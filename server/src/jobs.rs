@@ -0,0 +1,45 @@
+use std::time::Duration;
+
+use crate::{db, DBPool};
+
+/// Removes expired verification, password reset and email change tokens, two-factor challenges,
+/// provider logins and project invitations in the background every `period`.
+pub fn spawn_token_cleanup(db_pool: DBPool, period: Duration) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            if let Err(e) = db::delete_expired_verification_tokens(&db_pool).await {
+                eprintln!("could not delete expired verification tokens: {}", e);
+            }
+            if let Err(e) = db::delete_expired_password_reset_tokens(&db_pool).await {
+                eprintln!("could not delete expired password reset tokens: {}", e);
+            }
+            if let Err(e) = db::delete_expired_email_change_tokens(&db_pool).await {
+                eprintln!("could not delete expired email change tokens: {}", e);
+            }
+            if let Err(e) = db::delete_expired_two_factor_challenges(&db_pool).await {
+                eprintln!("could not delete expired two-factor challenges: {}", e);
+            }
+            if let Err(e) = db::delete_expired_oidc_logins(&db_pool).await {
+                eprintln!("could not delete expired OpenID Connect logins: {}", e);
+            }
+            if let Err(e) = db::delete_expired_project_invitations(&db_pool).await {
+                eprintln!("could not delete expired project invitations: {}", e);
+            }
+        }
+    });
+}
+
+/// Purges tasks and projects that have been in the trash for longer than `retention` every `period`.
+pub fn spawn_trash_purge(db_pool: DBPool, period: Duration, retention: chrono::Duration) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            if let Err(e) = db::purge_trash(&db_pool, retention).await {
+                eprintln!("could not purge the trash: {}", e);
+            }
+        }
+    });
+}
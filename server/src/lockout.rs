@@ -0,0 +1,110 @@
+use chrono::{DateTime, Utc};
+
+use crate::{db, DBPool, error};
+use crate::config::Config;
+use crate::data::LoginFailures;
+use crate::error::Error::TooManyLoginAttemptsError;
+
+type Result<T> = std::result::Result<T, error::Error>;
+
+/// Why a login attempt was recorded as failed, only wrong credentials count towards a lockout.
+pub const WRONG_CREDENTIALS: &str = "wrong_credentials";
+pub const NOT_ENABLED: &str = "not_enabled";
+pub const LOCKED: &str = "locked";
+/// The password was right, the login waits for the second factor.
+pub const SECOND_FACTOR: &str = "second_factor";
+
+/// Checks whether the email or the client address may try to log in now.
+///
+/// Every failed attempt doubles the wait before the next one, starting with a second. Once
+/// the threshold is reached the account, or the address, is locked for the lockout period.
+/// The error carries the number of seconds until the next attempt is allowed.
+pub async fn check_login_allowed(db_pool: &DBPool, config: &Config, email: &str, ip: &str) -> Result<()> {
+    let lockout = chrono::Duration::minutes(config.login_lockout_minutes);
+    let failures = db::count_login_failures(db_pool, email, ip, lockout).await?;
+    match retry_at(&failures, config, lockout) {
+        Some(retry_at) if retry_at > Utc::now() => {
+            let seconds = (retry_at - Utc::now()).num_seconds() + 1;
+            Err(TooManyLoginAttemptsError(seconds))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn retry_at(failures: &LoginFailures, config: &Config, lockout: chrono::Duration) -> Option<DateTime<Utc>> {
+    let account = failures.account_last.map(|last| {
+        if failures.account >= config.login_max_failures {
+            last + lockout
+        } else {
+            last + delay(failures.account)
+        }
+    });
+    let ip = failures.ip_last
+        .filter(|_| failures.ip >= config.login_ip_max_failures)
+        .map(|last| last + lockout);
+    account.max(ip)
+}
+
+fn delay(failures: i64) -> chrono::Duration {
+    if failures == 0 {
+        return chrono::Duration::zero();
+    }
+    chrono::Duration::seconds(1 << (failures - 1).min(10))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+
+    fn config() -> Config {
+        let mut config = config::from_env();
+        config.login_max_failures = 5;
+        config.login_ip_max_failures = 20;
+        config.login_lockout_minutes = 15;
+        config
+    }
+
+    fn failures(account: i64, ip: i64, last: DateTime<Utc>) -> LoginFailures {
+        LoginFailures {
+            account,
+            account_last: Some(last).filter(|_| account > 0),
+            ip,
+            ip_last: Some(last).filter(|_| ip > 0),
+        }
+    }
+
+    #[test]
+    fn first_attempt_is_allowed() {
+        let config = config();
+        assert_eq!(retry_at(&failures(0, 0, Utc::now()), &config, chrono::Duration::minutes(15)), None);
+    }
+
+    #[test]
+    fn delay_doubles_with_every_failure() {
+        let config = config();
+        let last = Utc::now();
+        let lockout = chrono::Duration::minutes(15);
+        for (count, seconds) in [(1, 1), (2, 2), (3, 4), (4, 8)] {
+            let expected = last + chrono::Duration::seconds(seconds);
+            assert_eq!(retry_at(&failures(count, count, last), &config, lockout), Some(expected));
+        }
+    }
+
+    #[test]
+    fn account_is_locked_at_the_threshold() {
+        let config = config();
+        let last = Utc::now();
+        let lockout = chrono::Duration::minutes(15);
+        assert_eq!(retry_at(&failures(5, 5, last), &config, lockout), Some(last + lockout));
+    }
+
+    #[test]
+    fn address_is_locked_across_accounts() {
+        let config = config();
+        let last = Utc::now();
+        let lockout = chrono::Duration::minutes(15);
+        assert_eq!(retry_at(&failures(0, 20, last), &config, lockout), Some(last + lockout));
+        assert_eq!(retry_at(&failures(0, 19, last), &config, lockout), None);
+    }
+}
@@ -1,21 +1,34 @@
 use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
 
 use mobc::Pool;
 use mobc_postgres::PgConnectionManager;
 use mobc_postgres::tokio_postgres::NoTls;
-use warp::{Filter, Rejection};
+use warp::{Filter, reject, Rejection, Reply};
 
-use crate::auth::Role;
+use crate::auth::{JwtKeys, Role, Scope};
+use crate::config::Config;
+use crate::error::Error;
+use crate::notification::Notifier;
 
+mod access;
 mod auth;
 mod db;
 mod error;
 mod handler;
+mod jobs;
+mod lockout;
 mod embedded;
 mod migrations;
 mod config;
 mod data;
 mod notification;
+mod oidc;
+mod totp;
+#[cfg(test)]
+mod tests;
 
 type Result<T> = std::result::Result<T, Rejection>;
 type DBPool = Pool<PgConnectionManager<NoTls>>;
@@ -24,93 +37,723 @@ type DBPool = Pool<PgConnectionManager<NoTls>>;
 async fn main() {
     let config = config::from_env();
     let db_pool = db::create_pool(&config).unwrap();
+    let jwt_keys = Arc::new(JwtKeys::from_config(&config)
+        .expect("JWT keys should be configured"));
+    let notifier = notification::from_config(&config)
+        .expect("notification settings should be valid");
+    let config = Arc::new(config);
     db::db_init(&config)
         .await
         .unwrap();
 
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() > 1 {
+        run_command(&args[1..], &db_pool).await;
+        return;
+    }
+
+    jobs::spawn_token_cleanup(db_pool.clone(),
+                              Duration::from_secs(config.token_cleanup_interval_seconds));
+    jobs::spawn_trash_purge(db_pool.clone(),
+                            Duration::from_secs(config.trash_purge_interval_seconds),
+                            chrono::Duration::days(config.trash_retention_days));
+
+    warp::serve(routes(db_pool, jwt_keys, config, notifier)).run(([127, 0, 0, 1], 8080)).await;
+}
+
+fn routes(db_pool: DBPool, jwt_keys: Arc<JwtKeys>, config: Arc<Config>, notifier: Arc<dyn Notifier>) -> impl Filter<Extract=impl Reply, Error=Infallible> + Clone {
     let registration = warp::path("registration");
     let login = warp::path("login");
+    let logout = warp::path("logout");
+    let token = warp::path("token");
+    let password = warp::path("password");
+    let oidc = warp::path("oidc");
+    let tokens = warp::path("tokens");
     let users = warp::path("users");
     let projects = warp::path("projects");
     let tasks = warp::path("tasks");
+    let invitations = warp::path("invitations");
+    let organizations = warp::path("organizations");
+    let trash = warp::path("trash");
 
     let health_route = warp::path!("health")
         .and(with_db(db_pool.clone()))
         .and_then(handler::health_handler);
 
     let registration_route = registration
+        .and(warp::path::end())
         .and(warp::post())
         .and(warp::body::json())
         .and(with_db(db_pool.clone()))
-        .and_then(handler::register_user);
+        .and(with_config(config.clone()))
+        .and(with_notifier(notifier.clone()))
+        .and_then(handler::register_user)
+        .or(registration
+            .and(warp::path("confirm"))
+            .and(warp::path::end())
+            .and(warp::get().or(warp::post()).unify())
+            .and(warp::query())
+            .and(with_db(db_pool.clone()))
+            .and_then(handler::confirm_registration))
+        .or(registration
+            .and(warp::path("resend"))
+            .and(warp::path::end())
+            .and(warp::post())
+            .and(warp::body::json())
+            .and(with_db(db_pool.clone()))
+            .and(with_config(config.clone()))
+            .and(with_notifier(notifier.clone()))
+            .and_then(handler::resend_verification));
 
     let login_route = login
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(with_client_ip(config.trust_forwarded_for))
+        .and(with_db(db_pool.clone()))
+        .and(with_jwt_keys(jwt_keys.clone()))
+        .and(with_config(config.clone()))
+        .and_then(handler::login_handler)
+        .or(login
+            .and(warp::path("2fa"))
+            .and(warp::path::end())
+            .and(warp::post())
+            .and(warp::body::json())
+            .and(with_client_ip(config.trust_forwarded_for))
+            .and(with_db(db_pool.clone()))
+            .and(with_jwt_keys(jwt_keys.clone()))
+            .and(with_config(config.clone()))
+            .and_then(handler::two_factor_login_handler));
+
+    let token_routes = token
+        .and(warp::path("refresh"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(with_db(db_pool.clone()))
+        .and(with_jwt_keys(jwt_keys.clone()))
+        .and(with_config(config.clone()))
+        .and_then(handler::refresh_handler)
+        .or(logout
+            .and(warp::path::end())
+            .and(warp::post())
+            .and(warp::body::json())
+            .and(with_db(db_pool.clone()))
+            .and_then(handler::logout_handler));
+
+    let oidc_routes = oidc
+        .and(warp::path("login"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(with_db(db_pool.clone()))
+        .and(with_config(config.clone()))
+        .and_then(handler::oidc_login)
+        .or(oidc
+            .and(warp::path("callback"))
+            .and(warp::path::end())
+            .and(warp::get())
+            .and(warp::query())
+            .and(with_client_ip(config.trust_forwarded_for))
+            .and(with_db(db_pool.clone()))
+            .and(with_jwt_keys(jwt_keys.clone()))
+            .and(with_config(config.clone()))
+            .and_then(handler::oidc_callback))
+        .or(oidc
+            .and(warp::path("device"))
+            .and(warp::path::end())
+            .and(warp::post())
+            .and(with_db(db_pool.clone()))
+            .and(with_config(config.clone()))
+            .and_then(handler::oidc_device_start))
+        .or(oidc
+            .and(warp::path("device"))
+            .and(warp::path::end())
+            .and(warp::get())
+            .and(warp::query())
+            .and(with_db(db_pool.clone()))
+            .and(with_config(config.clone()))
+            .and_then(handler::oidc_device_verify))
+        .or(oidc
+            .and(warp::path("device"))
+            .and(warp::path("token"))
+            .and(warp::path::end())
+            .and(warp::post())
+            .and(warp::body::json())
+            .and(with_db(db_pool.clone()))
+            .and(with_jwt_keys(jwt_keys.clone()))
+            .and(with_config(config.clone()))
+            .and_then(handler::oidc_device_token));
+
+    let password_routes = password
+        .and(warp::path("forgot"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(with_db(db_pool.clone()))
+        .and(with_config(config.clone()))
+        .and(with_notifier(notifier.clone()))
+        .and_then(handler::forgot_password)
+        .or(password
+            .and(warp::path("reset"))
+            .and(warp::path::end())
+            .and(warp::post())
+            .and(warp::body::json())
+            .and(with_db(db_pool.clone()))
+            .and_then(handler::reset_password));
+
+    let profile_routes = users
+        .and(warp::path("me"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(with_db(db_pool.clone()))
+        .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), None))
+        .and_then(handler::get_profile)
+        .or(users
+            .and(warp::path("me"))
+            .and(warp::path::end())
+            .and(warp::patch())
+            .and(warp::body::json())
+            .and(with_db(db_pool.clone()))
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), None))
+            .and_then(handler::update_profile))
+        .or(users
+            .and(warp::path("me"))
+            .and(warp::path("password"))
+            .and(warp::path::end())
+            .and(warp::put())
+            .and(warp::body::json())
+            .and(with_db(db_pool.clone()))
+            .and(with_jwt_keys(jwt_keys.clone()))
+            .and(with_config(config.clone()))
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), None))
+            .and_then(handler::change_password))
+        .or(users
+            .and(warp::path("me"))
+            .and(warp::path("email"))
+            .and(warp::path::end())
+            .and(warp::post())
+            .and(warp::body::json())
+            .and(with_db(db_pool.clone()))
+            .and(with_config(config.clone()))
+            .and(with_notifier(notifier.clone()))
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), None))
+            .and_then(handler::request_email_change))
+        .or(users
+            .and(warp::path("me"))
+            .and(warp::path("2fa"))
+            .and(warp::path::end())
+            .and(warp::post())
+            .and(with_db(db_pool.clone()))
+            .and(with_config(config.clone()))
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), None))
+            .and_then(handler::enroll_two_factor))
+        .or(users
+            .and(warp::path("me"))
+            .and(warp::path("2fa"))
+            .and(warp::path("confirm"))
+            .and(warp::path::end())
+            .and(warp::post())
+            .and(warp::body::json())
+            .and(with_db(db_pool.clone()))
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), None))
+            .and_then(handler::confirm_two_factor))
+        .or(users
+            .and(warp::path("me"))
+            .and(warp::path("2fa"))
+            .and(warp::path::end())
+            .and(warp::delete())
+            .and(warp::body::json())
+            .and(with_db(db_pool.clone()))
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), None))
+            .and_then(handler::disable_two_factor))
+        .or(users
+            .and(warp::path("email"))
+            .and(warp::path("confirm"))
+            .and(warp::path::end())
+            .and(warp::get().or(warp::post()).unify())
+            .and(warp::query())
+            .and(with_db(db_pool.clone()))
+            .and_then(handler::confirm_email_change));
+
+    let access_token_routes = tokens
         .and(warp::post())
+        .and(warp::path::end())
         .and(warp::body::json())
         .and(with_db(db_pool.clone()))
-        .and_then(handler::login_handler);
+        .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), None))
+        .and_then(handler::create_access_token)
+        .or(tokens
+            .and(warp::get())
+            .and(warp::path::end())
+            .and(with_db(db_pool.clone()))
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), None))
+            .and_then(handler::get_access_tokens))
+        .or(tokens
+            .and(warp::delete())
+            .and(warp::path::param())
+            .and(warp::path::end())
+            .and(with_db(db_pool.clone()))
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), None))
+            .and_then(handler::delete_access_token));
 
     let user_routes = users
         .and(warp::get())
+        .and(warp::path::end())
         .and(warp::query())
         .and(with_db(db_pool.clone()))
-        .and(auth::with_auth(vec!(Role::Admin)))
-        .and_then(handler::get_users);
+        .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::Admin), None))
+        .and_then(handler::get_users)
+        .or(users
+            .and(warp::put())
+            .and(warp::path::param())
+            .and(warp::path("role"))
+            .and(warp::path::end())
+            .and(warp::body::json())
+            .and(with_db(db_pool.clone()))
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::Admin), None))
+            .and_then(handler::update_user_role))
+        .or(users
+            .and(warp::get())
+            .and(warp::path::param())
+            .and(warp::path::end())
+            .and(with_db(db_pool.clone()))
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::Admin), None))
+            .and_then(handler::get_user))
+        .or(users
+            .and(warp::patch())
+            .and(warp::path::param())
+            .and(warp::path::end())
+            .and(warp::body::json())
+            .and(with_db(db_pool.clone()))
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::Admin), None))
+            .and_then(handler::update_user))
+        .or(users
+            .and(warp::delete())
+            .and(warp::path::param())
+            .and(warp::path::end())
+            .and(warp::query())
+            .and(with_db(db_pool.clone()))
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::Admin), None))
+            .and_then(handler::delete_user))
+        .or(users
+            .and(warp::post())
+            .and(warp::path::param())
+            .and(warp::path("password"))
+            .and(warp::path("reset"))
+            .and(warp::path::end())
+            .and(with_db(db_pool.clone()))
+            .and(with_config(config.clone()))
+            .and(with_notifier(notifier.clone()))
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::Admin), None))
+            .and_then(handler::force_password_reset));
 
     let project_routes = projects
         .and(warp::get())
+        .and(warp::path::end())
+        .and(warp::query())
         .and(warp::query())
         .and(with_db(db_pool.clone()))
-        .and(auth::with_auth(vec!(Role::User, Role::Admin)))
+        .and(with_organization())
+        .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), Some(Scope::ProjectsRead)))
         .and_then(handler::get_projects)
         .or(projects
             .and(warp::post())
+            .and(warp::path::end())
             .and(warp::body::json())
             .and(with_db(db_pool.clone()))
-            .and(auth::with_auth(vec!(Role::User, Role::Admin)))
+            .and(with_organization())
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), Some(Scope::ProjectsAdmin)))
             .and_then(handler::create_project))
         .or(projects
+            .and(warp::get())
+            .and(warp::path::param())
+            .and(warp::path::end())
+            .and(with_db(db_pool.clone()))
+            .and(with_organization())
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), Some(Scope::ProjectsRead)))
+            .and_then(handler::get_project))
+        .or(projects
+            .and(warp::patch())
+            .and(warp::path::param())
+            .and(warp::path::end())
+            .and(warp::body::json())
+            .and(with_db(db_pool.clone()))
+            .and(with_organization())
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), Some(Scope::ProjectsAdmin)))
+            .and_then(handler::update_project))
+        .or(projects
+            .and(warp::post())
+            .and(warp::path::param())
+            .and(warp::path("archive"))
+            .and(warp::path::end())
+            .and(with_db(db_pool.clone()))
+            .and(with_organization())
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), Some(Scope::ProjectsAdmin)))
+            .and_then(handler::archive_project))
+        .or(projects
+            .and(warp::post())
+            .and(warp::path::param())
+            .and(warp::path("unarchive"))
+            .and(warp::path::end())
+            .and(with_db(db_pool.clone()))
+            .and(with_organization())
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), Some(Scope::ProjectsAdmin)))
+            .and_then(handler::unarchive_project))
+        .or(projects
+            .and(warp::delete())
+            .and(warp::path::param())
+            .and(warp::path::end())
+            .and(warp::query())
+            .and(with_db(db_pool.clone()))
+            .and(with_organization())
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), Some(Scope::ProjectsAdmin)))
+            .and_then(handler::delete_project))
+        .or(projects
+            .and(warp::get())
+            .and(warp::path::param())
+            .and(warp::path("statuses"))
+            .and(warp::path::end())
+            .and(with_db(db_pool.clone()))
+            .and(with_organization())
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), Some(Scope::ProjectsRead)))
+            .and_then(handler::get_workflow))
+        .or(projects
+            .and(warp::put())
+            .and(warp::path::param())
+            .and(warp::path("statuses"))
+            .and(warp::path::end())
+            .and(warp::body::json())
+            .and(with_db(db_pool.clone()))
+            .and(with_organization())
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), Some(Scope::ProjectsAdmin)))
+            .and_then(handler::update_workflow))
+        .or(projects
+            .and(warp::get())
+            .and(warp::path::param())
+            .and(warp::path("members"))
+            .and(warp::path::end())
+            .and(with_db(db_pool.clone()))
+            .and(with_organization())
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), Some(Scope::ProjectsRead)))
+            .and_then(handler::get_project_members))
+        .or(projects
+            .and(warp::delete())
+            .and(warp::path::param())
+            .and(warp::path("members"))
+            .and(warp::path::param())
+            .and(warp::path::end())
+            .and(with_db(db_pool.clone()))
+            .and(with_organization())
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), Some(Scope::ProjectsAdmin)))
+            .and_then(handler::remove_project_member))
+        .or(projects
+            .and(warp::put())
+            .and(warp::path::param())
+            .and(warp::path("members"))
+            .and(warp::path::param())
+            .and(warp::path("role"))
+            .and(warp::path::end())
+            .and(warp::body::json())
+            .and(with_db(db_pool.clone()))
+            .and(with_organization())
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), Some(Scope::ProjectsAdmin)))
+            .and_then(handler::update_member_role))
+        .or(projects
+            .and(warp::post())
+            .and(warp::path::param())
+            .and(warp::path("transfer"))
+            .and(warp::path::end())
+            .and(warp::body::json())
+            .and(with_db(db_pool.clone()))
+            .and(with_organization())
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), Some(Scope::ProjectsAdmin)))
+            .and_then(handler::transfer_project))
+        .or(projects
+            .and(warp::post())
+            .and(warp::path::param())
+            .and(warp::path("invitations"))
+            .and(warp::path::end())
+            .and(warp::body::json())
+            .and(with_db(db_pool.clone()))
+            .and(with_config(config.clone()))
+            .and(with_notifier(notifier.clone()))
+            .and(with_organization())
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), Some(Scope::ProjectsAdmin)))
+            .and_then(handler::invite_to_project))
+        .or(projects
+            .and(warp::post())
+            .and(warp::path::param())
+            .and(warp::path("leave"))
+            .and(warp::path::end())
+            .and(with_db(db_pool.clone()))
+            .and(with_organization())
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), Some(Scope::ProjectsAdmin)))
+            .and_then(handler::leave_project));
+
+    let invitation_routes = invitations
+        .and(warp::get())
+        .and(warp::path::end())
+        .and(with_db(db_pool.clone()))
+        .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), None))
+        .and_then(handler::get_invitations)
+        .or(invitations
+            .and(warp::path("accept"))
+            .and(warp::path::end())
+            .and(warp::post())
+            .and(warp::body::json())
+            .and(with_db(db_pool.clone()))
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), None))
+            .and_then(handler::accept_invitation))
+        .or(invitations
+            .and(warp::path("decline"))
+            .and(warp::path::end())
+            .and(warp::post())
+            .and(warp::body::json())
+            .and(with_db(db_pool.clone()))
+            .and_then(handler::decline_invitation));
+
+    let organization_routes = organizations
+        .and(warp::get())
+        .and(warp::path::end())
+        .and(with_db(db_pool.clone()))
+        .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), Some(Scope::ProjectsRead)))
+        .and_then(handler::get_organizations)
+        .or(organizations
+            .and(warp::post())
+            .and(warp::path::end())
+            .and(warp::body::json())
+            .and(with_db(db_pool.clone()))
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), Some(Scope::ProjectsAdmin)))
+            .and_then(handler::create_organization))
+        .or(organizations
+            .and(warp::get())
+            .and(warp::path::param())
+            .and(warp::path("members"))
+            .and(warp::path::end())
+            .and(with_db(db_pool.clone()))
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), Some(Scope::ProjectsRead)))
+            .and_then(handler::get_organization_members))
+        .or(organizations
+            .and(warp::post())
+            .and(warp::path::param())
+            .and(warp::path("members"))
+            .and(warp::path::end())
+            .and(warp::body::json())
+            .and(with_db(db_pool.clone()))
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), Some(Scope::ProjectsAdmin)))
+            .and_then(handler::add_organization_member))
+        .or(organizations
+            .and(warp::put())
+            .and(warp::path::param())
+            .and(warp::path("members"))
+            .and(warp::path::param())
+            .and(warp::path("role"))
+            .and(warp::path::end())
+            .and(warp::body::json())
+            .and(with_db(db_pool.clone()))
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), Some(Scope::ProjectsAdmin)))
+            .and_then(handler::update_organization_member_role))
+        .or(organizations
             .and(warp::delete())
             .and(warp::path::param())
+            .and(warp::path("members"))
+            .and(warp::path::param())
+            .and(warp::path::end())
             .and(with_db(db_pool.clone()))
-            .and(auth::with_auth(vec!(Role::User, Role::Admin)))
-            .and_then(handler::delete_project));
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), Some(Scope::ProjectsAdmin)))
+            .and_then(handler::remove_organization_member));
+
+    let trash_routes = trash
+        .and(warp::get())
+        .and(warp::path::end())
+        .and(with_db(db_pool.clone()))
+        .and(with_organization())
+        .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), Some(Scope::ProjectsRead)))
+        .and_then(handler::get_trash)
+        .or(trash
+            .and(warp::post())
+            .and(warp::path("tasks"))
+            .and(warp::path::param())
+            .and(warp::path("restore"))
+            .and(warp::path::end())
+            .and(with_db(db_pool.clone()))
+            .and(with_organization())
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), Some(Scope::TasksWrite)))
+            .and_then(handler::restore_task))
+        .or(trash
+            .and(warp::post())
+            .and(warp::path("projects"))
+            .and(warp::path::param())
+            .and(warp::path("restore"))
+            .and(warp::path::end())
+            .and(with_db(db_pool.clone()))
+            .and(with_organization())
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), Some(Scope::ProjectsAdmin)))
+            .and_then(handler::restore_project));
 
     let task_routes = tasks
         .and(warp::get())
+        .and(warp::path::end())
+        .and(warp::query())
         .and(warp::query())
         .and(with_db(db_pool.clone()))
-        .and(auth::with_auth(vec!(Role::User, Role::Admin)))
+        .and(with_organization())
+        .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), Some(Scope::TasksRead)))
         .and_then(handler::get_tasks)
         .or(
             tasks
                 .and(warp::post())
+                .and(warp::path::end())
                 .and(warp::body::json())
                 .and(with_db(db_pool.clone()))
-                .and(auth::with_auth(vec!(Role::User, Role::Admin)))
+                .and(with_organization())
+                .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), Some(Scope::TasksWrite)))
                 .and_then(handler::create_task)
         )
         .or(tasks
             .and(warp::delete())
             .and(warp::path::param())
+            .and(warp::path::end())
+            .and(with_db(db_pool.clone()))
+            .and(with_organization())
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), Some(Scope::TasksWrite)))
+            .and_then(handler::delete_task))
+        .or(tasks
+            .and(warp::get())
+            .and(warp::path::param())
+            .and(warp::path("tree"))
+            .and(warp::path::end())
+            .and(with_db(db_pool.clone()))
+            .and(with_organization())
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), Some(Scope::TasksRead)))
+            .and_then(handler::get_task_tree))
+        .or(tasks
+            .and(warp::put())
+            .and(warp::path::param())
+            .and(warp::path("parent"))
+            .and(warp::path::end())
+            .and(warp::body::json())
+            .and(with_db(db_pool.clone()))
+            .and(with_organization())
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), Some(Scope::TasksWrite)))
+            .and_then(handler::update_task_parent))
+        .or(tasks
+            .and(warp::patch())
+            .and(warp::path::param())
+            .and(warp::path::end())
+            .and(warp::body::json())
+            .and(with_db(db_pool.clone()))
+            .and(with_organization())
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), Some(Scope::TasksWrite)))
+            .and_then(handler::update_task))
+        .or(tasks
+            .and(warp::patch())
+            .and(warp::path::param())
+            .and(warp::path("status"))
+            .and(warp::path::end())
+            .and(warp::body::json())
+            .and(with_db(db_pool.clone()))
+            .and(with_organization())
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), Some(Scope::TasksWrite)))
+            .and_then(handler::update_task_status))
+        .or(tasks
+            .and(warp::post())
+            .and(warp::path::param())
+            .and(warp::path("assignees"))
+            .and(warp::path::end())
+            .and(warp::body::json())
+            .and(with_db(db_pool.clone()))
+            .and(with_organization())
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), Some(Scope::TasksWrite)))
+            .and_then(handler::assign_task))
+        .or(tasks
+            .and(warp::delete())
+            .and(warp::path::param())
+            .and(warp::path("assignees"))
+            .and(warp::path::param())
+            .and(warp::path::end())
             .and(with_db(db_pool.clone()))
-            .and(auth::with_auth(vec!(Role::User, Role::Admin)))
-            .and_then(handler::delete_task));
+            .and(with_organization())
+            .and(auth::with_auth(jwt_keys.clone(), db_pool.clone(), vec!(Role::User, Role::Admin), Some(Scope::TasksWrite)))
+            .and_then(handler::unassign_task));
 
 
-    let routes = health_route
+    health_route
         .or(registration_route)
         .or(login_route)
+        .or(token_routes)
+        .or(oidc_routes)
+        .or(password_routes)
+        .or(profile_routes)
+        .or(access_token_routes)
         .or(user_routes)
         .or(task_routes)
         .or(project_routes)
+        .or(invitation_routes)
+        .or(organization_routes)
+        .or(trash_routes)
         .with(warp::cors().allow_any_origin())
-        .recover(error::handle_rejection);
+        .recover(error::handle_rejection)
+}
 
-    warp::serve(routes).run(([127, 0, 0, 1], 8080)).await;
+/// Maintenance commands, e.g. `server promote-admin admin@example.com` to bootstrap the first administrator.
+async fn run_command(args: &[String], db_pool: &DBPool) {
+    match (args[0].as_str(), args.get(1)) {
+        ("promote-admin", Some(email)) => {
+            match db::promote_to_admin(db_pool, email).await {
+                Ok(user) => println!("{} is now an administrator", user.email),
+                Err(e) => {
+                    eprintln!("could not promote {}: {}", email, e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        _ => {
+            eprintln!("usage: server [promote-admin <email>]");
+            std::process::exit(2);
+        }
+    }
 }
 
 fn with_db(db_pool: DBPool) -> impl Filter<Extract=(DBPool, ), Error=Infallible> + Clone {
     warp::any().map(move || db_pool.clone())
 }
 
+fn with_jwt_keys(jwt_keys: Arc<JwtKeys>) -> impl Filter<Extract=(Arc<JwtKeys>, ), Error=Infallible> + Clone {
+    warp::any().map(move || jwt_keys.clone())
+}
+
+fn with_config(config: Arc<Config>) -> impl Filter<Extract=(Arc<Config>, ), Error=Infallible> + Clone {
+    warp::any().map(move || config.clone())
+}
+
+fn with_notifier(notifier: Arc<dyn Notifier>) -> impl Filter<Extract=(Arc<dyn Notifier>, ), Error=Infallible> + Clone {
+    warp::any().map(move || notifier.clone())
+}
+
+/// Organization picked with the `X-Organization` header, without it requests span all
+/// organizations of the user.
+fn with_organization() -> impl Filter<Extract=(Option<i32>, ), Error=Rejection> + Clone {
+    warp::header::optional::<String>("x-organization")
+        .and_then(|organization: Option<String>| async move {
+            match organization {
+                Some(organization) => organization.trim().parse::<i32>()
+                    .map(Some)
+                    .map_err(|_| reject::custom(Error::NotFoundError)),
+                None => Ok(None),
+            }
+        })
+}
+
+/// Address of the client, taken from the first `X-Forwarded-For` entry when the proxy is trusted.
+fn with_client_ip(trust_forwarded_for: bool) -> impl Filter<Extract=(String, ), Error=Rejection> + Clone {
+    warp::addr::remote()
+        .and(warp::header::optional::<String>("x-forwarded-for"))
+        .map(move |remote: Option<SocketAddr>, forwarded_for: Option<String>| {
+            forwarded_for
+                .filter(|_| trust_forwarded_for)
+                .and_then(|value| value.split(',').next().map(|ip| ip.trim().to_string()))
+                .or_else(|| remote.map(|remote| remote.ip().to_string()))
+                .unwrap_or_else(|| "unknown".to_string())
+        })
+}
@@ -0,0 +1,15 @@
+use barrel::{Migration, types};
+use barrel::backend::Pg;
+
+pub fn migration() -> String {
+    let mut m = Migration::new();
+    m.create_table("email_change_tokens", |t| {
+        t.add_column("id", types::serial().primary(true));
+        t.add_column("user_id", types::integer().nullable(false));
+        t.add_column("new_email", types::varchar(255).nullable(false));
+        t.add_column("token_hash", types::varchar(64).unique(true).nullable(false));
+        t.add_column("expiry_date", types::custom("timestamp with time zone NOT NULL"));
+        t.add_foreign_key(&["user_id"], "app_users", &["id"]);
+    });
+    m.make::<Pg>()
+}
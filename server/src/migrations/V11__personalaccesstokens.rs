@@ -0,0 +1,18 @@
+use barrel::{Migration, types};
+use barrel::backend::Pg;
+
+pub fn migration() -> String {
+    let mut m = Migration::new();
+    m.create_table("personal_access_tokens", |t| {
+        t.add_column("id", types::serial().primary(true));
+        t.add_column("user_id", types::integer().nullable(false));
+        t.add_column("name", types::varchar(100).nullable(false));
+        t.add_column("token_hash", types::varchar(64).unique(true).nullable(false));
+        t.add_column("scopes", types::custom("varchar(32)[] NOT NULL"));
+        t.add_column("expiry_date", types::custom("timestamp with time zone").nullable(true));
+        t.add_column("last_used_at", types::custom("timestamp with time zone").nullable(true));
+        t.add_column("created_at", types::custom("timestamp with time zone DEFAULT (now() at time zone 'utc')"));
+        t.add_foreign_key(&["user_id"], "app_users", &["id"]);
+    });
+    m.make::<Pg>()
+}
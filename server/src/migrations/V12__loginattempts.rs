@@ -0,0 +1,19 @@
+use barrel::{Migration, types};
+use barrel::backend::Pg;
+
+pub fn migration() -> String {
+    let mut m = Migration::new();
+    m.create_table("login_attempts", |t| {
+        t.add_column("id", types::serial().primary(true));
+        t.add_column("user_id", types::integer().nullable(true));
+        t.add_column("email", types::varchar(255).nullable(false));
+        t.add_column("ip", types::varchar(45).nullable(false));
+        t.add_column("success", types::boolean().nullable(false));
+        t.add_column("reason", types::varchar(32).nullable(true));
+        t.add_column("created_at", types::custom("timestamp with time zone DEFAULT now()"));
+        t.add_foreign_key(&["user_id"], "app_users", &["id"]);
+    });
+    m.inject_custom("CREATE INDEX login_attempts_email ON login_attempts (email, created_at)");
+    m.inject_custom("CREATE INDEX login_attempts_ip ON login_attempts (ip, created_at)");
+    m.make::<Pg>()
+}
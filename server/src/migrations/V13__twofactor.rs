@@ -0,0 +1,29 @@
+use barrel::{Migration, types};
+use barrel::backend::Pg;
+use barrel::types::WrappedDefault;
+
+pub fn migration() -> String {
+    let mut m = Migration::new();
+    m.change_table("app_users", |t| {
+        t.add_column("totp_secret", types::varchar(64).nullable(true));
+        t.add_column("totp_enabled", types::boolean().nullable(false)
+            .default(WrappedDefault::Boolean(false)));
+        t.add_column("totp_last_step", types::custom("bigint").nullable(true));
+    });
+    m.create_table("recovery_codes", |t| {
+        t.add_column("id", types::serial().primary(true));
+        t.add_column("user_id", types::integer().nullable(false));
+        t.add_column("code_hash", types::varchar(64).nullable(false));
+        t.add_column("used_at", types::custom("timestamp with time zone").nullable(true));
+        t.add_foreign_key(&["user_id"], "app_users", &["id"]);
+    });
+    m.create_table("two_factor_challenges", |t| {
+        t.add_column("id", types::serial().primary(true));
+        t.add_column("user_id", types::integer().nullable(false));
+        t.add_column("token_hash", types::varchar(64).unique(true).nullable(false));
+        t.add_column("expiry_date", types::custom("timestamp with time zone NOT NULL"));
+        t.add_column("attempts", types::integer().nullable(false).default(WrappedDefault::Integer(0)));
+        t.add_foreign_key(&["user_id"], "app_users", &["id"]);
+    });
+    m.make::<Pg>()
+}
@@ -0,0 +1,33 @@
+use barrel::{Migration, types};
+use barrel::backend::Pg;
+
+pub fn migration() -> String {
+    let mut m = Migration::new();
+    m.create_table("user_identities", |t| {
+        t.add_column("id", types::serial().primary(true));
+        t.add_column("user_id", types::integer().nullable(false));
+        t.add_column("issuer", types::varchar(255).nullable(false));
+        t.add_column("subject", types::varchar(255).nullable(false));
+        t.add_column("created_at", types::custom("timestamp with time zone DEFAULT now()"));
+        t.add_foreign_key(&["user_id"], "app_users", &["id"]);
+    });
+    m.inject_custom("CREATE UNIQUE INDEX user_identities_subject ON user_identities (issuer, subject)");
+    m.create_table("device_codes", |t| {
+        t.add_column("id", types::serial().primary(true));
+        t.add_column("device_code_hash", types::varchar(64).unique(true).nullable(false));
+        t.add_column("user_code", types::varchar(9).unique(true).nullable(false));
+        t.add_column("user_id", types::integer().nullable(true));
+        t.add_column("expiry_date", types::custom("timestamp with time zone NOT NULL"));
+        t.add_foreign_key(&["user_id"], "app_users", &["id"]);
+    });
+    m.create_table("oidc_states", |t| {
+        t.add_column("id", types::serial().primary(true));
+        t.add_column("state_hash", types::varchar(64).unique(true).nullable(false));
+        t.add_column("nonce", types::varchar(64).nullable(false));
+        t.add_column("code_verifier", types::varchar(128).nullable(false));
+        t.add_column("device_code_id", types::integer().nullable(true));
+        t.add_column("expiry_date", types::custom("timestamp with time zone NOT NULL"));
+        t.add_foreign_key(&["device_code_id"], "device_codes", &["id"]);
+    });
+    m.make::<Pg>()
+}
@@ -0,0 +1,18 @@
+use barrel::{Migration, types};
+use barrel::backend::Pg;
+
+pub fn migration() -> String {
+    let mut m = Migration::new();
+    m.create_table("project_invitations", |t| {
+        t.add_column("id", types::serial().primary(true));
+        t.add_column("project_id", types::integer().nullable(false));
+        t.add_column("email", types::varchar(255).nullable(false));
+        t.add_column("token_hash", types::varchar(64).unique(true).nullable(false));
+        t.add_column("invited_by", types::integer().nullable(false));
+        t.add_column("expiry_date", types::custom("timestamp with time zone NOT NULL"));
+        t.add_column("created_at", types::custom("timestamp with time zone NOT NULL DEFAULT now()"));
+        t.add_foreign_key(&["project_id"], "projects", &["id"]);
+        t.add_foreign_key(&["invited_by"], "app_users", &["id"]);
+    });
+    m.make::<Pg>()
+}
@@ -0,0 +1,15 @@
+use barrel::{Migration, types};
+use barrel::backend::Pg;
+
+pub fn migration() -> String {
+    let mut m = Migration::new();
+    m.change_table("users_projects", |t| {
+        t.add_column("role", types::varchar(16).nullable(false).default("member"));
+    });
+    m.change_table("project_invitations", |t| {
+        t.add_column("role", types::varchar(16).nullable(false).default("member"));
+    });
+    // everybody could do everything before, existing members keep that as owners
+    m.inject_custom("UPDATE users_projects SET role = 'owner'");
+    m.make::<Pg>()
+}
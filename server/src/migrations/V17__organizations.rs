@@ -0,0 +1,34 @@
+use barrel::{Migration, types};
+use barrel::backend::Pg;
+
+pub fn migration() -> String {
+    let mut m = Migration::new();
+    m.create_table("organizations", |t| {
+        t.add_column("id", types::serial().primary(true));
+        t.add_column("name", types::varchar(255).nullable(false));
+        t.add_column("created_at", types::custom("timestamp with time zone NOT NULL DEFAULT now()"));
+    });
+    m.create_table("organization_members", |t| {
+        t.add_column("organization_id", types::integer().nullable(false));
+        t.add_column("user_id", types::integer().nullable(false));
+        t.add_column("role", types::varchar(16).nullable(false).default("member"));
+        t.add_column("created_at", types::custom("timestamp with time zone NOT NULL DEFAULT now()"));
+        t.set_primary_key(&["organization_id", "user_id"]);
+        t.add_foreign_key(&["organization_id"], "organizations", &["id"]);
+        t.add_foreign_key(&["user_id"], "app_users", &["id"]);
+    });
+    m.change_table("projects", |t| {
+        t.add_column("organization_id", types::integer().nullable(true));
+        t.add_foreign_key(&["organization_id"], "organizations", &["id"]);
+    });
+    // everything so far shared one namespace, it becomes the first organization; the global
+    // administrators run it, or everybody when there are none
+    m.inject_custom("INSERT INTO organizations (name) SELECT 'Default' \
+    WHERE EXISTS (SELECT 1 FROM app_users) OR EXISTS (SELECT 1 FROM projects)");
+    m.inject_custom("INSERT INTO organization_members (organization_id, user_id, role) \
+    SELECT o.id, u.id, CASE WHEN u.role = 'Admin' OR NOT EXISTS (SELECT 1 FROM app_users WHERE role = 'Admin') \
+    THEN 'admin' ELSE 'member' END FROM organizations o CROSS JOIN app_users u");
+    m.inject_custom("UPDATE projects SET organization_id = (SELECT min(id) FROM organizations)");
+    m.inject_custom("ALTER TABLE projects ALTER COLUMN organization_id SET NOT NULL");
+    m.make::<Pg>()
+}
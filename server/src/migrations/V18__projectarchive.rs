@@ -0,0 +1,10 @@
+use barrel::{Migration, types};
+use barrel::backend::Pg;
+
+pub fn migration() -> String {
+    let mut m = Migration::new();
+    m.change_table("projects", |t| {
+        t.add_column("archived_at", types::custom("timestamp with time zone").nullable(true));
+    });
+    m.make::<Pg>()
+}
@@ -0,0 +1,13 @@
+use barrel::{Migration, types};
+use barrel::backend::Pg;
+
+pub fn migration() -> String {
+    let mut m = Migration::new();
+    m.change_table("tasks", |t| {
+        t.add_column("deleted_at", types::custom("timestamp with time zone").nullable(true));
+    });
+    m.change_table("projects", |t| {
+        t.add_column("deleted_at", types::custom("timestamp with time zone").nullable(true));
+    });
+    m.make::<Pg>()
+}
@@ -0,0 +1,13 @@
+use barrel::{Migration, types};
+use barrel::backend::Pg;
+
+pub fn migration() -> String {
+    let mut m = Migration::new();
+    m.change_table("tasks", |t| {
+        t.add_column("parent_id", types::integer().nullable(true));
+    });
+    // subtasks outlive a parent that is removed for good, they become top-level tasks;
+    // barrel renders types::foreign inside ALTER TABLE without ADD, so the key goes in by hand
+    m.inject_custom("ALTER TABLE tasks ADD FOREIGN KEY (parent_id) REFERENCES tasks (id) ON DELETE SET NULL");
+    m.make::<Pg>()
+}
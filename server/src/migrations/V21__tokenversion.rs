@@ -0,0 +1,12 @@
+use barrel::{Migration, types};
+use barrel::backend::Pg;
+use barrel::types::WrappedDefault;
+
+pub fn migration() -> String {
+    let mut m = Migration::new();
+    m.change_table("app_users", |t| {
+        t.add_column("token_version", types::integer().nullable(false)
+            .default(WrappedDefault::Integer(0)));
+    });
+    m.make::<Pg>()
+}
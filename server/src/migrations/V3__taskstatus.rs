@@ -0,0 +1,41 @@
+use barrel::{Migration, types};
+use barrel::backend::Pg;
+use barrel::types::WrappedDefault;
+
+pub fn migration() -> String {
+    let mut m = Migration::new();
+    m.create_table("task_statuses", |t| {
+        t.add_column("id", types::serial().primary(true));
+        t.add_column("project_id", types::integer().nullable(false));
+        t.add_column("name", types::varchar(64).nullable(false));
+        t.add_column("position", types::integer().nullable(false));
+        t.add_column("done", types::boolean().nullable(false)
+            .default(WrappedDefault::Boolean(false)));
+        t.add_foreign_key(&["project_id"], "projects", &["id"]);
+        t.add_index("task_statuses_project_name", types::index(vec!["project_id", "name"]).unique(true));
+    });
+    m.create_table("task_status_transitions", |t| {
+        t.add_column("from_status_id", types::integer().nullable(false));
+        t.add_column("to_status_id", types::integer().nullable(false));
+        t.set_primary_key(&["from_status_id", "to_status_id"]);
+        t.add_foreign_key(&["from_status_id"], "task_statuses", &["id"]);
+        t.add_foreign_key(&["to_status_id"], "task_statuses", &["id"]);
+    });
+    m.change_table("tasks", |t| {
+        t.add_column("status_id", types::integer().nullable(true));
+        t.add_foreign_key(&["status_id"], "task_statuses", &["id"]);
+    });
+    // default workflow for the projects created before statuses existed
+    m.inject_custom("INSERT INTO task_statuses (project_id, name, position, done) \
+        SELECT p.id, s.name, s.position, s.done FROM projects p \
+        CROSS JOIN (VALUES ('Todo', 0, false), ('In Progress', 1, false), \
+        ('Review', 2, false), ('Done', 3, true)) AS s (name, position, done)");
+    m.inject_custom("INSERT INTO task_status_transitions (from_status_id, to_status_id) \
+        SELECT f.id, t.id FROM task_statuses f JOIN task_statuses t ON f.project_id = t.project_id \
+        JOIN (VALUES ('Todo', 'In Progress'), ('In Progress', 'Todo'), ('In Progress', 'Review'), \
+        ('Review', 'In Progress'), ('Review', 'Done'), ('Done', 'Todo')) AS d (from_name, to_name) \
+        ON f.name = d.from_name AND t.name = d.to_name");
+    m.inject_custom("UPDATE tasks SET status_id = s.id FROM task_statuses s \
+        WHERE s.project_id = tasks.project_id AND s.position = 0");
+    m.make::<Pg>()
+}
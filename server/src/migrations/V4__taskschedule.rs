@@ -0,0 +1,14 @@
+use barrel::{Migration, types};
+use barrel::backend::Pg;
+
+pub fn migration() -> String {
+    let mut m = Migration::new();
+    m.change_table("tasks", |t| {
+        t.add_column("start_at", types::custom("timestamp with time zone").nullable(true));
+        t.add_column("due_at", types::custom("timestamp with time zone").nullable(true));
+    });
+    m.change_table("app_users", |t| {
+        t.add_column("time_zone", types::varchar(64).nullable(false).default("UTC"));
+    });
+    m.make::<Pg>()
+}
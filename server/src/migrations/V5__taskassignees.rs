@@ -0,0 +1,17 @@
+use barrel::{Migration, types};
+use barrel::backend::Pg;
+
+pub fn migration() -> String {
+    let mut m = Migration::new();
+    m.change_table("tasks", |t| {
+        t.rename_column("user_id", "reporter_id");
+    });
+    m.create_table("task_assignees", |t| {
+        t.add_column("task_id", types::integer().nullable(false));
+        t.add_column("user_id", types::integer().nullable(false));
+        t.set_primary_key(&["task_id", "user_id"]);
+        t.add_foreign_key(&["task_id"], "tasks", &["id"]);
+        t.add_foreign_key(&["user_id"], "app_users", &["id"]);
+    });
+    m.make::<Pg>()
+}
@@ -0,0 +1,10 @@
+use barrel::{Migration, types};
+use barrel::backend::Pg;
+
+pub fn migration() -> String {
+    let mut m = Migration::new();
+    m.change_table("app_users", |t| {
+        t.add_column("role", types::varchar(32).nullable(false).default("User"));
+    });
+    m.make::<Pg>()
+}
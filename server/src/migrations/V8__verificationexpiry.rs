@@ -0,0 +1,10 @@
+use barrel::Migration;
+use barrel::backend::Pg;
+
+pub fn migration() -> String {
+    let mut m = Migration::new();
+    m.inject_custom("ALTER TABLE verification_tokens ALTER COLUMN expiry_date \
+        TYPE timestamp with time zone USING expiry_date AT TIME ZONE 'utc'");
+    m.inject_custom("CREATE UNIQUE INDEX verification_tokens_token ON verification_tokens (token)");
+    m.make::<Pg>()
+}
@@ -1,2 +1,21 @@
 mod V1__initial;
-mod V2__userenabled;
\ No newline at end of file
+mod V2__userenabled;
+mod V3__taskstatus;
+mod V4__taskschedule;
+mod V5__taskassignees;
+mod V6__userrole;
+mod V7__refreshtokens;
+mod V8__verificationexpiry;
+mod V9__passwordresettokens;
+mod V10__emailchangetokens;
+mod V11__personalaccesstokens;
+mod V12__loginattempts;
+mod V13__twofactor;
+mod V14__oidc;
+mod V15__projectinvitations;
+mod V16__projectroles;
+mod V17__organizations;
+mod V18__projectarchive;
+mod V19__softdelete;
+mod V20__subtasks;
+mod V21__tokenversion;
\ No newline at end of file
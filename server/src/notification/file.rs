@@ -0,0 +1,72 @@
+use std::fs;
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use lettre::{AsyncFileTransport, AsyncTransport, Tokio1Executor};
+use lettre::message::Mailbox;
+
+use crate::config::Config;
+
+use super::{Notification, notification_error, Notifier, Result, to_message};
+
+/// Writes every message as an `.eml` file into the spool directory instead of sending it.
+pub struct FileNotifier {
+    transport: AsyncFileTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl FileNotifier {
+    pub fn from_config(config: &Config) -> Result<FileNotifier> {
+        let spool_dir = PathBuf::from(&config.mail_spool_dir);
+        fs::create_dir_all(&spool_dir)
+            .map_err(notification_error)?;
+        let from = config.mail_from.parse()
+            .map_err(notification_error)?;
+        Ok(FileNotifier {
+            transport: AsyncFileTransport::new(spool_dir),
+            from,
+        })
+    }
+}
+
+#[async_trait]
+impl Notifier for FileNotifier {
+    async fn send(&self, notification: Notification) -> Result<()> {
+        let message = to_message(&self.from, notification)?;
+        self.transport.send(message)
+            .await
+            .map_err(notification_error)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use crate::config;
+    use crate::notification::registration_notification;
+    use crate::notification::tests::{token, user};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn messages_are_spooled_as_eml_files() {
+        let spool_dir = std::env::temp_dir().join(format!("tm-spool-{}", Uuid::new_v4()));
+        let mut config = config::from_env();
+        config.mail_spool_dir = spool_dir.to_string_lossy().to_string();
+        let notifier = FileNotifier::from_config(&config).unwrap();
+
+        let notification = registration_notification("https://tasks.example.com", &user(None), &token());
+        notifier.send(notification).await.unwrap();
+
+        let files: Vec<PathBuf> = fs::read_dir(&spool_dir).unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].extension().unwrap(), "eml");
+        let content = fs::read_to_string(&files[0]).unwrap();
+        assert!(content.contains("To: jane@example.com"));
+        fs::remove_dir_all(&spool_dir).unwrap();
+    }
+}
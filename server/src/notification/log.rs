@@ -0,0 +1,14 @@
+use async_trait::async_trait;
+
+use super::{Notification, Notifier, Result};
+
+/// Prints messages to stdout, handy during development when no mail server is around.
+pub struct LogNotifier;
+
+#[async_trait]
+impl Notifier for LogNotifier {
+    async fn send(&self, notification: Notification) -> Result<()> {
+        println!("To: {}\nSubject: {}\n\n{}", notification.to, notification.subject, notification.text);
+        Ok(())
+    }
+}
@@ -0,0 +1,26 @@
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+
+use super::{Notification, Notifier, Result};
+
+/// Keeps every message in memory so tests can look at what would have been sent.
+#[derive(Default)]
+pub struct MemoryNotifier {
+    sent: Mutex<Vec<Notification>>,
+}
+
+impl MemoryNotifier {
+    #[cfg(test)]
+    pub fn sent(&self) -> Vec<Notification> {
+        self.sent.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Notifier for MemoryNotifier {
+    async fn send(&self, notification: Notification) -> Result<()> {
+        self.sent.lock().unwrap().push(notification);
+        Ok(())
+    }
+}
@@ -0,0 +1,268 @@
+use std::fmt::Display;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use lettre::Message;
+use lettre::message::{Mailbox, MultiPart};
+
+use common::data::{ProjectInvitation, User};
+
+use crate::config::Config;
+use crate::data::VerificationToken;
+use crate::error;
+use crate::error::Error::NotificationError;
+
+pub use self::file::FileNotifier;
+pub use self::log::LogNotifier;
+pub use self::memory::MemoryNotifier;
+pub use self::smtp::SmtpNotifier;
+
+mod file;
+mod log;
+mod memory;
+mod smtp;
+
+type Result<T> = std::result::Result<T, error::Error>;
+
+const REGISTRATION_SUBJECT: &str = "Confirm your Task Manager registration";
+const REGISTRATION_TEXT: &str = include_str!("../../templates/registration.txt");
+const REGISTRATION_HTML: &str = include_str!("../../templates/registration.html");
+const EMAIL_CHANGE_SUBJECT: &str = "Confirm your new Task Manager email address";
+const EMAIL_CHANGE_TEXT: &str = include_str!("../../templates/email_change.txt");
+const EMAIL_CHANGE_HTML: &str = include_str!("../../templates/email_change.html");
+const PASSWORD_RESET_SUBJECT: &str = "Reset your Task Manager password";
+const PASSWORD_RESET_TEXT: &str = include_str!("../../templates/password_reset.txt");
+const PASSWORD_RESET_HTML: &str = include_str!("../../templates/password_reset.html");
+const PROJECT_INVITATION_SUBJECT: &str = "You are invited to a Task Manager project";
+const PROJECT_INVITATION_TEXT: &str = include_str!("../../templates/project_invitation.txt");
+const PROJECT_INVITATION_HTML: &str = include_str!("../../templates/project_invitation.html");
+
+/// A rendered message, independent of the way it is delivered.
+#[derive(Clone, Debug)]
+pub struct Notification {
+    pub to: String,
+    pub subject: String,
+    pub text: String,
+    pub html: String,
+}
+
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn send(&self, notification: Notification) -> Result<()>;
+}
+
+/// Creates the notifier selected by `NOTIFIER`: `smtp`, `file`, `log` or `memory`.
+pub fn from_config(config: &Config) -> Result<Arc<dyn Notifier>> {
+    let notifier: Arc<dyn Notifier> = match config.notifier.as_str() {
+        "smtp" => Arc::new(SmtpNotifier::from_config(config)?),
+        "file" => Arc::new(FileNotifier::from_config(config)?),
+        "log" => Arc::new(LogNotifier),
+        "memory" => Arc::new(MemoryNotifier::default()),
+        other => return Err(notification_error(format!("unknown notifier {}", other))),
+    };
+    Ok(notifier)
+}
+
+pub fn registration_notification(base_url: &str, user: &User, verification_token: &VerificationToken) -> Notification {
+    let confirm_link = format!("{}/registration/confirm?token={}",
+                               base_url.trim_end_matches('/'), verification_token.token);
+    let name = user.first_name.as_deref().unwrap_or(&user.email);
+    let expiry_date = verification_token.expiry_date.format("%Y-%m-%d %H:%M UTC").to_string();
+    let text = render(REGISTRATION_TEXT, &[
+        ("name", name),
+        ("confirm_link", &confirm_link),
+        ("expiry_date", &expiry_date),
+    ]);
+    let html = render(REGISTRATION_HTML, &[
+        ("name", &escape_html(name)),
+        ("confirm_link", &escape_html(&confirm_link)),
+        ("expiry_date", &expiry_date),
+    ]);
+    Notification {
+        to: user.email.clone(),
+        subject: REGISTRATION_SUBJECT.to_string(),
+        text,
+        html,
+    }
+}
+
+pub fn password_reset_notification(user: &User, token: &str, expiry_date: DateTime<Utc>) -> Notification {
+    let name = user.first_name.as_deref().unwrap_or(&user.email);
+    let expiry_date = expiry_date.format("%Y-%m-%d %H:%M UTC").to_string();
+    let text = render(PASSWORD_RESET_TEXT, &[
+        ("name", name),
+        ("token", token),
+        ("expiry_date", &expiry_date),
+    ]);
+    let html = render(PASSWORD_RESET_HTML, &[
+        ("name", &escape_html(name)),
+        ("token", token),
+        ("expiry_date", &expiry_date),
+    ]);
+    Notification {
+        to: user.email.clone(),
+        subject: PASSWORD_RESET_SUBJECT.to_string(),
+        text,
+        html,
+    }
+}
+
+/// Goes to the new address, the change only happens once its owner follows the link.
+pub fn email_change_notification(base_url: &str, user: &User, new_email: &str,
+                                 token: &str, expiry_date: DateTime<Utc>) -> Notification {
+    let confirm_link = format!("{}/users/email/confirm?token={}", base_url.trim_end_matches('/'), token);
+    let name = user.first_name.as_deref().unwrap_or(&user.email);
+    let expiry_date = expiry_date.format("%Y-%m-%d %H:%M UTC").to_string();
+    let text = render(EMAIL_CHANGE_TEXT, &[
+        ("name", name),
+        ("email", new_email),
+        ("confirm_link", &confirm_link),
+        ("expiry_date", &expiry_date),
+    ]);
+    let html = render(EMAIL_CHANGE_HTML, &[
+        ("name", &escape_html(name)),
+        ("email", &escape_html(new_email)),
+        ("confirm_link", &escape_html(&confirm_link)),
+        ("expiry_date", &expiry_date),
+    ]);
+    Notification {
+        to: new_email.to_string(),
+        subject: EMAIL_CHANGE_SUBJECT.to_string(),
+        text,
+        html,
+    }
+}
+
+/// Goes to the invited address, which may not belong to a registered user yet.
+pub fn project_invitation_notification(inviter: &User, invitation: &ProjectInvitation, token: &str) -> Notification {
+    let inviter_name = inviter.first_name.as_deref().unwrap_or(&inviter.email);
+    let expiry_date = invitation.expiry_date.format("%Y-%m-%d %H:%M UTC").to_string();
+    let text = render(PROJECT_INVITATION_TEXT, &[
+        ("inviter", inviter_name),
+        ("project", &invitation.project_title),
+        ("token", token),
+        ("expiry_date", &expiry_date),
+    ]);
+    let html = render(PROJECT_INVITATION_HTML, &[
+        ("inviter", &escape_html(inviter_name)),
+        ("project", &escape_html(&invitation.project_title)),
+        ("token", token),
+        ("expiry_date", &expiry_date),
+    ]);
+    Notification {
+        to: invitation.email.clone(),
+        subject: PROJECT_INVITATION_SUBJECT.to_string(),
+        text,
+        html,
+    }
+}
+
+/// Builds the email shared by the SMTP and file backends.
+fn to_message(from: &Mailbox, notification: Notification) -> Result<Message> {
+    Message::builder()
+        .from(from.clone())
+        .to(notification.to.parse().map_err(notification_error)?)
+        .subject(notification.subject)
+        .multipart(MultiPart::alternative_plain_html(notification.text, notification.html))
+        .map_err(notification_error)
+}
+
+/// Replaces `{key}` placeholders of the template.
+fn render(template: &str, values: &[(&str, &str)]) -> String {
+    values.iter()
+        .fold(template.to_string(), |result, (key, value)| {
+            result.replace(&format!("{{{}}}", key), value)
+        })
+}
+
+fn escape_html(value: &str) -> String {
+    value.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn notification_error<E: Display>(e: E) -> error::Error {
+    eprintln!("could not send notification: {}", e);
+    NotificationError
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+
+    pub(super) fn user(first_name: Option<&str>) -> User {
+        User {
+            id: 1,
+            first_name: first_name.map(String::from),
+            last_name: None,
+            email: "jane@example.com".to_string(),
+            pwd: String::new(),
+            enabled: false,
+            time_zone: "UTC".to_string(),
+            role: "User".to_string(),
+            created_at: Utc::now(),
+            token_version: 0,
+        }
+    }
+
+    pub(super) fn token() -> VerificationToken {
+        VerificationToken {
+            id: 1,
+            user_id: 1,
+            token: "0b9f6b1c-2d6e-4c4f-9a53-4c1b8a3e2f10".to_string(),
+            expiry_date: Utc.ymd(2022, 3, 1).and_hms(12, 30, 0),
+        }
+    }
+
+    #[test]
+    fn bodies_contain_the_confirm_link() {
+        let notification = registration_notification("https://tasks.example.com/", &user(Some("Jane")), &token());
+        let link = "https://tasks.example.com/registration/confirm?token=0b9f6b1c-2d6e-4c4f-9a53-4c1b8a3e2f10";
+        assert_eq!(notification.to, "jane@example.com");
+        assert!(notification.text.starts_with("Hello Jane,"));
+        assert!(notification.text.contains(link));
+        assert!(notification.text.contains("2022-03-01 12:30 UTC"));
+        assert!(notification.html.contains(&format!("<a href=\"{}\">", link)));
+        assert!(!notification.text.contains('{') && !notification.html.contains('{'));
+    }
+
+    #[test]
+    fn password_reset_contains_the_token() {
+        let notification = password_reset_notification(&user(Some("Jane")), "abc123", Utc.ymd(2022, 3, 1).and_hms(12, 30, 0));
+        assert_eq!(notification.subject, "Reset your Task Manager password");
+        assert!(notification.text.contains("\nabc123\n"));
+        assert!(notification.html.contains("<code>abc123</code>"));
+        assert!(!notification.text.contains('{') && !notification.html.contains('{'));
+    }
+
+    #[test]
+    fn invitation_names_inviter_and_project() {
+        let invitation = ProjectInvitation {
+            id: 1,
+            project_id: 2,
+            project_title: "<Launch>".to_string(),
+            email: "joe@example.com".to_string(),
+            role: "member".to_string(),
+            expiry_date: Utc.ymd(2022, 3, 1).and_hms(12, 30, 0),
+            created_at: Utc::now(),
+        };
+        let notification = project_invitation_notification(&user(Some("Jane")), &invitation, "abc123");
+        assert_eq!(notification.to, "joe@example.com");
+        assert!(notification.text.contains("Jane invited you to the Task Manager project \"<Launch>\""));
+        assert!(notification.html.contains("&quot;&lt;Launch&gt;&quot;"));
+        assert!(notification.text.contains("\nabc123\n"));
+        assert!(!notification.text.contains('{') && !notification.html.contains('{'));
+    }
+
+    #[test]
+    fn html_body_escapes_user_input() {
+        let notification = registration_notification("https://tasks.example.com", &user(Some("<b>Jane</b>")), &token());
+        assert!(notification.text.contains("Hello <b>Jane</b>,"));
+        assert!(notification.html.contains("Hello &lt;b&gt;Jane&lt;/b&gt;,"));
+    }
+}
@@ -0,0 +1,140 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+
+use crate::config::Config;
+
+use super::{Notification, notification_error, Notifier, Result, to_message};
+
+pub struct SmtpNotifier {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpNotifier {
+    pub fn from_config(config: &Config) -> Result<SmtpNotifier> {
+        let builder = if config.smtp_starttls {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.smtp_host)
+                .map_err(notification_error)?
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.smtp_host)
+        };
+        let mut builder = builder
+            .port(config.smtp_port)
+            .timeout(Some(Duration::from_secs(10)));
+        if let (Some(username), Some(password)) = (&config.smtp_username, &config.smtp_password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+        let from = config.mail_from.parse()
+            .map_err(notification_error)?;
+        Ok(SmtpNotifier {
+            transport: builder.build(),
+            from,
+        })
+    }
+}
+
+#[async_trait]
+impl Notifier for SmtpNotifier {
+    async fn send(&self, notification: Notification) -> Result<()> {
+        let message = to_message(&self.from, notification)?;
+        self.transport.send(message)
+            .await
+            .map_err(notification_error)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::task::JoinHandle;
+
+    use crate::config;
+    use crate::error::Error::NotificationError;
+    use crate::notification::registration_notification;
+    use crate::notification::tests::{token, user};
+
+    use super::*;
+
+    fn notifier(port: u16) -> SmtpNotifier {
+        let mut config = config::from_env();
+        config.smtp_host = "127.0.0.1".to_string();
+        config.smtp_port = port;
+        config.smtp_starttls = false;
+        config.smtp_username = None;
+        config.smtp_password = None;
+        config.mail_from = "Task Manager <noreply@example.com>".to_string();
+        SmtpNotifier::from_config(&config).unwrap()
+    }
+
+    /// Accepts a single SMTP session and returns what the client sent as message data.
+    async fn smtp_sink() -> (u16, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            writer.write_all(b"220 localhost ESMTP sink\r\n").await.unwrap();
+            let mut data = String::new();
+            let mut in_data = false;
+            while let Some(line) = lines.next_line().await.unwrap() {
+                if in_data {
+                    if line == "." {
+                        in_data = false;
+                        writer.write_all(b"250 OK\r\n").await.unwrap();
+                    } else {
+                        data.push_str(&line);
+                        data.push('\n');
+                    }
+                    continue;
+                }
+                let command = line.to_ascii_uppercase();
+                if command.starts_with("EHLO") {
+                    writer.write_all(b"250-localhost\r\n250 8BITMIME\r\n").await.unwrap();
+                } else if command.starts_with("DATA") {
+                    in_data = true;
+                    writer.write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n").await.unwrap();
+                } else if command.starts_with("QUIT") {
+                    writer.write_all(b"221 Bye\r\n").await.unwrap();
+                    break;
+                } else {
+                    writer.write_all(b"250 OK\r\n").await.unwrap();
+                }
+            }
+            data
+        });
+        (port, handle)
+    }
+
+    #[tokio::test]
+    async fn registration_email_is_delivered() {
+        let (port, sink) = smtp_sink().await;
+        let notification = registration_notification("https://tasks.example.com", &user(None), &token());
+        notifier(port).send(notification).await.unwrap();
+        let data = sink.await.unwrap();
+        // undo quoted-printable soft line breaks so long lines can be searched
+        let data = data.replace("=\n", "");
+        assert!(data.contains("To: jane@example.com"));
+        assert!(data.contains("Subject: Confirm your Task Manager registration"));
+        assert!(data.contains("multipart/alternative"));
+        assert!(data.contains("text/html"));
+        assert!(data.contains("Hello jane@example.com,"));
+        assert!(data.contains("0b9f6b1c-2d6e-4c4f-9a53-4c1b8a3e2f10"));
+    }
+
+    #[tokio::test]
+    async fn unreachable_server_is_a_notification_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+        let notification = registration_notification("https://tasks.example.com", &user(None), &token());
+        let result = notifier(port).send(notification).await;
+        assert!(matches!(result, Err(NotificationError)));
+    }
+}
@@ -0,0 +1,187 @@
+//! Login through an external OpenID Connect provider with the authorization code flow and PKCE.
+//!
+//! The provider only proves who the user is, the server then hands out its own tokens just
+//! like after a password login.
+
+use jsonwebtoken::{Algorithm, decode, decode_header, DecodingKey, Validation};
+use reqwest::Url;
+use serde_derive::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::{auth, error};
+use crate::config::Config;
+use crate::error::Error::{OidcNotConfiguredError, OidcProviderError};
+
+type Result<T> = std::result::Result<T, error::Error>;
+
+/// Characters of the code shown to the user during a device login, without look-alikes.
+const USER_CODE_ALPHABET: &[u8; 20] = b"BCDFGHJKLMNPQRSTVWXZ";
+
+/// Endpoints published by the provider at `/.well-known/openid-configuration`.
+#[derive(Deserialize)]
+pub struct Provider {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+/// The parts of the ID token used to find or create the user.
+#[derive(Deserialize)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    pub sub: String,
+    pub nonce: Option<String>,
+    pub email: Option<String>,
+    pub email_verified: Option<bool>,
+    pub given_name: Option<String>,
+    pub family_name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Deserialize)]
+struct Jwks {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Deserialize)]
+struct Jwk {
+    kid: Option<String>,
+    kty: String,
+    n: Option<String>,
+    e: Option<String>,
+}
+
+/// Fetches the provider metadata, OIDC login is off as long as `OIDC_ISSUER` is not set.
+pub async fn discover(config: &Config) -> Result<Provider> {
+    let issuer = config.oidc_issuer.as_ref().ok_or(OidcNotConfiguredError)?;
+    let url = format!("{}/.well-known/openid-configuration", issuer.trim_end_matches('/'));
+    let provider: Provider = reqwest::get(url)
+        .await
+        .map_err(provider_error)?
+        .error_for_status()
+        .map_err(provider_error)?
+        .json()
+        .await
+        .map_err(provider_error)?;
+    if provider.issuer.trim_end_matches('/') != issuer.trim_end_matches('/') {
+        return Err(provider_error("issuer of the metadata does not match OIDC_ISSUER"));
+    }
+    Ok(provider)
+}
+
+impl Provider {
+    /// Where the browser is sent to log in, the provider redirects back with a code for `state`.
+    pub fn authorization_url(&self, config: &Config, state: &str, nonce: &str, code_verifier: &str) -> Result<String> {
+        let url = Url::parse_with_params(&self.authorization_endpoint, &[
+            ("response_type", "code"),
+            ("client_id", config.oidc_client_id.as_str()),
+            ("redirect_uri", config.oidc_redirect_url.as_str()),
+            ("scope", config.oidc_scopes.as_str()),
+            ("state", state),
+            ("nonce", nonce),
+            ("code_challenge", code_challenge(code_verifier).as_str()),
+            ("code_challenge_method", "S256"),
+        ])
+            .map_err(provider_error)?;
+        Ok(url.to_string())
+    }
+
+    /// Trades the code for tokens and returns the validated claims of the ID token.
+    pub async fn exchange_code(&self, config: &Config, code: &str, code_verifier: &str,
+                               nonce: &str) -> Result<IdTokenClaims> {
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", config.oidc_redirect_url.as_str()),
+            ("client_id", config.oidc_client_id.as_str()),
+            ("code_verifier", code_verifier),
+        ];
+        if let Some(client_secret) = &config.oidc_client_secret {
+            form.push(("client_secret", client_secret.as_str()));
+        }
+        let response: TokenResponse = reqwest::Client::new()
+            .post(&self.token_endpoint)
+            .form(&form)
+            .send()
+            .await
+            .map_err(provider_error)?
+            .error_for_status()
+            .map_err(provider_error)?
+            .json()
+            .await
+            .map_err(provider_error)?;
+        self.validate_id_token(config, &response.id_token, nonce).await
+    }
+
+    async fn validate_id_token(&self, config: &Config, id_token: &str, nonce: &str) -> Result<IdTokenClaims> {
+        let header = decode_header(id_token).map_err(provider_error)?;
+        if header.alg != Algorithm::RS256 {
+            return Err(provider_error(format!("unsupported ID token algorithm {:?}", header.alg)));
+        }
+        let jwks: Jwks = reqwest::get(&self.jwks_uri)
+            .await
+            .map_err(provider_error)?
+            .json()
+            .await
+            .map_err(provider_error)?;
+        let jwk = jwks.keys.iter()
+            .filter(|jwk| jwk.kty == "RSA")
+            .find(|jwk| header.kid.is_none() || jwk.kid == header.kid)
+            .ok_or_else(|| provider_error("no matching key in the provider JWKS"))?;
+        let (n, e) = jwk.n.as_deref().zip(jwk.e.as_deref())
+            .ok_or_else(|| provider_error("RSA key without modulus or exponent"))?;
+        let mut validation = Validation::new(Algorithm::RS256);
+        validation.iss = Some(self.issuer.clone());
+        validation.set_audience(&[config.oidc_client_id.as_str()]);
+        let claims = decode::<IdTokenClaims>(id_token, &DecodingKey::from_rsa_components(n, e), &validation)
+            .map_err(provider_error)?
+            .claims;
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(provider_error("nonce of the ID token does not match"));
+        }
+        Ok(claims)
+    }
+}
+
+/// The S256 PKCE challenge, the verifier itself is only sent along with the code.
+pub fn code_challenge(code_verifier: &str) -> String {
+    base64::encode_config(Sha256::digest(code_verifier.as_bytes()), base64::URL_SAFE_NO_PAD)
+}
+
+/// Short code the user compares between terminal and browser, formatted as `XXXX-XXXX`.
+pub fn generate_user_code() -> String {
+    let code: String = Sha256::digest(auth::generate_token().as_bytes()).iter()
+        .take(8)
+        .map(|b| USER_CODE_ALPHABET[*b as usize % USER_CODE_ALPHABET.len()] as char)
+        .collect();
+    format!("{}-{}", &code[..4], &code[4..])
+}
+
+fn provider_error<E: std::fmt::Display>(e: E) -> error::Error {
+    eprintln!("OpenID Connect provider error: {}", e);
+    OidcProviderError
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code_challenge_matches_the_rfc_example() {
+        // RFC 7636, appendix B
+        assert_eq!(code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+                   "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM");
+    }
+
+    #[test]
+    fn user_codes_avoid_ambiguous_characters() {
+        let code = generate_user_code();
+        assert_eq!(code.len(), 9);
+        assert!(code.chars().filter(|c| *c != '-').all(|c| USER_CODE_ALPHABET.contains(&(c as u8))));
+    }
+}
@@ -0,0 +1,1342 @@
+//! Request level checks against a real database, configured like the server itself.
+//! Run with `cargo test -- --ignored` once Postgres is reachable.
+
+use chrono::{Timelike, Utc};
+use serde_json::{json, Value};
+use uuid::Uuid;
+use warp::http::header::LOCATION;
+use warp::http::StatusCode;
+use warp::Filter;
+use warp::test::RequestBuilder;
+
+use common::data::User;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::{auth, config, db, DBPool, oidc, routes, run_command, totp};
+use crate::auth::JwtKeys;
+use crate::config::Config;
+use crate::notification::MemoryNotifier;
+
+/// Modulus of `fixtures/oidc_test_key.pem`, published by the mock provider.
+const MOCK_IDP_KEY_MODULUS: &str = "oKpd9FOokAlLFrIUCk5rwGkDYTnXc02i6i2hzqXbuFBIGrht-rQDKW2jrnPFtuEK0Crcd6axhoTXf00-t_eINO4XE4ff8gyUM9WMjDxl79Sn79X_QWb0M0HM5d7x5OZihDxgm7wKrAUBKnaxPck5RYiBM3q_P53ryxfyupD2-O-Tz0KQsNSXr2LXWH2wxWrv3M6ESWmurkh8eJNuuAdNFkoShF8yeGo3o9FdAUm6DklKQS9C1U5CxN5J9xoOOxEvAENoDRDDoNZy04WqWInb1yF97_zLIYiqSA_frjdt9GvYG8HzIBvu3sdI8hw8Sogy14ia27mAFbySicg4gHZJhQ";
+const MOCK_IDP_CLIENT_ID: &str = "taskmanager";
+
+/// An OpenID Connect provider that logs in whoever `identity` names without asking,
+/// enough to run the whole code flow including PKCE and the ID token signature.
+struct MockIdp {
+    issuer: String,
+    /// Subject and email of the next login.
+    identity: Arc<Mutex<(String, String)>>,
+}
+
+struct IssuedCode {
+    code_challenge: String,
+    nonce: String,
+    subject: String,
+    email: String,
+}
+
+impl MockIdp {
+    async fn start() -> MockIdp {
+        let identity = Arc::new(Mutex::new((String::new(), String::new())));
+        let codes: Arc<Mutex<HashMap<String, IssuedCode>>> = Arc::default();
+        let issuer = |host: String| format!("http://{}", host);
+
+        let discovery = warp::path!(".well-known" / "openid-configuration")
+            .and(warp::header::<String>("host"))
+            .map(move |host: String| {
+                let issuer = issuer(host);
+                warp::reply::json(&json!({
+                    "issuer": issuer,
+                    "authorization_endpoint": format!("{}/authorize", issuer),
+                    "token_endpoint": format!("{}/token", issuer),
+                    "jwks_uri": format!("{}/jwks", issuer),
+                }))
+            });
+        let authorize_identity = identity.clone();
+        let authorize_codes = codes.clone();
+        let authorize = warp::path!("authorize")
+            .and(warp::query::<HashMap<String, String>>())
+            .map(move |query: HashMap<String, String>| {
+                let code = Uuid::new_v4().to_string();
+                let (subject, email) = authorize_identity.lock().unwrap().clone();
+                authorize_codes.lock().unwrap().insert(code.clone(), IssuedCode {
+                    code_challenge: query["code_challenge"].clone(),
+                    nonce: query["nonce"].clone(),
+                    subject,
+                    email,
+                });
+                let location = format!("{}?code={}&state={}", query["redirect_uri"], code, query["state"]);
+                warp::redirect::found(location.parse::<warp::http::Uri>().unwrap())
+            });
+        let token = warp::path!("token")
+            .and(warp::header::<String>("host"))
+            .and(warp::body::form::<HashMap<String, String>>())
+            .map(move |host: String, form: HashMap<String, String>| {
+                let issued = codes.lock().unwrap().remove(&form["code"]).unwrap();
+                if oidc::code_challenge(&form["code_verifier"]) != issued.code_challenge {
+                    return warp::reply::with_status(warp::reply::json(&json!({"error": "invalid_grant"})),
+                                                    StatusCode::BAD_REQUEST);
+                }
+                let claims = json!({
+                    "iss": issuer(host),
+                    "sub": issued.subject,
+                    "aud": form["client_id"],
+                    "exp": Utc::now().timestamp() + 300,
+                    "iat": Utc::now().timestamp(),
+                    "nonce": issued.nonce,
+                    "email": issued.email,
+                    "email_verified": true,
+                    "given_name": "Jane",
+                });
+                let mut header = jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256);
+                header.kid = Some("test".to_string());
+                let key = jsonwebtoken::EncodingKey::from_rsa_pem(include_bytes!("../fixtures/oidc_test_key.pem")).unwrap();
+                let id_token = jsonwebtoken::encode(&header, &claims, &key).unwrap();
+                warp::reply::with_status(warp::reply::json(&json!({"access_token": "unused", "token_type": "Bearer", "id_token": id_token})),
+                                         StatusCode::OK)
+            });
+        let jwks = warp::path!("jwks")
+            .map(|| warp::reply::json(&json!({"keys": [{"kty": "RSA", "kid": "test", "alg": "RS256", "n": MOCK_IDP_KEY_MODULUS, "e": "AQAB"}]})));
+
+        let (address, server) = warp::serve(discovery.or(authorize).or(token).or(jwks))
+            .bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        MockIdp {
+            issuer: format!("http://{}", address),
+            identity,
+        }
+    }
+
+    fn config(&self, auto_provision: bool) -> Config {
+        let mut config = config::from_env();
+        config.oidc_issuer = Some(self.issuer.clone());
+        config.oidc_client_id = MOCK_IDP_CLIENT_ID.to_string();
+        config.oidc_auto_provision = auto_provision;
+        config
+    }
+
+    /// Logs in at the provider and returns the path of the callback to the server.
+    async fn login(&self, authorization_url: &str, subject: &str, email: &str, config: &Config) -> String {
+        *self.identity.lock().unwrap() = (subject.to_string(), email.to_string());
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap();
+        let response = client.get(authorization_url).send().await.unwrap();
+        let location = response.headers()[LOCATION].to_str().unwrap();
+        location.strip_prefix(config.app_base_url.as_str()).unwrap().to_string()
+    }
+}
+
+async fn create_user(db_pool: &DBPool, jwt_keys: &JwtKeys) -> (i32, String) {
+    create_user_with_role(db_pool, jwt_keys, "User").await
+}
+
+async fn create_user_with_role(db_pool: &DBPool, jwt_keys: &JwtKeys, role: &str) -> (i32, String) {
+    let con = db::get_conn(db_pool).await.unwrap();
+    let email = format!("{}@example.com", Uuid::new_v4());
+    let row = con.query_one("INSERT INTO app_users (email, pwd, enabled, role) VALUES ($1, '', true, $2) RETURNING id",
+                            &[&email, &role])
+        .await
+        .unwrap();
+    let user_id: i32 = row.get(0);
+    con.execute("WITH o AS (INSERT INTO organizations (name) VALUES ($2) RETURNING id) \
+    INSERT INTO organization_members (organization_id, user_id, role) SELECT id, $1, 'admin' FROM o",
+                &[&user_id, &email])
+        .await
+        .unwrap();
+    let user = User {
+        id: user_id,
+        first_name: None,
+        last_name: None,
+        email,
+        pwd: String::new(),
+        enabled: true,
+        time_zone: "UTC".to_string(),
+        role: role.to_string(),
+        created_at: Utc::now(),
+        token_version: 0,
+    };
+    (user.id, auth::create_token(&user, jwt_keys).unwrap())
+}
+
+/// Gives the user a password to log in with, which ends their sessions, and returns a new one.
+async fn set_password(app: &TestApp, user_id: i32, pwd: &str) -> String {
+    let user = db::update_password(&app.db_pool, user_id, pwd).await.unwrap();
+    auth::create_token(&user, &app.jwt_keys).unwrap()
+}
+
+struct TestApp {
+    db_pool: DBPool,
+    jwt_keys: Arc<JwtKeys>,
+    config: Arc<Config>,
+    notifier: Arc<MemoryNotifier>,
+}
+
+impl TestApp {
+    async fn new() -> TestApp {
+        TestApp::with_config(config::from_env()).await
+    }
+
+    async fn with_config(config: Config) -> TestApp {
+        db::db_init(&config).await.unwrap();
+        TestApp {
+            db_pool: db::create_pool(&config).unwrap(),
+            jwt_keys: Arc::new(JwtKeys::from_config(&config).unwrap()),
+            config: Arc::new(config),
+            notifier: Arc::new(MemoryNotifier::default()),
+        }
+    }
+
+    async fn call(&self, method: &str, path: &str, token: &str, body: Option<Value>) -> (StatusCode, Value) {
+        self.reply(warp::test::request(), method, path, token, body).await
+    }
+
+    /// Calls a route within the given organization.
+    async fn call_in(&self, organization: &str, method: &str, path: &str, token: &str,
+                     body: Option<Value>) -> (StatusCode, Value) {
+        let request = warp::test::request().header("x-organization", organization);
+        self.reply(request, method, path, token, body).await
+    }
+
+    async fn reply(&self, request: RequestBuilder, method: &str, path: &str, token: &str,
+                   body: Option<Value>) -> (StatusCode, Value) {
+        let mut request = request
+            .method(method)
+            .path(path)
+            .header("authorization", format!("Bearer {}", token));
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+        let routes = routes(self.db_pool.clone(), self.jwt_keys.clone(), self.config.clone(), self.notifier.clone());
+        let response = request.reply(&routes).await;
+        let body = serde_json::from_slice(response.body()).unwrap_or(Value::Null);
+        (response.status(), body)
+    }
+
+    /// Where a route redirects the browser to.
+    async fn redirect(&self, path: &str) -> String {
+        let routes = routes(self.db_pool.clone(), self.jwt_keys.clone(), self.config.clone(), self.notifier.clone());
+        let response = warp::test::request().path(path).reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::FOUND, "{}", path);
+        response.headers()[LOCATION].to_str().unwrap().to_string()
+    }
+}
+
+#[tokio::test]
+#[ignore]
+async fn tasks_only_move_along_the_workflow_of_their_project() {
+    let app = TestApp::new().await;
+    let (_, owner) = create_user(&app.db_pool, &app.jwt_keys).await;
+    let (_, project) = app.call("POST", "/projects", &owner, Some(json!({"title": "workflow"}))).await;
+    let statuses = format!("/projects/{}/statuses", project["id"]);
+    let (_, task) = app.call("POST", "/tasks", &owner, Some(json!({"title": "task", "project_id": project["id"]}))).await;
+    let status_path = format!("/tasks/{}/status", task["id"]);
+    assert_eq!(task["status"], json!("Todo"));
+
+    for illegal in ["Done", "Review", "Nonexistent"] {
+        let (status, _) = app.call("PATCH", &status_path, &owner, Some(json!({"status": illegal}))).await;
+        assert_eq!(status, StatusCode::CONFLICT, "{}", illegal);
+    }
+    let (status, task) = app.call("PATCH", &status_path, &owner, Some(json!({"status": "In Progress"}))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(task["status"], json!("In Progress"));
+
+    let status = |name: &str, done: bool| json!({"name": name, "done": done});
+    let transition = |from: &str, to: &str| json!({"from": from, "to": to});
+    let invalid = [
+        // the same status twice
+        json!({"statuses": [status("Todo", false), status("Todo", true)], "transitions": []}),
+        // Done can never be reached
+        json!({"statuses": [status("Todo", false), status("In Progress", false), status("Done", true)],
+            "transitions": [transition("Todo", "In Progress"), transition("Done", "Todo")]}),
+        // the task is still In Progress
+        json!({"statuses": [status("Todo", false), status("Done", true)],
+            "transitions": [transition("Todo", "Done")]}),
+    ];
+    for workflow in invalid {
+        let (code, _) = app.call("PUT", &statuses, &owner, Some(workflow.clone())).await;
+        assert_eq!(code, StatusCode::BAD_REQUEST, "{}", workflow);
+    }
+    let (_, workflow) = app.call("GET", &statuses, &owner, None).await;
+    assert_eq!(workflow["statuses"].as_array().unwrap().len(), 4);
+
+    let (code, workflow) = app.call("PUT", &statuses, &owner,
+                                    Some(json!({"statuses": [status("Todo", false), status("In Progress", false), status("Done", true)],
+                                        "transitions": [transition("Todo", "In Progress"), transition("In Progress", "Done")]}))).await;
+    assert_eq!(code, StatusCode::OK);
+    assert_eq!(workflow["statuses"].as_array().unwrap().len(), 3);
+    let (code, _) = app.call("PATCH", &status_path, &owner, Some(json!({"status": "Done"}))).await;
+    assert_eq!(code, StatusCode::OK);
+    let (code, _) = app.call("PATCH", &status_path, &owner, Some(json!({"status": "In Progress"}))).await;
+    assert_eq!(code, StatusCode::CONFLICT);
+}
+
+/// The fixed offset zone in which it is `hour` o'clock right now.
+fn zone_at_hour(hour: i32) -> String {
+    let offset = (hour - Utc::now().hour() as i32).rem_euclid(24);
+    let offset = if offset > 11 { offset - 24 } else { offset };
+    // the sign of the Etc zones is inverted
+    format!("Etc/GMT{:+}", -offset)
+}
+
+#[tokio::test]
+#[ignore]
+async fn due_filters_follow_the_time_zone_of_the_user() {
+    let app = TestApp::new().await;
+    let (_, owner) = create_user(&app.db_pool, &app.jwt_keys).await;
+    let (_, project) = app.call("POST", "/projects", &owner, Some(json!({"title": "schedule"}))).await;
+    let now = Utc::now();
+    let (_, overdue) = app.call("POST", "/tasks", &owner,
+                                Some(json!({"title": "late", "project_id": project["id"],
+                                    "due_at": now - chrono::Duration::hours(1)}))).await;
+    let (_, soon) = app.call("POST", "/tasks", &owner,
+                             Some(json!({"title": "soon", "project_id": project["id"],
+                                 "due_at": now + chrono::Duration::hours(3)}))).await;
+    app.call("POST", "/tasks", &owner, Some(json!({"title": "someday", "project_id": project["id"]}))).await;
+
+    let (_, tasks) = app.call("GET", "/tasks?due=overdue", &owner, None).await;
+    assert_eq!(tasks.as_array().unwrap().len(), 1);
+    assert_eq!(tasks[0]["id"], overdue["id"]);
+
+    // three hours from now is still today in the morning, but already tomorrow late in the evening
+    let (status, _) = app.call("PATCH", "/users/me", &owner, Some(json!({"time_zone": zone_at_hour(9)}))).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, tasks) = app.call("GET", "/tasks?due=today", &owner, None).await;
+    assert!(tasks.as_array().unwrap().iter().any(|task| task["id"] == soon["id"]));
+    app.call("PATCH", "/users/me", &owner, Some(json!({"time_zone": zone_at_hour(22)}))).await;
+    let (_, tasks) = app.call("GET", "/tasks?due=today", &owner, None).await;
+    assert!(tasks.as_array().unwrap().iter().all(|task| task["id"] != soon["id"]));
+
+    let (status, error) = app.call("PATCH", "/users/me", &owner, Some(json!({"time_zone": "Mars/Olympus_Mons"}))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error["message"], json!("Unknown time zone"));
+    let registration = json!({"email": format!("{}@example.com", Uuid::new_v4()), "pwd": "secret",
+        "time_zone": "Mars/Olympus_Mons"});
+    let (status, _) = app.call("POST", "/registration", "", Some(registration)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+#[ignore]
+async fn tasks_are_assigned_to_project_members_only() {
+    let app = TestApp::new().await;
+    let (owner_id, owner) = create_user(&app.db_pool, &app.jwt_keys).await;
+    let (member_id, member) = create_user(&app.db_pool, &app.jwt_keys).await;
+    let (outsider_id, _) = create_user(&app.db_pool, &app.jwt_keys).await;
+    let (_, project) = app.call("POST", "/projects", &owner, Some(json!({"title": "team"}))).await;
+    join_project(&app, &owner, project["id"].as_i64().unwrap(), &member, "member").await;
+    let (_, task) = app.call("POST", "/tasks", &owner, Some(json!({"title": "handover", "project_id": project["id"]}))).await;
+    assert_eq!(task["reporter_id"], json!(owner_id));
+    assert_eq!(task["assignees"], json!([]));
+    let assignees = format!("/tasks/{}/assignees", task["id"]);
+
+    let (status, error) = app.call("POST", &assignees, &owner, Some(json!({"user_id": outsider_id}))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error["message"], json!("User is not a member of the project"));
+    let (status, assigned) = app.call("POST", &assignees, &owner, Some(json!({"user_id": member_id}))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(assigned["assignees"], json!([member_id]));
+    assert_eq!(assigned["reporter_id"], json!(owner_id));
+
+    let (_, tasks) = app.call("GET", "/tasks?assigned_to_me=true", &member, None).await;
+    assert_eq!(tasks.as_array().unwrap().len(), 1);
+    assert_eq!(tasks[0]["id"], task["id"]);
+    let (_, tasks) = app.call("GET", "/tasks?assigned_to_me=true", &owner, None).await;
+    assert_eq!(tasks, json!([]));
+    let (status, unassigned) = app.call("DELETE", &format!("{}/{}", assignees, member_id), &owner, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(unassigned["assignees"], json!([]));
+    let (_, tasks) = app.call("GET", "/tasks?assigned_to_me=true", &member, None).await;
+    assert_eq!(tasks, json!([]));
+}
+
+#[tokio::test]
+#[ignore]
+async fn cross_user_access_is_rejected() {
+    let app = TestApp::new().await;
+
+    let (_, owner) = create_user(&app.db_pool, &app.jwt_keys).await;
+    let (teammate_id, teammate) = create_user(&app.db_pool, &app.jwt_keys).await;
+    let (_, stranger) = create_user(&app.db_pool, &app.jwt_keys).await;
+
+    let (status, project) = app.call("POST", "/projects", &owner,
+                                 Some(json!({"title": "secret"}))).await;
+    assert_eq!(status, StatusCode::OK);
+    let project_id = project["id"].as_i64().unwrap();
+    let (status, task) = app.call("POST", "/tasks", &owner,
+                              Some(json!({"title": "task", "project_id": project_id}))).await;
+    assert_eq!(status, StatusCode::OK);
+    let task_id = task["id"].as_i64().unwrap();
+
+    // a stranger cannot tell the project or the task exist
+    let attempts = [
+        ("POST", "/tasks".to_string(), Some(json!({"title": "intruder", "project_id": project_id}))),
+        ("DELETE", format!("/tasks/{}", task_id), None),
+        ("PATCH", format!("/tasks/{}/status", task_id), Some(json!({"status": "In Progress"}))),
+        ("POST", format!("/tasks/{}/assignees", task_id), Some(json!({"user_id": teammate_id}))),
+        ("GET", format!("/projects/{}/statuses", project_id), None),
+        ("DELETE", format!("/projects/{}", project_id), None),
+    ];
+    for (method, path, body) in attempts {
+        let (status, _) = app.call(method, &path, &stranger, body).await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "{} {}", method, path);
+    }
+    let (_, tasks) = app.call("GET", "/tasks", &stranger, None).await;
+    assert_eq!(tasks, json!([]));
+
+    // a teammate works on the task but does not own it
+    let con = db::get_conn(&app.db_pool).await.unwrap();
+    con.execute("INSERT INTO users_projects (user_id, project_id) VALUES ($1, $2)",
+                &[&teammate_id, &(project_id as i32)])
+        .await
+        .unwrap();
+    con.execute("INSERT INTO organization_members (organization_id, user_id) \
+    SELECT organization_id, $1 FROM projects WHERE id = $2",
+                &[&teammate_id, &(project_id as i32)])
+        .await
+        .unwrap();
+    let (status, _) = app.call("PATCH", &format!("/tasks/{}/status", task_id), &teammate,
+                           Some(json!({"status": "In Progress"}))).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app.call("DELETE", &format!("/tasks/{}", task_id), &teammate, None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = app.call("DELETE", &format!("/tasks/{}", task_id), &owner, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app.call("DELETE", &format!("/projects/{}", project_id), &owner, None).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+#[ignore]
+async fn promoted_admins_get_the_admin_routes() {
+    let app = TestApp::new().await;
+    let (user_id, _) = create_user(&app.db_pool, &app.jwt_keys).await;
+    set_password(&app, user_id, "secret").await;
+    let user = db::find_user_by_id(&app.db_pool, user_id).await.unwrap();
+    let login = json!({"email": user.email, "pwd": "secret"});
+    let (_, tokens) = app.call("POST", "/login", "", Some(login.clone())).await;
+    let (status, _) = app.call("GET", "/users", tokens["token"].as_str().unwrap(), None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    run_command(&["promote-admin".to_string(), user.email.clone()], &app.db_pool).await;
+    let (_, tokens) = app.call("POST", "/login", "", Some(login)).await;
+    let token = tokens["token"].as_str().unwrap();
+    let (status, _) = app.call("GET", "/users", token, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, profile) = app.call("GET", "/users/me", token, None).await;
+    assert_eq!(profile["role"], json!("Admin"));
+    assert!(matches!(db::promote_to_admin(&app.db_pool, "nobody@example.com").await, Err(crate::error::Error::NotFoundError)));
+}
+
+#[tokio::test]
+#[ignore]
+async fn verification_token_enables_user_once() {
+    let app = TestApp::new().await;
+
+    let con = db::get_conn(&app.db_pool).await.unwrap();
+    let email = format!("{}@example.com", Uuid::new_v4());
+    let row = con.query_one("INSERT INTO app_users (email, pwd) VALUES ($1, '') RETURNING id", &[&email])
+        .await
+        .unwrap();
+    let user_id: i32 = row.get(0);
+    let token = Uuid::new_v4().to_string();
+    let expired = Uuid::new_v4().to_string();
+    con.execute("INSERT INTO verification_tokens (user_id, token, expiry_date) \
+                 VALUES ($1, $2, now() + interval '1 hour'), ($1, $3, now() - interval '1 hour')",
+                &[&user_id, &token, &expired])
+        .await
+        .unwrap();
+
+    let (status, _) = app.call("GET", &format!("/registration/confirm?token={}", expired), "", None).await;
+    assert_eq!(status, StatusCode::GONE);
+    let (status, user) = app.call("GET", &format!("/registration/confirm?token={}", token), "", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(user["email"], json!(email));
+    let (status, _) = app.call("POST", &format!("/registration/confirm?token={}", token), "", None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let user = db::find_user_by_id(&app.db_pool, user_id).await.unwrap();
+    assert!(user.enabled);
+}
+
+#[tokio::test]
+#[ignore]
+async fn registration_sends_a_working_confirm_link() {
+    let app = TestApp::new().await;
+    let email = format!("{}@example.com", Uuid::new_v4());
+
+    let (status, _) = app.call("POST", "/registration", "", Some(json!({"email": email, "pwd": "secret"}))).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app.call("POST", "/login", "", Some(json!({"email": email, "pwd": "secret"}))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let sent = app.notifier.sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].to, email);
+    let path = sent[0].text.lines()
+        .find_map(|line| line.strip_prefix(app.config.app_base_url.as_str()))
+        .unwrap()
+        .to_string();
+    let (status, _) = app.call("GET", &path, "", None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app.call("POST", "/login", "", Some(json!({"email": email, "pwd": "secret"}))).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+#[ignore]
+async fn refresh_tokens_rotate_and_revoke_their_family() {
+    let app = TestApp::new().await;
+    let (user_id, _) = create_user(&app.db_pool, &app.jwt_keys).await;
+    let first = db::create_refresh_token(&app.db_pool, user_id, chrono::Duration::days(1)).await.unwrap();
+
+    let (status, rotated) = app.call("POST", "/token/refresh", "", Some(json!({"refresh_token": first}))).await;
+    assert_eq!(status, StatusCode::OK);
+    let second = rotated["refresh_token"].as_str().unwrap().to_string();
+    let (status, _) = app.call("GET", "/users/me", rotated["token"].as_str().unwrap(), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, rotated) = app.call("POST", "/token/refresh", "", Some(json!({"refresh_token": second}))).await;
+    assert_eq!(status, StatusCode::OK);
+    let third = rotated["refresh_token"].as_str().unwrap().to_string();
+
+    // replaying an exchanged token takes the newest one of the family with it
+    let (status, _) = app.call("POST", "/token/refresh", "", Some(json!({"refresh_token": first}))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = app.call("POST", "/token/refresh", "", Some(json!({"refresh_token": third}))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let login = db::create_refresh_token(&app.db_pool, user_id, chrono::Duration::days(1)).await.unwrap();
+    let (_, rotated) = app.call("POST", "/token/refresh", "", Some(json!({"refresh_token": login}))).await;
+    let latest = rotated["refresh_token"].as_str().unwrap().to_string();
+    let (status, _) = app.call("POST", "/logout/everywhere", "", Some(json!({"refresh_token": login}))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = app.call("POST", "/logout", "", Some(json!({"refresh_token": login}))).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app.call("POST", "/token/refresh", "", Some(json!({"refresh_token": latest}))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let expired = db::create_refresh_token(&app.db_pool, user_id, chrono::Duration::days(-1)).await.unwrap();
+    let (status, _) = app.call("POST", "/token/refresh", "", Some(json!({"refresh_token": expired}))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let con = db::get_conn(&app.db_pool).await.unwrap();
+    let row = con.query_one("SELECT count(*) FROM refresh_tokens WHERE user_id = $1 AND NOT revoked", &[&user_id])
+        .await
+        .unwrap();
+    assert_eq!(row.get::<_, i64>(0), 1);
+}
+
+#[tokio::test]
+#[ignore]
+async fn password_reset_token_is_single_use() {
+    let app = TestApp::new().await;
+    let (user_id, jwt) = create_user(&app.db_pool, &app.jwt_keys).await;
+    let user = db::find_user_by_id(&app.db_pool, user_id).await.unwrap();
+    let refresh_token = db::create_refresh_token(&app.db_pool, user_id, chrono::Duration::days(1)).await.unwrap();
+
+    let (status, _) = app.call("POST", "/password/forgot", "", Some(json!({"email": "nobody@example.com"}))).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let (status, _) = app.call("POST", "/password/forgot", "", Some(json!({"email": user.email}))).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let sent = app.notifier.sent();
+    assert_eq!(sent.len(), 1);
+    let token = sent[0].text.lines()
+        .find(|line| line.len() == 64)
+        .unwrap();
+
+    let reset = json!({"token": token, "pwd": "changed"});
+    let (status, _) = app.call("POST", "/password/reset", "", Some(reset.clone())).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app.call("POST", "/password/reset", "", Some(reset)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, _) = app.call("POST", "/token/refresh", "", Some(json!({"refresh_token": refresh_token}))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = app.call("GET", "/users/me", &jwt, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, login) = app.call("POST", "/login", "", Some(json!({"email": user.email, "pwd": "changed"}))).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app.call("GET", "/users/me", login["token"].as_str().unwrap(), None).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+#[ignore]
+async fn password_change_ends_the_other_sessions() {
+    let app = TestApp::new().await;
+    let (user_id, _) = create_user(&app.db_pool, &app.jwt_keys).await;
+    let session = set_password(&app, user_id, "secret").await;
+    let user = db::find_user_by_id(&app.db_pool, user_id).await.unwrap();
+    let (_, other) = app.call("POST", "/login", "", Some(json!({"email": user.email, "pwd": "secret"}))).await;
+    let other = other["token"].as_str().unwrap();
+    let (status, _) = app.call("GET", "/users/me", other, None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = app.call("PUT", "/users/me/password", &session,
+                               Some(json!({"current_pwd": "wrong", "new_pwd": "changed"}))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, renewed) = app.call("PUT", "/users/me/password", &session,
+                                     Some(json!({"current_pwd": "secret", "new_pwd": "changed"}))).await;
+    assert_eq!(status, StatusCode::OK);
+    for token in [session.as_str(), other] {
+        let (status, _) = app.call("GET", "/users/me", token, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+    let (status, _) = app.call("GET", "/users/me", renewed["token"].as_str().unwrap(), None).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+#[ignore]
+async fn email_change_waits_for_confirmation() {
+    let app = TestApp::new().await;
+    let (user_id, _) = create_user(&app.db_pool, &app.jwt_keys).await;
+    let token = set_password(&app, user_id, "secret").await;
+    let new_email = format!("{}@example.com", Uuid::new_v4());
+
+    let (status, _) = app.call("POST", "/users/me/email", &token, Some(json!({"email": new_email, "pwd": "wrong"}))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = app.call("POST", "/users/me/email", &token, Some(json!({"email": new_email, "pwd": "secret"}))).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let (_, profile) = app.call("GET", "/users/me", &token, None).await;
+    assert_ne!(profile["email"], json!(new_email));
+
+    let sent = app.notifier.sent();
+    assert_eq!(sent[0].to, new_email);
+    let path = sent[0].text.lines()
+        .find_map(|line| line.strip_prefix(app.config.app_base_url.as_str()))
+        .unwrap()
+        .to_string();
+    let (status, _) = app.call("GET", &path, "", None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, profile) = app.call("GET", "/users/me", &token, None).await;
+    assert_eq!(profile["email"], json!(new_email));
+}
+
+#[tokio::test]
+#[ignore]
+async fn access_tokens_are_limited_to_their_scopes() {
+    let app = TestApp::new().await;
+    let (_, session) = create_user(&app.db_pool, &app.jwt_keys).await;
+
+    let (status, _) = app.call("POST", "/tokens", &session,
+                               Some(json!({"name": "ci", "scopes": ["tasks:everything"]}))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    for days in [0, -1, 3651, 9999999999_i64] {
+        let (status, _) = app.call("POST", "/tokens", &session,
+                                   Some(json!({"name": "ci", "scopes": ["tasks:read"], "expires_in_days": days}))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", days);
+    }
+    let (status, created) = app.call("POST", "/tokens", &session,
+                                     Some(json!({"name": "ci", "scopes": ["tasks:read"], "expires_in_days": 1}))).await;
+    assert_eq!(status, StatusCode::OK);
+    let token = created["token"].as_str().unwrap();
+    let token_id = created["access_token"]["id"].as_i64().unwrap();
+
+    let (status, _) = app.call("GET", "/tasks", token, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app.call("POST", "/projects", token, Some(json!({"title": "ci"}))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    // tokens cannot mint further tokens
+    let (status, _) = app.call("GET", "/tokens", token, None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = app.call("DELETE", &format!("/tokens/{}", token_id), &session, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app.call("GET", "/tasks", token, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+#[ignore]
+async fn two_factor_login_needs_a_second_step() {
+    let app = TestApp::new().await;
+    let (user_id, _) = create_user(&app.db_pool, &app.jwt_keys).await;
+    let session = set_password(&app, user_id, "secret").await;
+    let user = db::find_user_by_id(&app.db_pool, user_id).await.unwrap();
+    let login = json!({"email": user.email, "pwd": "secret"});
+
+    let (status, enrollment) = app.call("POST", "/users/me/2fa", &session, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let secret = enrollment["secret"].as_str().unwrap();
+    let recovery_code = enrollment["recovery_codes"][0].as_str().unwrap();
+    assert!(enrollment["otpauth_uri"].as_str().unwrap().contains(secret));
+    // nothing changes until the app is proven to work
+    let (_, response) = app.call("POST", "/login", "", Some(login.clone())).await;
+    assert!(response["token"].is_string());
+    let (status, _) = app.call("POST", "/users/me/2fa/confirm", &session,
+                               Some(json!({"code": totp::code(secret, Utc::now())}))).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, challenge) = app.call("POST", "/login", "", Some(login.clone())).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(challenge["token"].is_null());
+    // a challenge is answered once, with a code not used before
+    let next_code = totp::code(secret, Utc::now() + chrono::Duration::seconds(30));
+    let (status, response) = app.call("POST", "/login/2fa", "",
+                                      Some(json!({"challenge": challenge["challenge"], "code": next_code}))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(response["token"].is_string());
+    let (status, _) = app.call("POST", "/login/2fa", "",
+                               Some(json!({"challenge": challenge["challenge"], "code": next_code}))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (_, challenge) = app.call("POST", "/login", "", Some(login.clone())).await;
+    let answer = json!({"challenge": challenge["challenge"], "recovery_code": recovery_code.to_uppercase()});
+    let (status, _) = app.call("POST", "/login/2fa", "", Some(answer)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, challenge) = app.call("POST", "/login", "", Some(login)).await;
+    let answer = json!({"challenge": challenge["challenge"], "recovery_code": recovery_code});
+    let (status, _) = app.call("POST", "/login/2fa", "", Some(answer)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+#[ignore]
+async fn oidc_login_maps_provider_identities_to_users() {
+    let idp = MockIdp::start().await;
+    let app = TestApp::with_config(idp.config(false)).await;
+    let (user_id, _) = create_user(&app.db_pool, &app.jwt_keys).await;
+    let user = db::find_user_by_id(&app.db_pool, user_id).await.unwrap();
+    let subject = Uuid::new_v4().to_string();
+
+    // an existing account is linked by its email, later logins by the subject alone
+    for email in [user.email.as_str(), "changed@example.com"] {
+        let authorization_url = app.redirect("/oidc/login").await;
+        assert!(authorization_url.contains("code_challenge_method=S256"));
+        let callback = idp.login(&authorization_url, &subject, email, &app.config).await;
+        let (status, response) = app.call("GET", &callback, "", None).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, profile) = app.call("GET", "/users/me", response["token"].as_str().unwrap(), None).await;
+        assert_eq!(profile["id"], json!(user_id));
+        // the state is used up
+        let (status, _) = app.call("GET", &callback, "", None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    let stranger = format!("{}@example.com", Uuid::new_v4());
+    let authorization_url = app.redirect("/oidc/login").await;
+    let callback = idp.login(&authorization_url, &Uuid::new_v4().to_string(), &stranger, &app.config).await;
+    let (status, _) = app.call("GET", &callback, "", None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // the user did not consent
+    let authorization_url = reqwest::Url::parse(&app.redirect("/oidc/login").await).unwrap();
+    let (_, state) = authorization_url.query_pairs()
+        .find(|(name, _)| name == "state")
+        .unwrap();
+    let callback = format!("/oidc/callback?state={}&error=access_denied", state);
+    let (status, error) = app.call("GET", &callback, "", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(error["message"], json!("Login refused by the identity provider"));
+
+    // an unconfirmed account with the email, maybe registered by someone else, and an account
+    // with a second factor are not linked
+    let con = db::get_conn(&app.db_pool).await.unwrap();
+    let unconfirmed = format!("{}@example.com", Uuid::new_v4());
+    con.execute("INSERT INTO app_users (email, pwd, enabled) VALUES ($1, '', false)", &[&unconfirmed])
+        .await
+        .unwrap();
+    let (protected_id, _) = create_user(&app.db_pool, &app.jwt_keys).await;
+    con.execute("UPDATE app_users SET totp_enabled = true WHERE id = $1", &[&protected_id])
+        .await
+        .unwrap();
+    let protected = db::find_user_by_id(&app.db_pool, protected_id).await.unwrap().email;
+    for email in [unconfirmed, protected] {
+        let subject = Uuid::new_v4().to_string();
+        let authorization_url = app.redirect("/oidc/login").await;
+        let callback = idp.login(&authorization_url, &subject, &email, &app.config).await;
+        let (status, _) = app.call("GET", &callback, "", None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{}", email);
+        let row = con.query_one("SELECT count(*) FROM user_identities WHERE subject = $1", &[&subject])
+            .await
+            .unwrap();
+        assert_eq!(row.get::<_, i64>(0), 0);
+    }
+
+    let app = TestApp::with_config(idp.config(true)).await;
+    let authorization_url = app.redirect("/oidc/login").await;
+    let callback = idp.login(&authorization_url, &Uuid::new_v4().to_string(), &stranger, &app.config).await;
+    let (status, response) = app.call("GET", &callback, "", None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, profile) = app.call("GET", "/users/me", response["token"].as_str().unwrap(), None).await;
+    assert_eq!(profile["email"], json!(stranger));
+    assert_eq!(profile["first_name"], json!("Jane"));
+}
+
+#[tokio::test]
+#[ignore]
+async fn device_login_completes_in_the_browser() {
+    let idp = MockIdp::start().await;
+    let app = TestApp::with_config(idp.config(true)).await;
+
+    let (status, authorization) = app.call("POST", "/oidc/device", "", None).await;
+    assert_eq!(status, StatusCode::OK);
+    let poll = json!({"device_code": authorization["device_code"]});
+    let (status, _) = app.call("POST", "/oidc/device/token", "", Some(poll.clone())).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+
+    let verification_uri = authorization["verification_uri_complete"].as_str().unwrap();
+    let path = verification_uri.strip_prefix(app.config.app_base_url.as_str()).unwrap();
+    let authorization_url = app.redirect(path).await;
+    let email = format!("{}@example.com", Uuid::new_v4());
+    let callback = idp.login(&authorization_url, &Uuid::new_v4().to_string(), &email, &app.config).await;
+    let (status, _) = app.call("GET", &callback, "", None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, response) = app.call("POST", "/oidc/device/token", "", Some(poll.clone())).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, profile) = app.call("GET", "/users/me", response["token"].as_str().unwrap(), None).await;
+    assert_eq!(profile["email"], json!(email));
+    let (status, _) = app.call("POST", "/oidc/device/token", "", Some(poll)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+#[ignore]
+async fn admins_manage_and_delete_users() {
+    let app = TestApp::new().await;
+    let (_, admin) = create_user_with_role(&app.db_pool, &app.jwt_keys, "Admin").await;
+    let (leaver_id, leaver) = create_user(&app.db_pool, &app.jwt_keys).await;
+    let (successor_id, successor) = create_user(&app.db_pool, &app.jwt_keys).await;
+
+    let (_, project) = app.call("POST", "/projects", &leaver, Some(json!({"title": "handover"}))).await;
+    let project_id = project["id"].as_i64().unwrap();
+    let (_, task) = app.call("POST", "/tasks", &leaver,
+                             Some(json!({"title": "task", "project_id": project_id}))).await;
+
+    let (status, _) = app.call("GET", &format!("/users/{}", successor_id), &leaver, None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, user) = app.call("PATCH", &format!("/users/{}", leaver_id), &admin,
+                                  Some(json!({"enabled": false}))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(user["enabled"], json!(false));
+    assert_eq!(user["role"], json!("User"));
+    let (status, _) = app.call("GET", "/users/me", &leaver, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = app.call("PATCH", &format!("/users/{}", leaver_id), &admin,
+                               Some(json!({"role": "Boss"}))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // a demoted admin loses the admin routes with the token they already have
+    let (demoted_id, demoted) = create_user_with_role(&app.db_pool, &app.jwt_keys, "Admin").await;
+    let (status, _) = app.call("GET", &format!("/users/{}", successor_id), &demoted, None).await;
+    assert_eq!(status, StatusCode::OK);
+    app.call("PATCH", &format!("/users/{}", demoted_id), &admin, Some(json!({"role": "User"}))).await;
+    let (status, _) = app.call("GET", &format!("/users/{}", successor_id), &demoted, None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = app.call("POST", &format!("/users/{}/password/reset", leaver_id), &admin, None).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let (_, user) = app.call("GET", &format!("/users/{}", leaver_id), &admin, None).await;
+    assert_eq!(app.notifier.sent()[0].to, user["email"].as_str().unwrap());
+
+    let path = format!("/users/{}?strategy=reassign&reassign_to={}", leaver_id, leaver_id);
+    let (status, _) = app.call("DELETE", &path, &admin, None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let path = format!("/users/{}?strategy=reassign&reassign_to={}", leaver_id, successor_id);
+    let (status, _) = app.call("DELETE", &path, &admin, None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = app.call("GET", &format!("/users/{}", leaver_id), &admin, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (_, tasks) = app.call("GET", "/tasks", &successor, None).await;
+    assert_eq!(tasks[0]["id"], task["id"]);
+    assert_eq!(tasks[0]["reporter_id"], json!(successor_id));
+
+    // without a successor the projects nobody else works on go with the user, and so does
+    // their personal organization but no one else's
+    let con = db::get_conn(&app.db_pool).await.unwrap();
+    let row = con.query_one("INSERT INTO organizations (name) VALUES ('empty') RETURNING id", &[])
+        .await
+        .unwrap();
+    let empty_id: i32 = row.get(0);
+    let row = con.query_one("SELECT min(organization_id) FROM organization_members WHERE user_id = $1", &[&successor_id])
+        .await
+        .unwrap();
+    let personal_id: i32 = row.get(0);
+    let path = format!("/users/{}?strategy=cascade", successor_id);
+    let (status, _) = app.call("DELETE", &path, &admin, None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let row = con.query_one("SELECT count(*) FROM projects WHERE id = $1", &[&(project_id as i32)])
+        .await
+        .unwrap();
+    assert_eq!(row.get::<_, i64>(0), 0);
+    let rows = con.query("SELECT id FROM organizations WHERE id = $1 OR id = $2", &[&empty_id, &personal_id])
+        .await
+        .unwrap();
+    assert_eq!(rows.iter().map(|row| row.get(0)).collect::<Vec<i32>>(), vec![empty_id]);
+}
+
+#[tokio::test]
+#[ignore]
+async fn invitations_add_members_to_a_project() {
+    let app = TestApp::new().await;
+    let (_, owner) = create_user(&app.db_pool, &app.jwt_keys).await;
+    let (guest_id, guest) = create_user(&app.db_pool, &app.jwt_keys).await;
+    let (_, stranger) = create_user(&app.db_pool, &app.jwt_keys).await;
+    let (_, owner_profile) = app.call("GET", "/users/me", &owner, None).await;
+    let (_, guest_profile) = app.call("GET", "/users/me", &guest, None).await;
+
+    let (_, project) = app.call("POST", "/projects", &owner, Some(json!({"title": "shared"}))).await;
+    let project_id = project["id"].as_i64().unwrap();
+    let invitations = format!("/projects/{}/invitations", project_id);
+    let (status, _) = app.call("POST", &invitations, &owner, Some(json!({"email": owner_profile["email"]}))).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, _) = app.call("POST", &invitations, &stranger, Some(json!({"email": guest_profile["email"]}))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, invitation) = app.call("POST", &invitations, &owner, Some(json!({"email": guest_profile["email"]}))).await;
+    assert_eq!(status, StatusCode::OK);
+    let sent = app.notifier.sent();
+    assert_eq!(sent[0].to, guest_profile["email"].as_str().unwrap());
+    let token = sent[0].text.lines()
+        .find(|line| line.len() == 64)
+        .unwrap();
+
+    let (_, pending) = app.call("GET", "/invitations", &guest, None).await;
+    assert_eq!(pending[0]["id"], invitation["id"]);
+    assert_eq!(pending[0]["project_title"], json!("shared"));
+    let (status, _) = app.call("POST", "/invitations/accept", &stranger, Some(json!({"token": token}))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, joined) = app.call("POST", "/invitations/accept", &guest, Some(json!({"token": token}))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(joined["id"], json!(project_id));
+    let (status, _) = app.call("POST", "/invitations/accept", &guest, Some(json!({"token": token}))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let members = format!("/projects/{}/members", project_id);
+    let (_, listed) = app.call("GET", &members, &guest, None).await;
+    assert_eq!(listed.as_array().unwrap().len(), 2);
+
+    // a removed member also drops off the tasks of the project
+    let (_, task) = app.call("POST", "/tasks", &owner, Some(json!({"title": "task", "project_id": project_id}))).await;
+    let task_id = task["id"].as_i64().unwrap();
+    app.call("POST", &format!("/tasks/{}/assignees", task_id), &owner, Some(json!({"user_id": guest_id}))).await;
+    app.call("POST", "/tasks", &guest, Some(json!({"title": "reported", "project_id": project_id}))).await;
+    let (_, tasks) = app.call("GET", "/tasks", &guest, None).await;
+    assert_eq!(tasks.as_array().unwrap().len(), 2);
+    let (status, _) = app.call("DELETE", &format!("{}/{}", members, guest_id), &owner, None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = app.call("GET", &members, &guest, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (_, tasks) = app.call("GET", "/tasks", &owner, None).await;
+    assert_eq!(tasks[0]["assignees"], json!([]));
+    let (_, tasks) = app.call("GET", "/tasks", &guest, None).await;
+    assert_eq!(tasks, json!([]));
+    let (status, _) = app.call("POST", &format!("/projects/{}/leave", project_id), &owner, None).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let email = format!("{}@example.com", Uuid::new_v4());
+    app.call("POST", &invitations, &owner, Some(json!({"email": email}))).await;
+    let token = app.notifier.sent()[1].text.lines()
+        .find(|line| line.len() == 64)
+        .unwrap()
+        .to_string();
+    let (status, _) = app.call("POST", "/invitations/decline", "", Some(json!({"token": token}))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = app.call("POST", "/invitations/decline", "", Some(json!({"token": token}))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+async fn join_project(app: &TestApp, inviter: &str, project_id: i64, jwt: &str, role: &str) {
+    let (_, profile) = app.call("GET", "/users/me", jwt, None).await;
+    let (status, _) = app.call("POST", &format!("/projects/{}/invitations", project_id), inviter,
+                               Some(json!({"email": profile["email"], "role": role}))).await;
+    assert_eq!(status, StatusCode::OK);
+    let token = app.notifier.sent().last().unwrap().text.lines()
+        .find(|line| line.len() == 64)
+        .unwrap()
+        .to_string();
+    let (status, _) = app.call("POST", "/invitations/accept", jwt, Some(json!({"token": token}))).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+#[ignore]
+async fn project_roles_limit_members_and_ownership_moves_by_transfer() {
+    let app = TestApp::new().await;
+    let (owner_id, owner) = create_user(&app.db_pool, &app.jwt_keys).await;
+    let (maintainer_id, maintainer) = create_user(&app.db_pool, &app.jwt_keys).await;
+    let (viewer_id, viewer) = create_user(&app.db_pool, &app.jwt_keys).await;
+    let (_, project) = app.call("POST", "/projects", &owner, Some(json!({"title": "roles"}))).await;
+    let project_id = project["id"].as_i64().unwrap();
+    let members = format!("/projects/{}/members", project_id);
+
+    let (status, _) = app.call("POST", &format!("/projects/{}/invitations", project_id), &owner,
+                               Some(json!({"email": "someone@example.com", "role": "owner"}))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    join_project(&app, &owner, project_id, &maintainer, "maintainer").await;
+    join_project(&app, &maintainer, project_id, &viewer, "viewer").await;
+    let (_, listed) = app.call("GET", &members, &viewer, None).await;
+    assert_eq!(listed[0]["role"], json!("owner"));
+
+    let (status, _) = app.call("POST", "/tasks", &viewer, Some(json!({"title": "task", "project_id": project_id}))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let viewer_role = format!("{}/{}/role", members, viewer_id);
+    let (status, _) = app.call("PUT", &viewer_role, &viewer, Some(json!({"role": "member"}))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, updated) = app.call("PUT", &viewer_role, &maintainer, Some(json!({"role": "member"}))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(updated["role"], json!("member"));
+    let (status, _) = app.call("POST", "/tasks", &viewer, Some(json!({"title": "task", "project_id": project_id}))).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app.call("DELETE", &format!("{}/{}", members, owner_id), &maintainer, None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = app.call("DELETE", &format!("/projects/{}", project_id), &maintainer, None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // the project always keeps an owner
+    let (status, _) = app.call("POST", &format!("/projects/{}/leave", project_id), &owner, None).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let transfer = format!("/projects/{}/transfer", project_id);
+    let (status, _) = app.call("POST", &transfer, &maintainer, Some(json!({"user_id": maintainer_id}))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, new_owner) = app.call("POST", &transfer, &owner, Some(json!({"user_id": maintainer_id}))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(new_owner["role"], json!("owner"));
+    let (_, listed) = app.call("GET", &members, &owner, None).await;
+    assert_eq!(listed[0]["role"], json!("maintainer"));
+    let (status, _) = app.call("POST", &format!("/projects/{}/leave", project_id), &owner, None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+}
+
+#[tokio::test]
+#[ignore]
+async fn organizations_keep_their_projects_apart() {
+    let app = TestApp::new().await;
+    let (admin_id, admin) = create_user(&app.db_pool, &app.jwt_keys).await;
+    let (colleague_id, colleague) = create_user(&app.db_pool, &app.jwt_keys).await;
+    let (_, admin_profile) = app.call("GET", "/users/me", &admin, None).await;
+    let (_, colleague_profile) = app.call("GET", "/users/me", &colleague, None).await;
+
+    let (_, personal) = app.call("GET", "/organizations", &admin, None).await;
+    let personal = personal[0]["id"].to_string();
+    let (status, acme) = app.call("POST", "/organizations", &admin, Some(json!({"name": "acme"}))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(acme["role"], json!("admin"));
+    let acme = acme["id"].to_string();
+    let members = format!("/organizations/{}/members", acme);
+
+    // without a header new projects go to the organization the user joined first
+    let (_, private) = app.call("POST", "/projects", &admin, Some(json!({"title": "private"}))).await;
+    let (_, shared) = app.call_in(&acme, "POST", "/projects", &admin, Some(json!({"title": "shared"}))).await;
+    let shared_id = shared["id"].as_i64().unwrap();
+    let (_, listed) = app.call_in(&acme, "GET", "/projects", &admin, None).await;
+    assert_eq!(listed, json!([shared]));
+    let (_, listed) = app.call_in(&personal, "GET", "/projects", &admin, None).await;
+    assert_eq!(listed, json!([private]));
+    let (_, listed) = app.call("GET", "/projects", &admin, None).await;
+    assert_eq!(listed.as_array().unwrap().len(), 2);
+    let (status, _) = app.call_in(&personal, "GET", &format!("/projects/{}/members", shared_id), &admin, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = app.call_in("acme", "GET", "/projects", &admin, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // outsiders neither see the organization nor get in by naming it
+    let (status, _) = app.call("GET", &members, &colleague, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = app.call_in(&acme, "POST", "/projects", &colleague, Some(json!({"title": "intruder"}))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = app.call("POST", &members, &admin, Some(json!({"email": colleague_profile["email"], "role": "owner"}))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, member) = app.call("POST", &members, &admin, Some(json!({"email": colleague_profile["email"]}))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(member["role"], json!("member"));
+    let (status, _) = app.call("POST", &members, &colleague, Some(json!({"email": admin_profile["email"]}))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = app.call("PUT", &format!("{}/{}/role", members, admin_id), &admin, Some(json!({"role": "member"}))).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    join_project(&app, &admin, shared_id, &colleague, "member").await;
+    let (_, listed) = app.call_in(&acme, "GET", "/projects", &colleague, None).await;
+    assert_eq!(listed.as_array().unwrap().len(), 1);
+    let (status, _) = app.call("DELETE", &format!("{}/{}", members, colleague_id), &admin, None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = app.call("GET", &format!("/projects/{}/members", shared_id), &colleague, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = app.call("DELETE", &format!("{}/{}", members, admin_id), &admin, None).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+#[ignore]
+async fn tasks_are_edited_and_moved_in_place() {
+    let app = TestApp::new().await;
+    let (_, owner) = create_user(&app.db_pool, &app.jwt_keys).await;
+    let (teammate_id, teammate) = create_user(&app.db_pool, &app.jwt_keys).await;
+    let (_, stranger) = create_user(&app.db_pool, &app.jwt_keys).await;
+    let (_, source) = app.call("POST", "/projects", &owner, Some(json!({"title": "source"}))).await;
+    let source_id = source["id"].as_i64().unwrap();
+    let (_, target) = app.call("POST", "/projects", &owner, Some(json!({"title": "target"}))).await;
+    let target_id = target["id"].as_i64().unwrap();
+    join_project(&app, &owner, source_id, &teammate, "member").await;
+    let (_, task) = app.call("POST", "/tasks", &owner,
+                             Some(json!({"title": "tpyo", "description": "keep", "project_id": source_id}))).await;
+    let task_path = format!("/tasks/{}", task["id"]);
+    app.call("POST", &format!("{}/assignees", task_path), &owner, Some(json!({"user_id": teammate_id}))).await;
+    app.call("PATCH", &format!("{}/status", task_path), &owner, Some(json!({"status": "In Progress"}))).await;
+
+    let (status, updated) = app.call("PATCH", &task_path, &teammate, Some(json!({"title": "typo"}))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(updated["id"], task["id"]);
+    assert_eq!(updated["title"], json!("typo"));
+    assert_eq!(updated["description"], json!("keep"));
+    let (status, _) = app.call("PATCH", &task_path, &stranger, Some(json!({"title": "mine"}))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = app.call("PATCH", &task_path, &owner,
+                               Some(json!({"start_at": "2030-01-02T00:00:00Z", "due_at": "2030-01-01T00:00:00Z"}))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    app.call("PATCH", &task_path, &owner,
+             Some(json!({"start_at": "2030-01-01T00:00:00Z", "due_at": "2030-01-02T00:00:00Z"}))).await;
+    let (status, cleared) = app.call("PATCH", &task_path, &owner,
+                                     Some(json!({"description": null, "due_at": null}))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(cleared["description"], Value::Null);
+    assert_eq!(cleared["due_at"], Value::Null);
+    assert_eq!(cleared["start_at"], json!("2030-01-01T00:00:00Z"));
+    assert_eq!(cleared["title"], json!("typo"));
+
+    // the teammate may not create tasks in the target project, so may not move there either
+    let (status, _) = app.call("PATCH", &task_path, &teammate, Some(json!({"project_id": target_id}))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, moved) = app.call("PATCH", &task_path, &owner, Some(json!({"project_id": target_id}))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(moved["project_id"], json!(target_id));
+    assert_eq!(moved["status"], json!("In Progress"));
+    assert_eq!(moved["assignees"], json!([]));
+}
+
+#[tokio::test]
+#[ignore]
+async fn projects_are_shown_edited_and_archived() {
+    let app = TestApp::new().await;
+    let (_, owner) = create_user(&app.db_pool, &app.jwt_keys).await;
+    let (_, teammate) = create_user(&app.db_pool, &app.jwt_keys).await;
+    let (_, project) = app.call("POST", "/projects", &owner,
+                                Some(json!({"title": "tpyo", "description": "keep"}))).await;
+    let project_id = project["id"].as_i64().unwrap();
+    let project_path = format!("/projects/{}", project_id);
+    join_project(&app, &owner, project_id, &teammate, "member").await;
+    let (_, task) = app.call("POST", "/tasks", &owner, Some(json!({"title": "a", "project_id": project_id}))).await;
+    app.call("POST", "/tasks", &owner, Some(json!({"title": "b", "project_id": project_id}))).await;
+    app.call("PATCH", &format!("/tasks/{}/status", task["id"]), &owner, Some(json!({"status": "In Progress"}))).await;
+
+    let (status, details) = app.call("GET", &project_path, &teammate, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(details["title"], json!("tpyo"));
+    assert_eq!(details["task_count"], json!(2));
+    assert_eq!(details["status_counts"][0], json!({"status": "Todo", "done": false, "count": 1}));
+    assert_eq!(details["status_counts"][1], json!({"status": "In Progress", "done": false, "count": 1}));
+
+    let (status, _) = app.call("PATCH", &project_path, &teammate, Some(json!({"title": "mine"}))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, updated) = app.call("PATCH", &project_path, &owner, Some(json!({"title": "typo"}))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(updated["title"], json!("typo"));
+    assert_eq!(updated["description"], json!("keep"));
+
+    let (status, archived) = app.call("POST", &format!("{}/archive", project_path), &owner, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(archived["archived_at"].is_string());
+    let (_, projects) = app.call("GET", "/projects", &owner, None).await;
+    assert_eq!(projects, json!([]));
+    let (_, projects) = app.call("GET", "/projects?archived=true", &owner, None).await;
+    assert_eq!(projects[0]["id"], json!(project_id));
+    // archiving keeps the tasks
+    let (status, _) = app.call("PATCH", &format!("/tasks/{}", task["id"]), &owner, Some(json!({"title": "c"}))).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, restored) = app.call("POST", &format!("{}/unarchive", project_path), &owner, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(restored["archived_at"], Value::Null);
+    let (_, projects) = app.call("GET", "/projects", &owner, None).await;
+    assert_eq!(projects[0]["id"], json!(project_id));
+}
+
+#[tokio::test]
+#[ignore]
+async fn projects_with_tasks_are_deleted_only_with_a_strategy() {
+    let app = TestApp::new().await;
+    let (_, owner) = create_user(&app.db_pool, &app.jwt_keys).await;
+    let (teammate_id, teammate) = create_user(&app.db_pool, &app.jwt_keys).await;
+    let (_, project) = app.call("POST", "/projects", &owner, Some(json!({"title": "old"}))).await;
+    let project_id = project["id"].as_i64().unwrap();
+    let project_path = format!("/projects/{}", project_id);
+    let (_, target) = app.call("POST", "/projects", &owner, Some(json!({"title": "new"}))).await;
+    let target_id = target["id"].as_i64().unwrap();
+    join_project(&app, &owner, project_id, &teammate, "member").await;
+    let (_, task) = app.call("POST", "/tasks", &owner, Some(json!({"title": "a", "project_id": project_id}))).await;
+    app.call("POST", &format!("/tasks/{}/assignees", task["id"]), &owner, Some(json!({"user_id": teammate_id}))).await;
+    app.call("PATCH", &format!("/tasks/{}/status", task["id"]), &owner, Some(json!({"status": "In Progress"}))).await;
+
+    let (status, refused) = app.call("DELETE", &project_path, &owner, None).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(refused["task_count"], json!(1));
+    assert_eq!(refused["status_counts"][1], json!({"status": "In Progress", "done": false, "count": 1}));
+    let (status, _) = app.call("DELETE", &format!("{}?strategy=move", project_path), &owner, None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = app.call("DELETE", &format!("{}?strategy=move&move_to={}", project_path, project_id), &owner, None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, _) = app.call("DELETE", &format!("{}?strategy=move&move_to={}", project_path, target_id), &owner, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app.call("GET", &project_path, &owner, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (_, details) = app.call("GET", &format!("/projects/{}", target_id), &owner, None).await;
+    assert_eq!(details["task_count"], json!(1));
+    assert_eq!(details["status_counts"][1]["count"], json!(1));
+    let (_, moved) = app.call("PATCH", &format!("/tasks/{}", task["id"]), &owner, Some(json!({}))).await;
+    assert_eq!(moved["project_id"], json!(target_id));
+    assert_eq!(moved["assignees"], json!([]));
+
+    let target_path = format!("/projects/{}", target_id);
+    let (status, _) = app.call("DELETE", &format!("{}?strategy=cascade", target_path), &owner, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app.call("PATCH", &format!("/tasks/{}", task["id"]), &owner, Some(json!({}))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+#[ignore]
+async fn deleted_tasks_and_projects_wait_in_the_trash() {
+    let app = TestApp::new().await;
+    let (_, owner) = create_user(&app.db_pool, &app.jwt_keys).await;
+    let (_, teammate) = create_user(&app.db_pool, &app.jwt_keys).await;
+    let (_, project) = app.call("POST", "/projects", &owner, Some(json!({"title": "project"}))).await;
+    let project_id = project["id"].as_i64().unwrap();
+    join_project(&app, &owner, project_id, &teammate, "member").await;
+    let (_, first) = app.call("POST", "/tasks", &owner, Some(json!({"title": "first", "project_id": project_id}))).await;
+    let (_, second) = app.call("POST", "/tasks", &owner, Some(json!({"title": "second", "project_id": project_id}))).await;
+
+    let (status, _) = app.call("DELETE", &format!("/tasks/{}", first["id"]), &owner, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, tasks) = app.call("GET", "/tasks", &owner, None).await;
+    assert_eq!(tasks.as_array().unwrap().len(), 1);
+    let (status, _) = app.call("PATCH", &format!("/tasks/{}", first["id"]), &owner, Some(json!({"title": "x"}))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (_, trash) = app.call("GET", "/trash", &teammate, None).await;
+    assert_eq!(trash[0]["kind"], json!("task"));
+    assert_eq!(trash[0]["id"], first["id"]);
+    // restoring takes the permission to delete, members only delete what they reported
+    let restore_first = format!("/trash/tasks/{}/restore", first["id"]);
+    let (status, _) = app.call("POST", &restore_first, &teammate, None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, restored) = app.call("POST", &restore_first, &owner, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(restored["title"], json!("first"));
+    let (status, _) = app.call("POST", &restore_first, &owner, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // a task trashed on its own stays in the trash when the project comes back
+    app.call("DELETE", &format!("/tasks/{}", first["id"]), &owner, None).await;
+    let project_path = format!("/projects/{}", project_id);
+    let (status, _) = app.call("DELETE", &format!("{}?strategy=cascade", project_path), &owner, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app.call("GET", &project_path, &owner, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (_, trash) = app.call("GET", "/trash", &owner, None).await;
+    assert_eq!(trash, json!([{"kind": "project", "id": project_id, "title": "project", "deleted_at": trash[0]["deleted_at"]}]));
+    let restore_project = format!("/trash/projects/{}/restore", project_id);
+    let (status, _) = app.call("POST", &restore_project, &teammate, None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = app.call("POST", &restore_project, &owner, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, details) = app.call("GET", &project_path, &owner, None).await;
+    assert_eq!(details["task_count"], json!(1));
+    let (_, trash) = app.call("GET", "/trash", &owner, None).await;
+    assert_eq!(trash[0]["id"], first["id"]);
+
+    // nothing is purged before the retention period is over
+    assert_eq!(db::purge_trash(&app.db_pool, chrono::Duration::days(1)).await.unwrap(), 0);
+    app.call("DELETE", &format!("{}?strategy=cascade", project_path), &owner, None).await;
+    db::purge_trash(&app.db_pool, chrono::Duration::zero()).await.unwrap();
+    let (_, trash) = app.call("GET", "/trash", &owner, None).await;
+    assert_eq!(trash, json!([]));
+    let con = db::get_conn(&app.db_pool).await.unwrap();
+    let remaining = con.query("SELECT id FROM tasks WHERE id = ANY($1) UNION SELECT id FROM projects WHERE id = $2",
+                              &[&vec![first["id"].as_i64().unwrap() as i32, second["id"].as_i64().unwrap() as i32],
+                                  &(project_id as i32)])
+        .await
+        .unwrap();
+    assert!(remaining.is_empty());
+}
+
+#[tokio::test]
+#[ignore]
+async fn subtasks_form_trees_without_cycles() {
+    let app = TestApp::new().await;
+    let (_, owner) = create_user(&app.db_pool, &app.jwt_keys).await;
+    let (_, project) = app.call("POST", "/projects", &owner, Some(json!({"title": "project"}))).await;
+    let project_id = project["id"].as_i64().unwrap();
+    let (_, other) = app.call("POST", "/projects", &owner, Some(json!({"title": "other"}))).await;
+    let other_id = other["id"].as_i64().unwrap();
+    let (_, root) = app.call("POST", "/tasks", &owner, Some(json!({"title": "root", "project_id": project_id}))).await;
+    let mut chain = vec![root.clone()];
+    for depth in 2..=5 {
+        let parent = chain.last().unwrap()["id"].clone();
+        let (status, task) = app.call("POST", "/tasks", &owner,
+                                      Some(json!({"title": format!("level {}", depth), "project_id": project_id, "parent_id": parent}))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(task["parent_id"], parent);
+        chain.push(task);
+    }
+    let (status, _) = app.call("POST", "/tasks", &owner,
+                               Some(json!({"title": "too deep", "project_id": project_id, "parent_id": chain[4]["id"]}))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (_, foreign) = app.call("POST", "/tasks", &owner, Some(json!({"title": "foreign", "project_id": other_id}))).await;
+    let (status, _) = app.call("POST", "/tasks", &owner,
+                               Some(json!({"title": "x", "project_id": project_id, "parent_id": foreign["id"]}))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // a task cannot move below itself or its subtasks
+    let root_parent = format!("/tasks/{}/parent", root["id"]);
+    let (status, _) = app.call("PUT", &root_parent, &owner, Some(json!({"parent_id": chain[2]["id"]}))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = app.call("PUT", &root_parent, &owner, Some(json!({"parent_id": root["id"]}))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (_, sibling) = app.call("POST", "/tasks", &owner,
+                                Some(json!({"title": "sibling", "project_id": project_id, "parent_id": root["id"]}))).await;
+    for status in ["In Progress", "Review", "Done"] {
+        app.call("PATCH", &format!("/tasks/{}/status", sibling["id"]), &owner, Some(json!({"status": status}))).await;
+    }
+    let (status, tree) = app.call("GET", &format!("/tasks/{}/tree", root["id"]), &owner, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(tree["subtask_count"], json!(5));
+    assert_eq!(tree["done_subtask_count"], json!(1));
+    assert_eq!(tree["subtasks"][0]["id"], chain[1]["id"]);
+    assert_eq!(tree["subtasks"][0]["subtask_count"], json!(3));
+    assert_eq!(tree["subtasks"][1]["done"], json!(true));
+
+    // moving a subtree checks the depth of its deepest task
+    let (status, _) = app.call("PUT", &format!("/tasks/{}/parent", chain[1]["id"]), &owner,
+                               Some(json!({"parent_id": sibling["id"]}))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, moved) = app.call("PUT", &format!("/tasks/{}/parent", chain[3]["id"]), &owner,
+                                   Some(json!({"parent_id": sibling["id"]}))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(moved["parent_id"], sibling["id"]);
+    let (_, tree) = app.call("GET", &format!("/tasks/{}/tree", sibling["id"]), &owner, None).await;
+    assert_eq!(tree["subtask_count"], json!(2));
+    let (status, detached) = app.call("PUT", &format!("/tasks/{}/parent", chain[3]["id"]), &owner,
+                                      Some(json!({"parent_id": null}))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(detached["parent_id"], Value::Null);
+
+    // a subtree goes to the trash, to another project and back as a whole
+    app.call("DELETE", &format!("/tasks/{}", chain[1]["id"]), &owner, None).await;
+    let (_, tree) = app.call("GET", &format!("/tasks/{}/tree", root["id"]), &owner, None).await;
+    assert_eq!(tree["subtask_count"], json!(1));
+    let (_, trash) = app.call("GET", "/trash", &owner, None).await;
+    assert_eq!(trash.as_array().unwrap().len(), 1);
+    app.call("POST", &format!("/trash/tasks/{}/restore", chain[1]["id"]), &owner, None).await;
+    let (status, moved) = app.call("PATCH", &format!("/tasks/{}", chain[1]["id"]), &owner,
+                                   Some(json!({"project_id": other_id}))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(moved["parent_id"], Value::Null);
+    let (_, tree) = app.call("GET", &format!("/tasks/{}/tree", chain[1]["id"]), &owner, None).await;
+    assert_eq!(tree["subtasks"][0]["project_id"], json!(other_id));
+    assert_eq!(tree["subtask_count"], json!(1));
+}
@@ -0,0 +1,149 @@
+//! Time-based one-time passwords as described in RFC 6238, compatible with the usual
+//! authenticator apps: HMAC-SHA1, 30 second steps and 6 digits.
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use uuid::Uuid;
+
+const STEP_SECONDS: i64 = 30;
+const DIGITS: u32 = 6;
+/// Steps before and after the current one that are still accepted, to allow for clock drift.
+const ALLOWED_DRIFT: i64 = 1;
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// A random 160 bit secret, base32 encoded the way authenticator apps expect it.
+pub fn generate_secret() -> String {
+    let bytes: Vec<u8> = Uuid::new_v4().as_bytes().iter()
+        .chain(Uuid::new_v4().as_bytes().iter())
+        .take(20)
+        .copied()
+        .collect();
+    base32_encode(&bytes)
+}
+
+/// The `otpauth://` URI authenticator apps import, usually shown as a QR code.
+pub fn otpauth_uri(issuer: &str, account: &str, secret: &str) -> String {
+    format!("otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={digits}&period={period}",
+            issuer = url_encode(issuer),
+            account = url_encode(account),
+            secret = secret,
+            digits = DIGITS,
+            period = STEP_SECONDS)
+}
+
+/// Checks the code against the steps around `now` and returns the step it belongs to,
+/// so callers can refuse a code that was already used.
+pub fn verify(secret: &str, code: &str, now: DateTime<Utc>) -> Option<i64> {
+    let key = base32_decode(secret)?;
+    let code = code.trim();
+    if code.len() != DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let current = now.timestamp() / STEP_SECONDS;
+    (current - ALLOWED_DRIFT..=current + ALLOWED_DRIFT)
+        .find(|step| format!("{:0width$}", code_at(&key, *step), width = DIGITS as usize) == code)
+}
+
+/// The code an authenticator app would show at `now`.
+#[cfg(test)]
+pub fn code(secret: &str, now: DateTime<Utc>) -> String {
+    let key = base32_decode(secret).unwrap();
+    format!("{:06}", code_at(&key, now.timestamp() / STEP_SECONDS))
+}
+
+fn code_at(key: &[u8], step: i64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key)
+        .expect("HMAC accepts keys of any length");
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([hash[offset], hash[offset + 1], hash[offset + 2], hash[offset + 3]])
+        & 0x7fff_ffff;
+    binary % 10u32.pow(DIGITS)
+}
+
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut result = String::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for byte in bytes {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            result.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        result.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    result
+}
+
+fn base32_decode(value: &str) -> Option<Vec<u8>> {
+    let mut result = Vec::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for c in value.trim_end_matches('=').chars() {
+        let index = BASE32_ALPHABET.iter().position(|a| *a as char == c.to_ascii_uppercase())?;
+        buffer = (buffer << 5) | index as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            result.push((buffer >> bits) as u8);
+        }
+    }
+    Some(result)
+}
+
+fn url_encode(value: &str) -> String {
+    value.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'@' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    /// The SHA1 key of the RFC 6238 test vectors, "12345678901234567890".
+    const RFC_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    #[test]
+    fn matches_the_rfc_test_vectors() {
+        let key = base32_decode(RFC_SECRET).unwrap();
+        assert_eq!(key, b"12345678901234567890");
+        for (time, code) in [(59, 287082), (1111111109, 81804), (1234567890, 5924), (2000000000, 279037)] {
+            assert_eq!(code_at(&key, time / STEP_SECONDS), code);
+        }
+    }
+
+    #[test]
+    fn accepts_the_neighbouring_steps_only() {
+        let now = Utc.timestamp(1111111109, 0);
+        assert_eq!(verify(RFC_SECRET, "081804", now), Some(1111111109 / STEP_SECONDS));
+        assert_eq!(verify(RFC_SECRET, "081804", now + chrono::Duration::seconds(30)), Some(1111111109 / STEP_SECONDS));
+        assert_eq!(verify(RFC_SECRET, "081804", now + chrono::Duration::seconds(90)), None);
+        assert_eq!(verify(RFC_SECRET, "81804", now), None);
+    }
+
+    #[test]
+    fn generated_secrets_round_trip() {
+        let secret = generate_secret();
+        assert_eq!(secret.len(), 32);
+        assert_eq!(base32_decode(&secret).unwrap().len(), 20);
+        assert_eq!(base32_encode(&base32_decode(&secret).unwrap()), secret);
+    }
+
+    #[test]
+    fn uri_escapes_issuer_and_account() {
+        let uri = otpauth_uri("Task Manager", "jane@example.com", "ABC");
+        assert_eq!(uri, "otpauth://totp/Task%20Manager:jane@example.com?secret=ABC&issuer=Task%20Manager&algorithm=SHA1&digits=6&period=30");
+    }
+}
@@ -2,6 +2,8 @@ use std::fmt;
 use std::fmt::Formatter;
 use clap::{clap_derive, ArgEnum, Parser};
 
+use common::data::{DueFilter, ProjectDeleteStrategy};
+
 #[derive(Parser)]
 #[clap(author, version, about)]
 pub struct Cli {
@@ -9,6 +11,9 @@ pub struct Cli {
     pub command: Command,
     #[clap(arg_enum)]
     pub resource: Option<Resource>,
+    /// What to do with the resource, e.g. `tm admin users list`
+    #[clap(arg_enum)]
+    pub action: Option<Action>,
     #[clap(short, long)]
     pub offset: Option<usize>,
     #[clap(short, long)]
@@ -17,6 +22,30 @@ pub struct Cli {
     pub order: Option<Order>,
     #[clap(long, arg_enum)]
     pub direction: Option<Direction>,
+    /// Task start, "YYYY-MM-DD", "YYYY-MM-DD HH:MM" in local time or RFC 3339, "-" clears it
+    /// when editing a task
+    #[clap(long)]
+    pub start_at: Option<String>,
+    /// Task due date, same formats as --start-at
+    #[clap(long)]
+    pub due_at: Option<String>,
+    #[clap(long, arg_enum)]
+    pub due: Option<Due>,
+    /// Only tasks assigned to me
+    #[clap(long)]
+    pub assigned: bool,
+    /// Only archived projects
+    #[clap(long)]
+    pub archived: bool,
+    /// What happens to the tasks of a deleted project
+    #[clap(long, arg_enum)]
+    pub strategy: Option<Strategy>,
+    /// Parent of a new task, which becomes its subtask
+    #[clap(long)]
+    pub parent: Option<i32>,
+    /// Show tasks with their subtasks
+    #[clap(long)]
+    pub tree: bool,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ArgEnum)]
@@ -25,6 +54,32 @@ pub enum Command {
     Delete,
     Get,
     Login,
+    Logout,
+    Move,
+    Assign,
+    Unassign,
+    Forgot,
+    Reset,
+    Edit,
+    Enable,
+    Disable,
+    Admin,
+    Project,
+    Accept,
+    Decline,
+    Organization,
+    Switch,
+    Trash,
+    Restore,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ArgEnum)]
+pub enum Action {
+    List,
+    Show,
+    Edit,
+    Delete,
+    ResetPassword,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ArgEnum)]
@@ -39,6 +94,40 @@ pub enum Direction {
     Desc,
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ArgEnum)]
+pub enum Due {
+    Overdue,
+    Today,
+    Week,
+}
+
+impl From<Due> for DueFilter {
+    fn from(due: Due) -> Self {
+        match due {
+            Due::Overdue => DueFilter::Overdue,
+            Due::Today => DueFilter::Today,
+            Due::Week => DueFilter::Week,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ArgEnum)]
+pub enum Strategy {
+    Refuse,
+    Cascade,
+    Move,
+}
+
+impl From<Strategy> for ProjectDeleteStrategy {
+    fn from(strategy: Strategy) -> Self {
+        match strategy {
+            Strategy::Refuse => ProjectDeleteStrategy::Refuse,
+            Strategy::Cascade => ProjectDeleteStrategy::Cascade,
+            Strategy::Move => ProjectDeleteStrategy::Move,
+        }
+    }
+}
+
 impl fmt::Display for Order {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -65,4 +154,27 @@ pub enum Resource {
     Projects,
     User,
     Users,
+    Status,
+    Statuses,
+    Password,
+    Profile,
+    Email,
+    Token,
+    Tokens,
+    TwoFactor,
+    Sso,
+    Members,
+    Invite,
+    Remove,
+    Leave,
+    Role,
+    Transfer,
+    Invitation,
+    Invitations,
+    Organization,
+    Organizations,
+    Add,
+    Archive,
+    Unarchive,
+    Subtree,
 }
\ No newline at end of file
@@ -1,26 +1,75 @@
 extern crate core;
 
-use std::{fs, io, process};
+use std::{env, fs, io, process};
 use std::fs::File;
 use std::io::Write;
 use std::path::Path;
+use std::time::Duration;
 
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
 use clap::Parser;
-use reqwest::{Response, StatusCode, Url};
+use reqwest::{Client, RequestBuilder, Response, StatusCode, Url};
+use reqwest::header::RETRY_AFTER;
 
-use common::data::{LoginRequest, LoginResponse, Pageable, Project, ProjectRequest, Task, TaskRequest};
+use common::data::{AccessToken, AccessTokenRequest, AccessTokenResponse, AssigneeRequest, DeviceAuthorization, DeviceTokenRequest, EmailChangeRequest, ForgotPasswordRequest, InvitationRequest, InvitationTokenRequest, LoginRequest, LoginResponse, LoginResult, MemberRoleRequest, Organization, OrganizationMember, OrganizationMemberRequest, OrganizationRequest, Pageable, PasswordChangeRequest, ProfileUpdateRequest, Project, ProjectDeleteQuery, ProjectDeleteStrategy, ProjectDetails, ProjectFilter, ProjectInvitation, ProjectMember, ProjectRequest, ProjectTransferRequest, ProjectUpdateRequest, RefreshRequest, ResetPasswordRequest, Task, TaskFilter, TaskParentRequest, TaskRequest, TaskStatusChangeRequest, TaskTree, TaskUpdateRequest, TrashItem, TwoFactorCodeRequest, TwoFactorDisableRequest, TwoFactorEnrollment, TwoFactorLoginRequest, UserDeleteQuery, UserDeleteStrategy, UserDto, UserUpdateRequest, Workflow};
 
-use crate::argument::{Cli, Command, Resource};
+use crate::argument::{Action, Cli, Command, Resource};
+use crate::error::Error;
 
 mod error;
 mod argument;
 
+const TOKEN_VARIABLE: &str = "TM_TOKEN";
+const ORGANIZATION_FILE: &str = "organization";
+const ORGANIZATION_HEADER: &str = "X-Organization";
+/// Entered instead of a value to clear an optional field of a task.
+const CLEAR_VALUE: &str = "-";
+
 #[tokio::main]
 async fn main() {
     let args: Cli = Cli::parse();
     let client = reqwest::Client::new();
     let config_path = home::home_dir().unwrap().join(".tm").join("config");
     match args.command {
+        Command::Login if args.resource == Some(Resource::Sso) => {
+            let response = client.post("http://localhost:8080/oidc/device")
+                .send()
+                .await
+                .unwrap();
+            if response.status() == StatusCode::NOT_FOUND {
+                eprintln!("Вход через SSO не настроен на сервере.");
+                process::exit(1);
+            }
+            let authorization: DeviceAuthorization = response.json().await.unwrap();
+            println!("Откройте в браузере {}", authorization.verification_uri_complete);
+            println!("и проверьте, что там указан код {}.", authorization.user_code);
+
+            // the server answers 202 until the login is completed in the browser
+            let request = DeviceTokenRequest { device_code: authorization.device_code };
+            let login_response: LoginResponse = loop {
+                tokio::time::sleep(Duration::from_secs(authorization.interval)).await;
+                let response = client.post("http://localhost:8080/oidc/device/token")
+                    .json(&request)
+                    .send()
+                    .await
+                    .unwrap();
+                match response.status() {
+                    StatusCode::ACCEPTED => continue,
+                    StatusCode::OK => break response.json().await.unwrap(),
+                    _ => {
+                        eprintln!("Время для входа истекло, пожалуйста попробуйте снова.");
+                        process::exit(1);
+                    }
+                }
+            };
+
+            let path = home::home_dir().unwrap().join(".tm");
+            if !path.exists() {
+                fs::create_dir(path.clone()).unwrap();
+            }
+            save_tokens(&config_path, &login_response);
+            println!("Вход выполнен.");
+        }
         Command::Login => {
             let mut email = String::new();
             let mut pwd = String::new();
@@ -45,41 +94,90 @@ async fn main() {
                 .send()
                 .await
                 .unwrap();
-            let login_response: LoginResponse = response.json()
-                .await
-                .unwrap();
+            check_login_attempts(&response);
+            if response.status() == StatusCode::BAD_REQUEST {
+                eprintln!("Неверный email или пароль, либо пользователь не активирован.");
+                process::exit(1);
+            }
+            let login_response = match response.json().await.unwrap() {
+                LoginResult::Tokens(login_response) => login_response,
+                LoginResult::Challenge(challenge) => {
+                    let code = read_input("Пожалуйста введите 6-значный код из приложения или код восстановления.");
+                    // recovery codes are longer and contain dashes
+                    let (code, recovery_code) = if code.trim().len() == 6 {
+                        (Some(code), None)
+                    } else {
+                        (None, Some(code))
+                    };
+                    let request = TwoFactorLoginRequest { challenge: challenge.challenge, code, recovery_code };
+                    let response = client.post("http://localhost:8080/login/2fa")
+                        .json(&request)
+                        .send()
+                        .await
+                        .unwrap();
+                    check_login_attempts(&response);
+                    match response.status() {
+                        StatusCode::BAD_REQUEST => {
+                            eprintln!("Неверный код.");
+                            process::exit(1);
+                        }
+                        StatusCode::UNAUTHORIZED => {
+                            eprintln!("Время для ввода кода истекло, пожалуйста войдите снова.");
+                            process::exit(1);
+                        }
+                        _ => {}
+                    }
+                    response.json().await.unwrap()
+                }
+            };
 
             let path = home::home_dir().unwrap().join(".tm");
             if !path.exists() {
                 fs::create_dir(path.clone()).unwrap();
             }
-            let path = path.join("config");
-            let mut f = File::create(path).unwrap();
-            write!(f, "{}", login_response.token).unwrap();
+            save_tokens(&config_path, &login_response);
+        }
+        Command::Logout => {
+            let refresh_token_path = config_path.with_file_name("refresh_token");
+            if let Ok(refresh_token) = fs::read_to_string(&refresh_token_path) {
+                let request = RefreshRequest { refresh_token: refresh_token.trim().to_string() };
+                client.post("http://localhost:8080/logout")
+                    .json(&request)
+                    .send()
+                    .await
+                    .unwrap();
+            }
+            let _ = fs::remove_file(&refresh_token_path);
+            let _ = fs::remove_file(config_path.with_file_name(ORGANIZATION_FILE));
+            let _ = fs::remove_file(&config_path);
         }
         Command::Get => {
             let resource = args.resource.expect("Ресурс обязателен.");
             match resource {
-                Resource::Project | Resource::Projects => {
-                    let mut token = fs::read_to_string(config_path)
-                        .unwrap_or_else(|err| {
-                            eprintln!("Problem reading config, please login.: {}", err);
-                            process::exit(1);
-                        });
-                    trim_newline(&mut token);
+                Resource::Project => {
+                    let project_id = read_input("Пожалуйста введите номер проекта.");
+                    let response = send_authorized(&client, &config_path, client.get(format!("http://localhost:8080/projects/{}", project_id)))
+                        .await;
+                    if response.status() == StatusCode::NOT_FOUND {
+                        eprintln!("Проект не найден.");
+                        process::exit(1);
+                    }
+
+                    let project: ProjectDetails = response.json().await.unwrap();
+                    println!("{}", project);
+                }
+                Resource::Projects => {
                     let pageable = Pageable {
                         limit: args.limit,
                         offset: args.offset,
                         order_by: args.order.map(|x| x.to_string()),
                         direction: args.direction.map(|x| x.to_string()),
                     };
-                    let response = client.get("http://localhost:8080/projects")
-                        .bearer_auth(token)
+                    let project_filter = ProjectFilter { archived: Some(args.archived) };
+                    let response = send_authorized(&client, &config_path, client.get("http://localhost:8080/projects")
                         .query(&pageable)
-                        .send()
-                        .await
-                        .unwrap();
-                    check_response(&response);
+                        .query(&project_filter))
+                        .await;
 
                     let projects: Vec<Project> = response.json().await.unwrap();
                     projects.iter()
@@ -87,8 +185,12 @@ async fn main() {
                             println!("{}", project);
                         });
                 }
-                Resource::Task | Resource::Tasks => {
-                    let token = get_token(&config_path);
+                Resource::Task => {
+                    let task_id = read_input("Пожалуйста введите номер задачи.");
+                    let tree = get_task_tree(&client, &config_path, &task_id).await;
+                    print!("{}", tree);
+                }
+                Resource::Tasks => {
                     let task_url = Url::parse("http://localhost:8080/tasks").unwrap();
                     let pageable = Pageable {
                         limit: args.limit,
@@ -96,17 +198,67 @@ async fn main() {
                         order_by: args.order.map(|x| x.to_string()),
                         direction: args.direction.map(|x| x.to_string()),
                     };
-                    let response = client.get(task_url)
-                        .bearer_auth(token)
+                    let task_filter = TaskFilter {
+                        due: args.due.map(Into::into),
+                        assigned_to_me: Some(args.assigned),
+                    };
+                    let response = send_authorized(&client, &config_path, client.get(task_url)
                         .query(&pageable)
-                        .send()
-                        .await
-                        .unwrap();
-                    check_response(&response);
+                        .query(&task_filter))
+                        .await;
 
                     let tasks: Vec<Task> = response.json().await.unwrap();
-                    tasks.iter()
-                        .for_each(|task| println!("{}", task));
+                    if args.tree {
+                        // subtasks are shown below their parents, only tasks without a listed parent start a tree
+                        let listed: Vec<i32> = tasks.iter().map(|task| task.id).collect();
+                        for task in tasks.iter().filter(|task| task.parent_id.is_none_or(|parent_id| !listed.contains(&parent_id))) {
+                            let tree = get_task_tree(&client, &config_path, &task.id.to_string()).await;
+                            print!("{}", tree);
+                        }
+                    } else {
+                        tasks.iter()
+                            .for_each(|task| println!("{}", task));
+                    }
+                }
+                Resource::Status | Resource::Statuses => {
+                    let project_id = read_input("Пожалуйста введите номер проекта.");
+                    let response = send_authorized(&client, &config_path, client.get(format!("http://localhost:8080/projects/{}/statuses", project_id)))
+                        .await;
+
+                    let workflow: Workflow = response.json().await.unwrap();
+                    print!("{}", workflow);
+                }
+                Resource::Token | Resource::Tokens => {
+                    let response = send_authorized(&client, &config_path, client.get("http://localhost:8080/tokens"))
+                        .await;
+
+                    let tokens: Vec<AccessToken> = response.json().await.unwrap();
+                    tokens.iter()
+                        .for_each(|token| println!("{}", token));
+                }
+                Resource::Organization | Resource::Organizations => {
+                    let organizations = get_organizations(&client, &config_path).await;
+                    organizations.iter()
+                        .for_each(|organization| println!("{}", organization));
+                    match current_organization(&config_path) {
+                        Some(organization) => println!("Текущая организация: {}.", organization),
+                        None => println!("Организация не выбрана, видны все, новые проекты создаются в первой."),
+                    }
+                }
+                Resource::Invitation | Resource::Invitations => {
+                    let response = send_authorized(&client, &config_path, client.get("http://localhost:8080/invitations"))
+                        .await;
+
+                    let invitations: Vec<ProjectInvitation> = response.json().await.unwrap();
+                    invitations.iter()
+                        .for_each(|invitation| println!("{}", invitation));
+                }
+                Resource::Profile => {
+                    let response = send_authorized(&client, &config_path, client.get("http://localhost:8080/users/me"))
+                        .await;
+
+                    let user: UserDto = response.json().await.unwrap();
+                    println!("{}", user);
                 }
                 _ => {}
             }
@@ -115,12 +267,6 @@ async fn main() {
             let resource = args.resource.expect("Ресурс обязателен.");
             match resource {
                 Resource::Project => {
-                    let mut token = fs::read_to_string(config_path)
-                        .unwrap_or_else(|err| {
-                            eprintln!("Problem reading config, please login.: {}", err);
-                            process::exit(1);
-                        });
-                    trim_newline(&mut token);
 
                     let mut title = String::new();
                     let mut description = String::new();
@@ -137,19 +283,14 @@ async fn main() {
 
                     let request = ProjectRequest { title, description: Some(description) };
                     let body = serde_json::to_string(&request).unwrap();
-                    let response = client.post("http://localhost:8080/projects")
-                        .bearer_auth(token)
-                        .body(body)
-                        .send()
-                        .await
-                        .unwrap();
-                    check_response(&response);
+                    let response = send_authorized(&client, &config_path, client.post("http://localhost:8080/projects")
+                        .body(body))
+                        .await;
 
                     let project: Project = response.json().await.unwrap();
                     println!("{}", project);
                 }
                 Resource::Task => {
-                    let token = get_token(&config_path);
                     let mut title = String::new();
                     let mut description = String::new();
                     let mut project_id_str = String::new();
@@ -172,24 +313,73 @@ async fn main() {
                     trim_newline(&mut description);
                     trim_newline(&mut project_id_str);
 
+                    let start_at = args.start_at.as_deref()
+                        .map(parse_date_time)
+                        .transpose()
+                        .unwrap_or_else(|err| {
+                            eprintln!("{}", err);
+                            process::exit(1);
+                        });
+                    let due_at = args.due_at.as_deref()
+                        .map(parse_date_time)
+                        .transpose()
+                        .unwrap_or_else(|err| {
+                            eprintln!("{}", err);
+                            process::exit(1);
+                        });
                     let request = TaskRequest {
                         title,
                         description: Some(description),
                         project_id: project_id_str.parse::<i32>().unwrap(),
+                        start_at,
+                        due_at,
+                        parent_id: args.parent,
                     };
 
                     let body = serde_json::to_string(&request).unwrap();
-                    let response = client.post("http://localhost:8080/tasks")
-                        .bearer_auth(token)
-                        .body(body)
-                        .send()
-                        .await
-                        .unwrap();
-                    check_response(&response);
+                    let response = send_authorized(&client, &config_path, client.post("http://localhost:8080/tasks")
+                        .body(body))
+                        .await;
 
                     let task: Task = response.json().await.unwrap();
                     println!("{}", task);
                 }
+                Resource::Organization => {
+                    let name = read_input("Пожалуйста введите название организации.");
+                    let response = send_authorized(&client, &config_path, client.post("http://localhost:8080/organizations")
+                        .json(&OrganizationRequest { name }))
+                        .await;
+
+                    let organization: Organization = response.json().await.unwrap();
+                    println!("{}", organization);
+                    println!("Переключиться на неё: tm switch organization.");
+                }
+                Resource::Token => {
+                    let name = read_input("Пожалуйста введите название токена.");
+                    let scopes = read_input("Пожалуйста введите права через запятую: tasks:read, tasks:write, projects:read, projects:admin.");
+                    let expires_in_days = optional_input("Пожалуйста введите срок действия в днях (пусто - бессрочно).")
+                        .map(|days| days.parse::<i64>().unwrap());
+                    let request = AccessTokenRequest {
+                        name,
+                        scopes: scopes.split(',')
+                            .map(|scope| scope.trim().to_string())
+                            .filter(|scope| !scope.is_empty())
+                            .collect(),
+                        expires_in_days,
+                    };
+                    let response = send_authorized(&client, &config_path, client.post("http://localhost:8080/tokens")
+                        .json(&request))
+                        .await;
+                    if response.status() == StatusCode::BAD_REQUEST {
+                        eprintln!("Неизвестные права доступа или срок действия не от 1 до 3650 дней.");
+                        process::exit(1);
+                    }
+
+                    let created: AccessTokenResponse = response.json().await.unwrap();
+                    println!("{}", created.access_token);
+                    println!("Сохраните токен, больше он показан не будет:");
+                    println!("{}", created.token);
+                }
                 _ => {}
             }
         }
@@ -197,25 +387,814 @@ async fn main() {
             let resource = args.resource.expect("Ресурс обязателен.");
             match resource {
                 Resource::Task => {
-                    let token = get_token(&config_path);
-                    let mut task_id = String::new();
-                    println!("Пожалуйста введите номер задачи.");
-                    io::stdin().read_line(&mut task_id)
-                        .unwrap();
-                    let mut tasks_url = Url::parse("http://localhost:8080/tasks").unwrap();
-                    tasks_url.set_path(&task_id);
-                    let response = client.delete(tasks_url)
-                        .bearer_auth(token)
-                        .send()
+                    let task_id = read_input("Пожалуйста введите номер задачи.");
+                    let response = send_authorized(&client, &config_path, client.delete(format!("http://localhost:8080/tasks/{}", task_id)))
+                        .await;
+                    match response.status() {
+                        StatusCode::NOT_FOUND => {
+                            eprintln!("Задача не найдена.");
+                            process::exit(1);
+                        }
+                        StatusCode::FORBIDDEN => {
+                            eprintln!("Вашей роли в проекте для этого недостаточно.");
+                            process::exit(1);
+                        }
+                        _ => println!("Задача перемещена в корзину."),
+                    }
+                }
+                Resource::Project => {
+                    let project_id = read_input("Пожалуйста введите номер проекта.");
+                    let strategy = args.strategy.map(ProjectDeleteStrategy::from);
+                    let move_to = match strategy {
+                        Some(ProjectDeleteStrategy::Move) => Some(read_input("Пожалуйста введите номер проекта, в который перенести задачи.")
+                            .parse::<i32>()
+                            .expect("Номер должен быть числом.")),
+                        _ => None,
+                    };
+                    let response = send_authorized(&client, &config_path, client.delete(format!("http://localhost:8080/projects/{}", project_id))
+                        .query(&ProjectDeleteQuery { strategy, move_to }))
+                        .await;
+                    match response.status() {
+                        StatusCode::NOT_FOUND => {
+                            eprintln!("Проект не найден.");
+                            process::exit(1);
+                        }
+                        StatusCode::FORBIDDEN => {
+                            eprintln!("Вашей роли в проекте для этого недостаточно.");
+                            process::exit(1);
+                        }
+                        StatusCode::BAD_REQUEST => {
+                            eprintln!("Нельзя перенести задачи в этот проект.");
+                            process::exit(1);
+                        }
+                        StatusCode::CONFLICT => {
+                            let conflict: serde_json::Value = response.json().await.unwrap();
+                            eprintln!("В проекте осталось задач: {}.", conflict["task_count"]);
+                            if let Some(status_counts) = conflict["status_counts"].as_array() {
+                                status_counts.iter()
+                                    .for_each(|count| eprintln!("{}: {}", count["status"].as_str().unwrap_or_default(), count["count"]));
+                            }
+                            eprintln!("Удалите их вместе с проектом (--strategy cascade) или перенесите (--strategy move).");
+                            process::exit(1);
+                        }
+                        _ => println!("Проект удалён."),
+                    }
+                }
+                Resource::Token => {
+                    let token_id = read_input("Пожалуйста введите номер токена.");
+                    let response = send_authorized(&client, &config_path, client.delete(format!("http://localhost:8080/tokens/{}", token_id)))
+                        .await;
+                    if response.status() == StatusCode::NOT_FOUND {
+                        eprintln!("Токен не найден.");
+                        process::exit(1);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Command::Move => {
+            let resource = args.resource.expect("Ресурс обязателен.");
+            if let Resource::Task = resource {
+                let task_id = read_input("Пожалуйста введите номер задачи.");
+                let status = read_input("Пожалуйста введите новый статус задачи.");
+                let request = TaskStatusChangeRequest { status };
+                let response = send_authorized(&client, &config_path, client.patch(format!("http://localhost:8080/tasks/{}/status", task_id))
+                    .json(&request))
+                    .await;
+                if response.status() == StatusCode::CONFLICT {
+                    eprintln!("Переход в этот статус не разрешён.");
+                    process::exit(1);
+                }
+
+                let task: Task = response.json().await.unwrap();
+                println!("{}", task);
+            }
+            if let Resource::Subtree = resource {
+                let task_id = read_input("Пожалуйста введите номер задачи.");
+                let parent_id = optional_input("Пожалуйста введите номер новой родительской задачи (пусто - без родителя).")
+                    .map(|parent_id| parent_id.parse::<i32>().expect("Номер должен быть числом."));
+                let response = send_authorized(&client, &config_path, client.put(format!("http://localhost:8080/tasks/{}/parent", task_id))
+                    .json(&TaskParentRequest { parent_id }))
+                    .await;
+                match response.status() {
+                    StatusCode::NOT_FOUND => {
+                        eprintln!("Задача не найдена.");
+                        process::exit(1);
+                    }
+                    StatusCode::FORBIDDEN => {
+                        eprintln!("Вашей роли в проекте для этого недостаточно.");
+                        process::exit(1);
+                    }
+                    StatusCode::BAD_REQUEST => {
+                        eprintln!("Родитель должен быть задачей того же проекта вне переносимого поддерева, а вложенность не больше допустимой.");
+                        process::exit(1);
+                    }
+                    _ => {}
+                }
+
+                let task: Task = response.json().await.unwrap();
+                println!("{}", task);
+            }
+        }
+        Command::Assign => {
+            let resource = args.resource.expect("Ресурс обязателен.");
+            if let Resource::Task = resource {
+                let task_id = read_input("Пожалуйста введите номер задачи.");
+                let user_id = read_input("Пожалуйста введите номер пользователя.");
+                let request = AssigneeRequest { user_id: user_id.parse::<i32>().unwrap() };
+                let response = send_authorized(&client, &config_path, client.post(format!("http://localhost:8080/tasks/{}/assignees", task_id))
+                    .json(&request))
+                    .await;
+                if response.status() == StatusCode::BAD_REQUEST {
+                    eprintln!("Пользователь не является участником проекта.");
+                    process::exit(1);
+                }
+
+                let task: Task = response.json().await.unwrap();
+                println!("{}", task);
+            }
+        }
+        Command::Unassign => {
+            let resource = args.resource.expect("Ресурс обязателен.");
+            if let Resource::Task = resource {
+                let task_id = read_input("Пожалуйста введите номер задачи.");
+                let user_id = read_input("Пожалуйста введите номер пользователя.");
+                let response = send_authorized(&client, &config_path, client.delete(format!("http://localhost:8080/tasks/{}/assignees/{}", task_id, user_id)))
+                    .await;
+
+                let task: Task = response.json().await.unwrap();
+                println!("{}", task);
+            }
+        }
+        Command::Forgot => {
+            let resource = args.resource.expect("Ресурс обязателен.");
+            if let Resource::Password = resource {
+                let email = read_input("Пожалуйста введите email.");
+                client.post("http://localhost:8080/password/forgot")
+                    .json(&ForgotPasswordRequest { email })
+                    .send()
+                    .await
+                    .unwrap();
+                println!("Если такой пользователь существует, ему отправлено письмо с кодом для сброса пароля.");
+            }
+        }
+        Command::Reset => {
+            let resource = args.resource.expect("Ресурс обязателен.");
+            if let Resource::Password = resource {
+                let token = read_input("Пожалуйста введите код из письма.");
+                let pwd = read_input("Пожалуйста введите новый пароль.");
+                let response = client.post("http://localhost:8080/password/reset")
+                    .json(&ResetPasswordRequest { token, pwd })
+                    .send()
+                    .await
+                    .unwrap();
+                if !response.status().is_success() {
+                    eprintln!("Код недействителен или устарел.");
+                    process::exit(1);
+                }
+                // the server signed out every session, the saved tokens are of no use anymore
+                let _ = fs::remove_file(config_path.with_file_name("refresh_token"));
+                let _ = fs::remove_file(&config_path);
+                println!("Пароль изменён, пожалуйста войдите в систему.");
+            }
+        }
+        Command::Edit => {
+            let resource = args.resource.expect("Ресурс обязателен.");
+            match resource {
+                Resource::Profile => {
+                    println!("Оставьте поле пустым, чтобы не менять его.");
+                    let request = ProfileUpdateRequest {
+                        first_name: optional_input("Пожалуйста введите имя."),
+                        last_name: optional_input("Пожалуйста введите фамилию."),
+                        time_zone: optional_input("Пожалуйста введите часовой пояс, например Europe/Moscow."),
+                    };
+                    let response = send_authorized(&client, &config_path, client.patch("http://localhost:8080/users/me")
+                        .json(&request))
+                        .await;
+                    if response.status() == StatusCode::BAD_REQUEST {
+                        eprintln!("Неизвестный часовой пояс.");
+                        process::exit(1);
+                    }
+
+                    let user: UserDto = response.json().await.unwrap();
+                    println!("{}", user);
+                }
+                Resource::Task => {
+                    let task_id = read_input("Пожалуйста введите номер задачи.");
+                    println!("Оставьте поле пустым, чтобы не менять его, или введите «{}», чтобы очистить описание.", CLEAR_VALUE);
+                    let title = optional_input("Пожалуйста введите название задачи.");
+                    let description = optional_input("Пожалуйста введите описание задачи.")
+                        .map(|description| Some(description).filter(|description| description != CLEAR_VALUE));
+                    let project_id = optional_input("Пожалуйста введите номер проекта, чтобы перенести задачу.")
+                        .map(|project_id| project_id.parse::<i32>().expect("Номер должен быть числом."));
+                    let start_at = args.start_at.as_deref()
+                        .map(parse_clearable_date_time)
+                        .transpose()
+                        .unwrap_or_else(|err| {
+                            eprintln!("{}", err);
+                            process::exit(1);
+                        });
+                    let due_at = args.due_at.as_deref()
+                        .map(parse_clearable_date_time)
+                        .transpose()
+                        .unwrap_or_else(|err| {
+                            eprintln!("{}", err);
+                            process::exit(1);
+                        });
+                    let request = TaskUpdateRequest { title, description, project_id, start_at, due_at };
+                    let response = send_authorized(&client, &config_path, client.patch(format!("http://localhost:8080/tasks/{}", task_id))
+                        .json(&request))
+                        .await;
+                    match response.status() {
+                        StatusCode::NOT_FOUND => {
+                            eprintln!("Задача или проект не найдены.");
+                            process::exit(1);
+                        }
+                        StatusCode::FORBIDDEN => {
+                            eprintln!("Вашей роли в проекте для этого недостаточно.");
+                            process::exit(1);
+                        }
+                        StatusCode::BAD_REQUEST => {
+                            eprintln!("Начало задачи позже срока.");
+                            process::exit(1);
+                        }
+                        _ => {}
+                    }
+
+                    let task: Task = response.json().await.unwrap();
+                    println!("{}", task);
+                }
+                Resource::Project => {
+                    let project_id = read_input("Пожалуйста введите номер проекта.");
+                    println!("Оставьте поле пустым, чтобы не менять его.");
+                    let request = ProjectUpdateRequest {
+                        title: optional_input("Пожалуйста введите название проекта."),
+                        description: optional_input("Пожалуйста введите описание проекта."),
+                    };
+                    let response = send_authorized(&client, &config_path, client.patch(format!("http://localhost:8080/projects/{}", project_id))
+                        .json(&request))
+                        .await;
+                    match response.status() {
+                        StatusCode::NOT_FOUND => {
+                            eprintln!("Проект не найден.");
+                            process::exit(1);
+                        }
+                        StatusCode::FORBIDDEN => {
+                            eprintln!("Вашей роли в проекте для этого недостаточно.");
+                            process::exit(1);
+                        }
+                        _ => {}
+                    }
+
+                    let project: Project = response.json().await.unwrap();
+                    println!("{}", project);
+                }
+                Resource::Password => {
+                    let current_pwd = read_input("Пожалуйста введите текущий пароль.");
+                    let new_pwd = read_input("Пожалуйста введите новый пароль.");
+                    let request = PasswordChangeRequest { current_pwd, new_pwd };
+                    let response = send_authorized(&client, &config_path, client.put("http://localhost:8080/users/me/password")
+                        .json(&request))
+                        .await;
+                    if response.status() == StatusCode::BAD_REQUEST {
+                        eprintln!("Неверный пароль.");
+                        process::exit(1);
+                    }
+
+                    // every other session was signed out, this one continues with the new tokens
+                    let login_response: LoginResponse = response.json().await.unwrap();
+                    save_tokens(&config_path, &login_response);
+                    println!("Пароль изменён.");
+                }
+                Resource::Email => {
+                    let email = read_input("Пожалуйста введите новый email.");
+                    let pwd = read_input("Пожалуйста введите пароль.");
+                    let request = EmailChangeRequest { email: email.clone(), pwd };
+                    let response = send_authorized(&client, &config_path, client.post("http://localhost:8080/users/me/email")
+                        .json(&request))
+                        .await;
+                    match response.status() {
+                        StatusCode::BAD_REQUEST => {
+                            eprintln!("Неверный пароль.");
+                            process::exit(1);
+                        }
+                        StatusCode::CONFLICT => {
+                            eprintln!("Этот email уже используется.");
+                            process::exit(1);
+                        }
+                        _ => println!("Письмо для подтверждения отправлено на {}.", email),
+                    }
+                }
+                _ => {}
+            }
+        }
+        Command::Enable => {
+            let resource = args.resource.expect("Ресурс обязателен.");
+            if let Resource::TwoFactor = resource {
+                let response = send_authorized(&client, &config_path, client.post("http://localhost:8080/users/me/2fa"))
+                    .await;
+                if response.status() == StatusCode::CONFLICT {
+                    eprintln!("Двухфакторная аутентификация уже включена.");
+                    process::exit(1);
+                }
+                let enrollment: TwoFactorEnrollment = response.json().await.unwrap();
+                println!("Добавьте ключ в приложение-аутентификатор: {}", enrollment.secret);
+                println!("{}", enrollment.otpauth_uri);
+                println!("Сохраните коды восстановления, они показываются только один раз:");
+                enrollment.recovery_codes.iter()
+                    .for_each(|code| println!("{}", code));
+                let code = read_input("Пожалуйста введите 6-значный код из приложения.");
+                let response = send_authorized(&client, &config_path, client.post("http://localhost:8080/users/me/2fa/confirm")
+                    .json(&TwoFactorCodeRequest { code }))
+                    .await;
+                if response.status() == StatusCode::BAD_REQUEST {
+                    eprintln!("Неверный код, двухфакторная аутентификация не включена.");
+                    process::exit(1);
+                }
+                println!("Двухфакторная аутентификация включена.");
+            }
+        }
+        Command::Disable => {
+            let resource = args.resource.expect("Ресурс обязателен.");
+            if let Resource::TwoFactor = resource {
+                let pwd = read_input("Пожалуйста введите пароль.");
+                let response = send_authorized(&client, &config_path, client.delete("http://localhost:8080/users/me/2fa")
+                    .json(&TwoFactorDisableRequest { pwd }))
+                    .await;
+                if response.status() == StatusCode::BAD_REQUEST {
+                    eprintln!("Неверный пароль.");
+                    process::exit(1);
+                }
+                println!("Двухфакторная аутентификация выключена.");
+            }
+        }
+        Command::Switch => {
+            let resource = args.resource.expect("Ресурс обязателен.");
+            if let Resource::Organization = resource {
+                let organization_path = config_path.with_file_name(ORGANIZATION_FILE);
+                match optional_input("Пожалуйста введите номер организации (пусто - все организации).") {
+                    Some(organization_id) => {
+                        let organization_id: i32 = organization_id.parse().expect("Номер должен быть числом.");
+                        let organizations = get_organizations(&client, &config_path).await;
+                        let organization = organizations.iter()
+                            .find(|organization| organization.id == organization_id)
+                            .unwrap_or_else(|| {
+                                eprintln!("Вы не состоите в этой организации.");
+                                process::exit(1);
+                            });
+                        fs::write(&organization_path, organization.id.to_string()).expect("Unable to write file");
+                        println!("Текущая организация: {}.", organization.name);
+                    }
+                    None => {
+                        let _ = fs::remove_file(&organization_path);
+                        println!("Организация не выбрана, видны все, новые проекты создаются в первой.");
+                    }
+                }
+            }
+        }
+        Command::Organization => {
+            let resource = args.resource.expect("Ресурс обязателен.");
+            let organization_id = read_input("Пожалуйста введите номер организации.");
+            let members = format!("http://localhost:8080/organizations/{}/members", organization_id);
+            let response = match resource {
+                Resource::Members => {
+                    send_authorized(&client, &config_path, client.get(&members))
+                        .await
+                }
+                Resource::Add => {
+                    let email = read_input("Пожалуйста введите email пользователя.");
+                    let role = optional_input("Пожалуйста введите роль: admin или member (пусто - member).");
+                    send_authorized(&client, &config_path, client.post(&members)
+                        .json(&OrganizationMemberRequest { email, role }))
+                        .await
+                }
+                Resource::Role => {
+                    let user_id = read_input("Пожалуйста введите номер пользователя.");
+                    let role = read_input("Пожалуйста введите роль: admin или member.");
+                    send_authorized(&client, &config_path, client.put(format!("{}/{}/role", members, user_id))
+                        .json(&MemberRoleRequest { role }))
+                        .await
+                }
+                Resource::Remove => {
+                    let user_id = read_input("Пожалуйста введите номер пользователя.");
+                    send_authorized(&client, &config_path, client.delete(format!("{}/{}", members, user_id)))
+                        .await
+                }
+                Resource::Leave => {
+                    let profile: UserDto = send_authorized(&client, &config_path, client.get("http://localhost:8080/users/me"))
+                        .await
+                        .json()
                         .await
                         .unwrap();
-                    check_response(&response);
+                    send_authorized(&client, &config_path, client.delete(format!("{}/{}", members, profile.id)))
+                        .await
+                }
+                _ => return,
+            };
+            match response.status() {
+                StatusCode::NOT_FOUND => {
+                    eprintln!("Организация или пользователь не найдены.");
+                    process::exit(1);
+                }
+                StatusCode::FORBIDDEN => {
+                    eprintln!("Это могут только администраторы организации.");
+                    process::exit(1);
+                }
+                StatusCode::BAD_REQUEST => {
+                    eprintln!("Неизвестная роль.");
+                    process::exit(1);
+                }
+                StatusCode::CONFLICT if resource == Resource::Add => {
+                    eprintln!("Пользователь уже состоит в организации.");
+                    process::exit(1);
+                }
+                StatusCode::CONFLICT => {
+                    eprintln!("В организации должен остаться администратор, а в её проектах - участник и владелец.");
+                    process::exit(1);
+                }
+                _ => {}
+            }
+            match resource {
+                Resource::Members => {
+                    let members: Vec<OrganizationMember> = response.json().await.unwrap();
+                    members.iter()
+                        .for_each(|member| println!("{}", member));
+                }
+                Resource::Add | Resource::Role => {
+                    let member: OrganizationMember = response.json().await.unwrap();
+                    println!("{}", member);
+                }
+                _ => println!("Готово."),
+            }
+        }
+        Command::Project => {
+            let resource = args.resource.expect("Ресурс обязателен.");
+            let project_id = read_input("Пожалуйста введите номер проекта.");
+            let response = match resource {
+                Resource::Members => {
+                    send_authorized(&client, &config_path, client.get(format!("http://localhost:8080/projects/{}/members", project_id)))
+                        .await
+                }
+                Resource::Invite => {
+                    let email = read_input("Пожалуйста введите email приглашаемого.");
+                    let role = optional_input("Пожалуйста введите роль: maintainer, member или viewer (пусто - member).");
+                    send_authorized(&client, &config_path, client.post(format!("http://localhost:8080/projects/{}/invitations", project_id))
+                        .json(&InvitationRequest { email, role }))
+                        .await
+                }
+                Resource::Remove => {
+                    let user_id = read_input("Пожалуйста введите номер пользователя.");
+                    send_authorized(&client, &config_path, client.delete(format!("http://localhost:8080/projects/{}/members/{}", project_id, user_id)))
+                        .await
+                }
+                Resource::Leave => {
+                    send_authorized(&client, &config_path, client.post(format!("http://localhost:8080/projects/{}/leave", project_id)))
+                        .await
+                }
+                Resource::Role => {
+                    let user_id = read_input("Пожалуйста введите номер пользователя.");
+                    let role = read_input("Пожалуйста введите роль: maintainer, member или viewer.");
+                    send_authorized(&client, &config_path, client.put(format!("http://localhost:8080/projects/{}/members/{}/role", project_id, user_id))
+                        .json(&MemberRoleRequest { role }))
+                        .await
+                }
+                Resource::Transfer => {
+                    let user_id: i32 = read_input("Пожалуйста введите номер нового владельца.").parse().expect("Номер должен быть числом.");
+                    send_authorized(&client, &config_path, client.post(format!("http://localhost:8080/projects/{}/transfer", project_id))
+                        .json(&ProjectTransferRequest { user_id }))
+                        .await
+                }
+                Resource::Archive => {
+                    send_authorized(&client, &config_path, client.post(format!("http://localhost:8080/projects/{}/archive", project_id)))
+                        .await
+                }
+                Resource::Unarchive => {
+                    send_authorized(&client, &config_path, client.post(format!("http://localhost:8080/projects/{}/unarchive", project_id)))
+                        .await
+                }
+                _ => return,
+            };
+            match response.status() {
+                StatusCode::NOT_FOUND => {
+                    eprintln!("Проект или участник не найден.");
+                    process::exit(1);
+                }
+                StatusCode::CONFLICT if resource == Resource::Invite => {
+                    eprintln!("Пользователь уже участник проекта.");
+                    process::exit(1);
+                }
+                StatusCode::CONFLICT => {
+                    eprintln!("У проекта должен остаться участник и владелец: передайте владение или удалите проект.");
+                    process::exit(1);
+                }
+                StatusCode::FORBIDDEN => {
+                    eprintln!("Вашей роли в проекте для этого недостаточно.");
+                    process::exit(1);
+                }
+                StatusCode::BAD_REQUEST => {
+                    eprintln!("Неизвестная роль или пользователь не участник проекта.");
+                    process::exit(1);
+                }
+                _ => {}
+            }
+            match resource {
+                Resource::Members => {
+                    let members: Vec<ProjectMember> = response.json().await.unwrap();
+                    members.iter()
+                        .for_each(|member| println!("{}", member));
+                }
+                Resource::Invite => {
+                    let invitation: ProjectInvitation = response.json().await.unwrap();
+                    println!("Приглашение отправлено на {}.", invitation.email);
+                }
+                Resource::Role | Resource::Transfer => {
+                    let member: ProjectMember = response.json().await.unwrap();
+                    println!("{}", member);
+                }
+                Resource::Archive | Resource::Unarchive => {
+                    let project: Project = response.json().await.unwrap();
+                    println!("{}", project);
+                }
+                _ => println!("Готово."),
+            }
+        }
+        Command::Trash => {
+            let response = send_authorized(&client, &config_path, client.get("http://localhost:8080/trash"))
+                .await;
+
+            let trash: Vec<TrashItem> = response.json().await.unwrap();
+            if trash.is_empty() {
+                println!("Корзина пуста.");
+            }
+            trash.iter()
+                .for_each(|item| println!("{}", item));
+        }
+        Command::Restore => {
+            let resource = args.resource.expect("Ресурс обязателен.");
+            let (kind, message) = match resource {
+                Resource::Task => ("tasks", "Пожалуйста введите номер задачи."),
+                Resource::Project => ("projects", "Пожалуйста введите номер проекта."),
+                _ => return,
+            };
+            let id = read_input(message);
+            let response = send_authorized(&client, &config_path, client.post(format!("http://localhost:8080/trash/{}/{}/restore", kind, id)))
+                .await;
+            match response.status() {
+                StatusCode::NOT_FOUND => {
+                    eprintln!("В корзине такого нет.");
+                    process::exit(1);
+                }
+                StatusCode::FORBIDDEN => {
+                    eprintln!("Вашей роли в проекте для этого недостаточно.");
+                    process::exit(1);
                 }
-                Resource::Project => {}
                 _ => {}
             }
+            if resource == Resource::Task {
+                let task: Task = response.json().await.unwrap();
+                println!("{}", task);
+            } else {
+                let project: Project = response.json().await.unwrap();
+                println!("{}", project);
+            }
+        }
+        Command::Accept => {
+            let resource = args.resource.expect("Ресурс обязателен.");
+            if let Resource::Invitation = resource {
+                let token = read_input("Пожалуйста введите код приглашения из письма.");
+                let response = send_authorized(&client, &config_path, client.post("http://localhost:8080/invitations/accept")
+                    .json(&InvitationTokenRequest { token }))
+                    .await;
+                if response.status() == StatusCode::BAD_REQUEST {
+                    eprintln!("Приглашение недействительно, устарело или отправлено на другой email.");
+                    process::exit(1);
+                }
+
+                let project: Project = response.json().await.unwrap();
+                println!("{}", project);
+            }
+        }
+        Command::Decline => {
+            let resource = args.resource.expect("Ресурс обязателен.");
+            if let Resource::Invitation = resource {
+                let token = read_input("Пожалуйста введите код приглашения из письма.");
+                let response = client.post("http://localhost:8080/invitations/decline")
+                    .json(&InvitationTokenRequest { token })
+                    .send()
+                    .await
+                    .unwrap();
+                if !response.status().is_success() {
+                    eprintln!("Приглашение не найдено.");
+                    process::exit(1);
+                }
+                println!("Приглашение отклонено.");
+            }
+        }
+        Command::Admin => {
+            let resource = args.resource.expect("Ресурс обязателен.");
+            if let Resource::User | Resource::Users = resource {
+                match args.action.unwrap_or(Action::List) {
+                    Action::List => {
+                        let pageable = Pageable {
+                            limit: args.limit,
+                            offset: args.offset,
+                            order_by: args.order.map(|x| x.to_string()),
+                            direction: args.direction.map(|x| x.to_string()),
+                        };
+                        let response = send_authorized(&client, &config_path, client.get("http://localhost:8080/users")
+                            .query(&pageable))
+                            .await;
+                        check_admin(&response);
+
+                        let users: Vec<UserDto> = response.json().await.unwrap();
+                        users.iter()
+                            .for_each(|user| println!("{}", user));
+                    }
+                    Action::Show => {
+                        let user_id = read_input("Пожалуйста введите номер пользователя.");
+                        let response = send_authorized(&client, &config_path, client.get(format!("http://localhost:8080/users/{}", user_id)))
+                            .await;
+                        check_admin(&response);
+
+                        let user: UserDto = response.json().await.unwrap();
+                        println!("{}", user);
+                    }
+                    Action::Edit => {
+                        let user_id = read_input("Пожалуйста введите номер пользователя.");
+                        println!("Оставьте поле пустым, чтобы не менять его.");
+                        let role = optional_input("Пожалуйста введите роль: User или Admin.");
+                        let enabled = optional_input("Пользователь активен? (да/нет)")
+                            .map(|answer| answer.to_lowercase().starts_with('д'));
+                        let response = send_authorized(&client, &config_path, client.patch(format!("http://localhost:8080/users/{}", user_id))
+                            .json(&UserUpdateRequest { enabled, role }))
+                            .await;
+                        check_admin(&response);
+                        match response.status() {
+                            StatusCode::BAD_REQUEST => {
+                                eprintln!("Неизвестная роль.");
+                                process::exit(1);
+                            }
+                            StatusCode::CONFLICT => {
+                                eprintln!("Нельзя лишить прав или отключить последнего администратора.");
+                                process::exit(1);
+                            }
+                            _ => {}
+                        }
+
+                        let user: UserDto = response.json().await.unwrap();
+                        println!("{}", user);
+                    }
+                    Action::Delete => {
+                        let user_id = read_input("Пожалуйста введите номер пользователя.");
+                        let reassign_to = optional_input("Пожалуйста введите номер пользователя, которому передать задачи и проекты (пусто - удалить их).")
+                            .map(|id| id.parse::<i32>().unwrap());
+                        let query = UserDeleteQuery {
+                            strategy: if reassign_to.is_some() { UserDeleteStrategy::Reassign } else { UserDeleteStrategy::Cascade },
+                            reassign_to,
+                        };
+                        let response = send_authorized(&client, &config_path, client.delete(format!("http://localhost:8080/users/{}", user_id))
+                            .query(&query))
+                            .await;
+                        check_admin(&response);
+                        match response.status() {
+                            StatusCode::BAD_REQUEST => {
+                                eprintln!("Нельзя передать задачи этому пользователю.");
+                                process::exit(1);
+                            }
+                            StatusCode::CONFLICT => {
+                                eprintln!("Нельзя удалить последнего администратора.");
+                                process::exit(1);
+                            }
+                            StatusCode::NOT_FOUND => {
+                                eprintln!("Пользователь не найден.");
+                                process::exit(1);
+                            }
+                            _ => println!("Пользователь удалён."),
+                        }
+                    }
+                    Action::ResetPassword => {
+                        let user_id = read_input("Пожалуйста введите номер пользователя.");
+                        let response = send_authorized(&client, &config_path, client.post(format!("http://localhost:8080/users/{}/password/reset", user_id)))
+                            .await;
+                        check_admin(&response);
+                        if response.status() == StatusCode::NOT_FOUND {
+                            eprintln!("Пользователь не найден.");
+                            process::exit(1);
+                        }
+                        println!("Пароль сброшен, пользователю отправлено письмо с кодом для установки нового.");
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Sends the request with the saved token. When the server no longer accepts the token it is
+/// refreshed once and the request is repeated, so the user only logs in again when that fails.
+///
+/// A personal access token in `TM_TOKEN` is used as is, there is nothing to refresh.
+async fn send_authorized(client: &Client, config_path: &Path, request: RequestBuilder) -> Response {
+    let request = match current_organization(config_path) {
+        Some(organization) => request.header(ORGANIZATION_HEADER, organization),
+        None => request,
+    };
+    if let Ok(token) = env::var(TOKEN_VARIABLE) {
+        let response = request.bearer_auth(token)
+            .send()
+            .await
+            .unwrap();
+        check_response(&response);
+        return response;
+    }
+    let retry = request.try_clone();
+    let response = request.bearer_auth(get_token(config_path))
+        .send()
+        .await
+        .unwrap();
+    if response.status() == StatusCode::UNAUTHORIZED {
+        if let Some(retry) = retry {
+            if refresh_tokens(client, config_path).await {
+                let response = retry.bearer_auth(get_token(config_path))
+                    .send()
+                    .await
+                    .unwrap();
+                check_response(&response);
+                return response;
+            }
         }
     }
+    check_response(&response);
+    response
+}
+
+async fn refresh_tokens(client: &Client, config_path: &Path) -> bool {
+    let refresh_token = match fs::read_to_string(config_path.with_file_name("refresh_token")) {
+        Ok(refresh_token) => refresh_token.trim().to_string(),
+        Err(_) => return false,
+    };
+    let response = client.post("http://localhost:8080/token/refresh")
+        .json(&RefreshRequest { refresh_token })
+        .send()
+        .await
+        .unwrap();
+    if !response.status().is_success() {
+        return false;
+    }
+    let login_response: LoginResponse = response.json().await.unwrap();
+    save_tokens(config_path, &login_response);
+    true
+}
+
+fn save_tokens(config_path: &Path, login_response: &LoginResponse) {
+    let mut f = File::create(config_path).unwrap();
+    write!(f, "{}", login_response.token).unwrap();
+    let mut f = File::create(config_path.with_file_name("refresh_token")).unwrap();
+    write!(f, "{}", login_response.refresh_token).unwrap();
+}
+
+fn check_login_attempts(response: &Response) {
+    if response.status() == StatusCode::TOO_MANY_REQUESTS {
+        let retry_after = response.headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("?")
+            .to_string();
+        eprintln!("Слишком много неудачных попыток входа. Повторите через {} сек.", retry_after);
+        process::exit(1);
+    }
+}
+
+fn check_admin(response: &Response) {
+    if response.status() == StatusCode::FORBIDDEN {
+        eprintln!("Команда доступна только администраторам.");
+        process::exit(1);
+    }
+}
+
+async fn get_organizations(client: &Client, config_path: &Path) -> Vec<Organization> {
+    send_authorized(client, config_path, client.get("http://localhost:8080/organizations"))
+        .await
+        .json()
+        .await
+        .unwrap()
+}
+
+async fn get_task_tree(client: &Client, config_path: &Path, task_id: &str) -> TaskTree {
+    let response = send_authorized(client, config_path, client.get(format!("http://localhost:8080/tasks/{}/tree", task_id)))
+        .await;
+    if response.status() == StatusCode::NOT_FOUND {
+        eprintln!("Задача не найдена.");
+        process::exit(1);
+    }
+    response.json().await.unwrap()
+}
+
+/// The organization picked with `tm switch organization`, without it requests span all of them.
+fn current_organization(config_path: &Path) -> Option<String> {
+    fs::read_to_string(config_path.with_file_name(ORGANIZATION_FILE))
+        .ok()
+        .map(|organization| organization.trim().to_string())
+        .filter(|organization| !organization.is_empty())
 }
 
 fn check_response(response: &Response) {
@@ -235,6 +1214,43 @@ fn get_token(config_path: &Path) -> String {
     token
 }
 
+/// Parses a date given on the command line, dates without an offset are taken in local time.
+fn parse_date_time(value: &str) -> Result<DateTime<Utc>, Error> {
+    if let Ok(date_time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(date_time.with_timezone(&Utc));
+    }
+    let naive = NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M")
+        .or_else(|_| NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .map(|date| date.and_hms(0, 0, 0)))
+        .map_err(|_| Error::ParseArgumentsError)?;
+    Local.from_local_datetime(&naive)
+        .single()
+        .map(|date_time| date_time.with_timezone(&Utc))
+        .ok_or(Error::ParseArgumentsError)
+}
+
+/// Like `parse_date_time`, but `CLEAR_VALUE` stands for no date at all.
+fn parse_clearable_date_time(value: &str) -> Result<Option<DateTime<Utc>>, Error> {
+    if value == CLEAR_VALUE {
+        return Ok(None);
+    }
+    parse_date_time(value).map(Some)
+}
+
+fn read_input(message: &str) -> String {
+    let mut input = String::new();
+    println!("{}", message);
+    io::stdin()
+        .read_line(&mut input)
+        .expect("error");
+    trim_newline(&mut input);
+    input
+}
+
+fn optional_input(message: &str) -> Option<String> {
+    Some(read_input(message)).filter(|input| !input.is_empty())
+}
+
 fn trim_newline(s: &mut String) {
     if s.ends_with('\n') {
         s.pop();